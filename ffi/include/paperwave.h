@@ -0,0 +1,45 @@
+/* C interface to the paperwave Inky display drivers. Link with -lpaperwave_ffi. */
+#ifndef PAPERWAVE_H
+#define PAPERWAVE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct PaperwaveDisplay PaperwaveDisplay;
+
+/* Probes the HAT's EEPROM and opens the matching display, rotated by 0, 90,
+ * 180 or 270 degrees. Returns NULL on failure; see paperwave_last_error(). */
+PaperwaveDisplay *paperwave_display_open(int rotation_degrees);
+
+/* Releases the display; NULL is ignored. */
+void paperwave_display_free(PaperwaveDisplay *display);
+
+/* Writes the image size set_image_rgb expects, after rotation. */
+int paperwave_display_size(const PaperwaveDisplay *display, uint16_t *width, uint16_t *height);
+
+/* Quantizes a packed 8-bit RGB image (width * height * 3 bytes) into the
+ * frame buffer, resizing it to the display if needed. Saturation runs from
+ * 0.0 to 1.0. The panel is not touched until paperwave_display_show(). */
+int paperwave_display_set_image_rgb(PaperwaveDisplay *display, const uint8_t *data,
+                                    uint32_t width, uint32_t height, float saturation);
+
+/* Fills the frame buffer with one palette colour. */
+int paperwave_display_clear(PaperwaveDisplay *display, uint8_t colour);
+
+/* Sends the frame buffer and blocks until the refresh completes. */
+int paperwave_display_show(PaperwaveDisplay *display);
+
+/* Message for the last failed call on this thread, or NULL. Valid until the
+ * next failing call on the same thread. */
+const char *paperwave_last_error(void);
+
+/* The int-returning functions return 0 on success and -1 on failure. */
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PAPERWAVE_H */
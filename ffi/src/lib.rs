@@ -0,0 +1,174 @@
+//! C interface to the paperwave drivers, declared in `include/paperwave.h`.
+//!
+//! Functions returning `c_int` return 0 on success and -1 on failure, with
+//! the reason available from [`paperwave_last_error`].
+#![cfg(target_os = "linux")]
+
+use std::cell::RefCell;
+use std::ffi::{CString, c_char, c_int};
+use std::fmt::Display;
+use std::ptr;
+use std::slice;
+
+use image::{DynamicImage, RgbImage};
+use paperwave::{DisplayOverrides, InkyDisplay, Rotation};
+
+/// Opaque handle owning one open display.
+pub struct PaperwaveDisplay {
+    inner: Box<dyn InkyDisplay + Send>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(err: impl Display) {
+    // Interior NULs would truncate the message on the C side anyway.
+    let message = err.to_string().replace('\0', " ");
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status(result: Result<(), String>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            set_error(err);
+            -1
+        }
+    }
+}
+
+fn rotation(degrees: c_int) -> Result<Rotation, String> {
+    match degrees {
+        0 => Ok(Rotation::Deg0),
+        90 => Ok(Rotation::Deg90),
+        180 => Ok(Rotation::Deg180),
+        270 => Ok(Rotation::Deg270),
+        _ => Err(format!(
+            "unsupported rotation {degrees}; use 0, 90, 180 or 270"
+        )),
+    }
+}
+
+/// Safety: `display` must be null or a live handle from
+/// [`paperwave_display_open`].
+unsafe fn handle<'a>(display: *mut PaperwaveDisplay) -> Result<&'a mut PaperwaveDisplay, String> {
+    unsafe { display.as_mut() }.ok_or_else(|| "null display handle".to_string())
+}
+
+/// Probes the HAT and opens the matching display; null on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn paperwave_display_open(rotation_degrees: c_int) -> *mut PaperwaveDisplay {
+    let opened = rotation(rotation_degrees).and_then(|rotation| {
+        let probe = paperwave::probe_system();
+        paperwave::open_display(&probe, rotation, &DisplayOverrides::default())
+            .map_err(|err| err.to_string())
+    });
+    match opened {
+        Ok(inner) => Box::into_raw(Box::new(PaperwaveDisplay { inner })),
+        Err(err) => {
+            set_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `display` must be null or a handle from [`paperwave_display_open`] that
+/// has not been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn paperwave_display_free(display: *mut PaperwaveDisplay) {
+    if !display.is_null() {
+        drop(unsafe { Box::from_raw(display) });
+    }
+}
+
+/// # Safety
+///
+/// `display` must be a live handle; `width` and `height` must be null or
+/// valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn paperwave_display_size(
+    display: *const PaperwaveDisplay,
+    width: *mut u16,
+    height: *mut u16,
+) -> c_int {
+    status((|| {
+        let display = unsafe { handle(display.cast_mut()) }?;
+        let (w, h) = display.inner.input_dimensions();
+        unsafe {
+            if let Some(width) = width.as_mut() {
+                *width = w;
+            }
+            if let Some(height) = height.as_mut() {
+                *height = h;
+            }
+        }
+        Ok(())
+    })())
+}
+
+/// # Safety
+///
+/// `display` must be a live handle and `data` must point to
+/// `width * height * 3` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn paperwave_display_set_image_rgb(
+    display: *mut PaperwaveDisplay,
+    data: *const u8,
+    width: u32,
+    height: u32,
+    saturation: f32,
+) -> c_int {
+    status((|| {
+        let display = unsafe { handle(display) }?;
+        if data.is_null() {
+            return Err("null image data".to_string());
+        }
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| format!("image {width}x{height} is too large"))?;
+        let pixels = unsafe { slice::from_raw_parts(data, len) }.to_vec();
+        let image = RgbImage::from_raw(width, height, pixels)
+            .ok_or_else(|| format!("invalid image size {width}x{height}"))?;
+        display
+            .inner
+            .set_image(&DynamicImage::ImageRgb8(image), saturation, 0.0)
+            .map_err(|err| err.to_string())
+    })())
+}
+
+/// # Safety
+///
+/// `display` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn paperwave_display_clear(
+    display: *mut PaperwaveDisplay,
+    colour: u8,
+) -> c_int {
+    status(unsafe { handle(display) }.map(|display| display.inner.clear(colour)))
+}
+
+/// # Safety
+///
+/// `display` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn paperwave_display_show(display: *mut PaperwaveDisplay) -> c_int {
+    status(
+        unsafe { handle(display) }
+            .and_then(|display| display.inner.show().map_err(|err| err.to_string())),
+    )
+}
+
+/// The last error on this thread, or null; owned by the library.
+#[unsafe(no_mangle)]
+pub extern "C" fn paperwave_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
@@ -0,0 +1,216 @@
+//! Image statistics for picking processing settings and previewing how an
+//! image will map onto a panel.
+
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbImage};
+
+use crate::displays::{DisplaySpec, RenderOptions, RenderReport, Rotation, el133uf1, uc8159};
+use crate::dither::dither_with_stats;
+
+/// Longest side of the thumbnail statistics are gathered from. Colour
+/// distribution barely changes with scale, and this keeps analysis cheap on
+/// a Pi Zero.
+const SAMPLE_SIZE: u32 = 256;
+
+/// Mean chroma at or below which an image counts as muted.
+const MUTED_CHROMA: f32 = 0.12;
+/// Mean chroma at or above which an image counts as vivid.
+const VIVID_CHROMA: f32 = 0.40;
+
+/// Channel values at or beyond these count as clipped.
+pub(crate) const HIGHLIGHT_CLIP: u8 = 250;
+pub(crate) const SHADOW_CLIP: u8 = 5;
+
+const MUTED_SATURATION: f32 = 1.0;
+const VIVID_SATURATION: f32 = 0.3;
+
+/// Per-pixel chroma (max minus min channel, 0.0-1.0) over a downsampled copy
+/// of `image`.
+pub fn chroma_samples(image: &DynamicImage) -> Vec<f32> {
+    sample(image)
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0;
+            let max = r.max(g).max(b);
+            let min = r.min(g).min(b);
+            (max - min) as f32 / 255.0
+        })
+        .collect()
+}
+
+fn sample(image: &DynamicImage) -> RgbImage {
+    image
+        .resize(SAMPLE_SIZE, SAMPLE_SIZE, FilterType::Triangle)
+        .to_rgb8()
+}
+
+/// Picks a palette saturation for `image` from its chroma distribution.
+///
+/// Muted images (soft landscapes, overcast photos) get the measured ink
+/// palette (1.0), so their subdued colours still map onto coloured inks
+/// instead of dithering to grey. Vivid images (posters, illustrations) lean
+/// towards the idealised primaries, which match their flat colours with less
+/// dithering noise.
+pub fn auto_saturation(image: &DynamicImage) -> f32 {
+    let samples = chroma_samples(image);
+    if samples.is_empty() {
+        return MUTED_SATURATION;
+    }
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    let position = ((mean - MUTED_CHROMA) / (VIVID_CHROMA - MUTED_CHROMA)).clamp(0.0, 1.0);
+    MUTED_SATURATION + (VIVID_SATURATION - MUTED_SATURATION) * position
+}
+
+/// How one palette colour is used once an image is dithered for the panel.
+#[derive(Clone, Debug)]
+pub struct PaletteUsage {
+    pub name: &'static str,
+    pub colour: [u8; 3],
+    /// Fraction of panel pixels, 0.0-1.0.
+    pub fraction: f32,
+}
+
+/// What an image will look like on a panel, without touching the hardware.
+#[derive(Clone, Debug)]
+pub struct AnalysisReport {
+    pub source_width: u32,
+    pub source_height: u32,
+    pub panel_width: u16,
+    pub panel_height: u16,
+    pub saturation: f32,
+    pub lighten: f32,
+    pub palette: Vec<PaletteUsage>,
+    /// See [`DitherStats::mean_error`](crate::dither::DitherStats::mean_error).
+    pub mean_error: f32,
+    /// Fraction of prepared pixels with every channel at or above 250.
+    pub clipped_highlights: f32,
+    /// Fraction of prepared pixels with every channel at or below 5.
+    pub clipped_shadows: f32,
+    pub mean_chroma: f32,
+    pub recommended_saturation: f32,
+    pub recommended_lighten: f32,
+}
+
+/// One entry of a panel palette.
+#[derive(Clone, Copy, Debug)]
+pub struct PanelColour {
+    pub name: &'static str,
+    pub rgb: [f32; 3],
+    /// Value written to the display buffer (e.g. via `set_pixel`).
+    pub value: u8,
+}
+
+/// The palette for `spec` at `saturation`; a UC8159 palette when the panel
+/// is unknown.
+pub fn panel_palette(spec: Option<&DisplaySpec>, saturation: f32) -> Vec<PanelColour> {
+    match spec {
+        Some(DisplaySpec::El133Uf1 { .. }) => el133uf1::blend_palette(saturation)
+            .into_iter()
+            .enumerate()
+            .map(|(index, rgb)| PanelColour {
+                name: el133uf1::COLOUR_NAMES[index],
+                rgb,
+                value: el133uf1::REMAP[index],
+            })
+            .collect(),
+        _ => uc8159::build_palette(saturation)
+            .into_iter()
+            .enumerate()
+            .map(|(index, rgb)| PanelColour {
+                name: uc8159::COLOUR_NAMES[index],
+                rgb,
+                value: index as u8,
+            })
+            .collect(),
+    }
+}
+
+/// Input dimensions for `spec` at `rotation`, without opening the display;
+/// 600x448 when the panel is unknown.
+pub fn panel_dimensions(spec: Option<&DisplaySpec>, rotation: Rotation) -> (u16, u16) {
+    let (width, height) = match spec {
+        Some(DisplaySpec::Uc8159 { width, height, .. })
+        | Some(DisplaySpec::El133Uf1 { width, height }) => (*width, *height),
+        None => (600, 448),
+    };
+    rotation.target_dimensions(width, height)
+}
+
+/// Prepares and dithers `image` for the panel described by `spec` the way
+/// [`InkyDisplay::set_image_with`](crate::displays::InkyDisplay::set_image_with)
+/// would with `options`, and reports palette usage alongside some source
+/// statistics.
+pub fn analyze(
+    image: &DynamicImage,
+    spec: Option<&DisplaySpec>,
+    rotation: Rotation,
+    options: &RenderOptions,
+) -> AnalysisReport {
+    let (panel_width, panel_height) = panel_dimensions(spec, rotation);
+    let rgb = options.prepare(image, panel_width as u32, panel_height as u32);
+    analyze_prepared(image, &rgb, spec, options)
+}
+
+/// [`analyze`] for a frame already prepared at panel resolution from
+/// `image`, e.g. with colour correction applied. Only the saturation and
+/// dither settings of `options` are applied; the rest are reported.
+pub fn analyze_prepared(
+    image: &DynamicImage,
+    rgb: &RgbImage,
+    spec: Option<&DisplaySpec>,
+    options: &RenderOptions,
+) -> AnalysisReport {
+    let palette = panel_palette(spec, options.saturation);
+    let names: Vec<&'static str> = palette.iter().map(|colour| colour.name).collect();
+    let colours: Vec<[f32; 3]> = palette.iter().map(|colour| colour.rgb).collect();
+    let (_, stats) = dither_with_stats(rgb, &colours, options.dither);
+    let mut render = RenderReport::from_dither(&names, &colours, &stats);
+    render.measure_clipping(rgb);
+
+    let samples = chroma_samples(image);
+    let mean_chroma = if samples.is_empty() {
+        0.0
+    } else {
+        samples.iter().sum::<f32>() / samples.len() as f32
+    };
+
+    AnalysisReport {
+        source_width: image.width(),
+        source_height: image.height(),
+        panel_width: rgb.width() as u16,
+        panel_height: rgb.height() as u16,
+        saturation: options.saturation,
+        lighten: options.lighten,
+        palette: render.palette,
+        mean_error: render.mean_error,
+        clipped_highlights: render.clipped_highlights,
+        clipped_shadows: render.clipped_shadows,
+        mean_chroma,
+        recommended_saturation: round_to_step(auto_saturation(image)),
+        recommended_lighten: recommend_lighten(mean_luma(image)),
+    }
+}
+
+/// Mean luma (0.0-1.0) of the source image, before any adjustments.
+fn mean_luma(image: &DynamicImage) -> f32 {
+    let sample = sample(image);
+    let total = (sample.width() as usize * sample.height() as usize).max(1);
+    let sum: f32 = sample
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0;
+            (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0
+        })
+        .sum();
+    sum / total as f32
+}
+
+/// Panels reflect far less light than a screen emits, so dark images read
+/// as murky; lift anything darker than mid-grey, up to a lighten of 0.4.
+fn recommend_lighten(mean_luma: f32) -> f32 {
+    round_to_step(((0.5 - mean_luma) * 0.8).clamp(0.0, 0.4))
+}
+
+fn round_to_step(value: f32) -> f32 {
+    (value * 20.0).round() / 20.0
+}
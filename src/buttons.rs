@@ -0,0 +1,250 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gpio_cdev::{Chip, EventRequestFlags, EventType, LineRequestFlags};
+
+use crate::displays::common::line_request_error;
+use crate::displays::{DisplaySpec, InkyError, Result};
+
+/// Presses on the same button closer together than this are contact bounce.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Button {
+    pub const ALL: [Button; 4] = [Button::A, Button::B, Button::C, Button::D];
+
+    fn index(self) -> usize {
+        match self {
+            Button::A => 0,
+            Button::B => 1,
+            Button::C => 2,
+            Button::D => 3,
+        }
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Button::A => "A",
+            Button::B => "B",
+            Button::C => "C",
+            Button::D => "D",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Button {
+    type Err = InkyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "c" => Ok(Button::C),
+            "d" => Ok(Button::D),
+            _ => Err(InkyError::InvalidButton(format!(
+                "unknown button `{value}`"
+            ))),
+        }
+    }
+}
+
+/// BCM GPIO lines for the four buttons on the Inky Impression boards.
+///
+/// The buttons pull the line to ground, so the lines need pull-ups. GPIO 5
+/// and 6 have them by default; 16/25 and 24 do not, so add e.g.
+/// `gpio=5,6,16,24=ip,pu` to `config.txt`.
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonPins {
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    pub d: u32,
+}
+
+impl Default for ButtonPins {
+    fn default() -> Self {
+        Self {
+            a: 5,
+            b: 6,
+            c: 16,
+            d: 24,
+        }
+    }
+}
+
+impl ButtonPins {
+    /// The 13.3" board uses GPIO 16 as its second chip select, so button C
+    /// moves to GPIO 25 there.
+    pub fn for_display(spec: Option<&DisplaySpec>) -> Self {
+        match spec {
+            Some(DisplaySpec::El133Uf1 { .. }) => Self {
+                c: 25,
+                ..Self::default()
+            },
+            _ => Self::default(),
+        }
+    }
+
+    fn pin(&self, button: Button) -> u32 {
+        match button {
+            Button::A => self.a,
+            Button::B => self.b,
+            Button::C => self.c,
+            Button::D => self.d,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ButtonAction {
+    NextItem,
+    PreviousItem,
+    /// Send the current frame to the panel again.
+    Redisplay,
+    /// Clear the panel to white.
+    Clear,
+    /// Render the named content provider.
+    RunProvider(String),
+    Ignore,
+}
+
+impl FromStr for ButtonAction {
+    type Err = InkyError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        if let Some(name) = value.strip_prefix("provider:") {
+            if name.is_empty() {
+                return Err(InkyError::InvalidButton(
+                    "provider action needs a name, e.g. provider:clock".to_string(),
+                ));
+            }
+            return Ok(ButtonAction::RunProvider(name.to_string()));
+        }
+        match value {
+            "next" => Ok(ButtonAction::NextItem),
+            "previous" | "prev" => Ok(ButtonAction::PreviousItem),
+            "redisplay" => Ok(ButtonAction::Redisplay),
+            "clear" => Ok(ButtonAction::Clear),
+            "none" => Ok(ButtonAction::Ignore),
+            _ => Err(InkyError::InvalidButton(format!(
+                "unknown action `{value}` (expected next, previous, redisplay, clear, none or provider:NAME)"
+            ))),
+        }
+    }
+}
+
+/// Action bound to each button. Defaults: A next, B previous, C redisplay,
+/// D clear.
+#[derive(Clone, Debug)]
+pub struct ButtonMap {
+    actions: [ButtonAction; 4],
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        Self {
+            actions: [
+                ButtonAction::NextItem,
+                ButtonAction::PreviousItem,
+                ButtonAction::Redisplay,
+                ButtonAction::Clear,
+            ],
+        }
+    }
+}
+
+impl ButtonMap {
+    pub fn action(&self, button: Button) -> &ButtonAction {
+        &self.actions[button.index()]
+    }
+
+    pub fn set(&mut self, button: Button, action: ButtonAction) {
+        self.actions[button.index()] = action;
+    }
+
+    /// Applies a `button=action` binding such as `c=provider:clock`.
+    pub fn apply_binding(&mut self, binding: &str) -> Result<()> {
+        let (button, action) = binding.split_once('=').ok_or_else(|| {
+            InkyError::InvalidButton(format!("expected BUTTON=ACTION, got `{binding}`"))
+        })?;
+        self.set(button.trim().parse()?, action.trim().parse()?);
+        Ok(())
+    }
+}
+
+/// Debounced button presses, delivered from one watcher thread per line.
+pub struct ButtonEvents {
+    receiver: Receiver<Button>,
+    last_press: [Option<Instant>; 4],
+}
+
+impl ButtonEvents {
+    pub fn open(gpio_chip: &str, pins: &ButtonPins) -> Result<Self> {
+        let mut chip = Chip::new(gpio_chip)?;
+        let (sender, receiver) = mpsc::channel();
+
+        for button in Button::ALL {
+            let line = chip.get_line(pins.pin(button))?;
+            let handle = line
+                .events(
+                    LineRequestFlags::INPUT,
+                    EventRequestFlags::FALLING_EDGE,
+                    "paperwave-button",
+                )
+                .map_err(|err| line_request_error(&line, err))?;
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for event in handle {
+                    let Ok(event) = event else {
+                        break;
+                    };
+                    if event.event_type() == EventType::FallingEdge && sender.send(button).is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Ok(Self {
+            receiver,
+            last_press: [None; 4],
+        })
+    }
+
+    /// Waits until `deadline` for the next press; `None` on timeout.
+    pub fn wait_until(&mut self, deadline: Instant) -> Option<Button> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(remaining) {
+                Ok(button) => {
+                    let now = Instant::now();
+                    let last = &mut self.last_press[button.index()];
+                    let bounced = last.is_some_and(|at| now.duration_since(at) < DEBOUNCE);
+                    *last = Some(now);
+                    if !bounced {
+                        return Some(button);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(remaining);
+                    return None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::displays::{InkyError, Result, Timeouts};
+use crate::dither::ColourMapping;
+use crate::document::{self, Section};
+use crate::state::StateValue;
+
+/// Prefix of the environment variables read by [`Config::apply_env`].
+pub const ENV_PREFIX: &str = "PAPERWAVE_";
+
+/// Settings loaded from a TOML file:
+///
+/// ```toml
+/// [display]
+/// busy_retries = 2
+/// reset_timeout = 1       # seconds
+/// power_timeout = 0.5
+/// refresh_timeout = 60
+/// spi_path = "/dev/spidev0.0"
+/// gpio_chip = "/dev/gpiochip0"
+/// cs_pin = 8              # cs1_pin, dc_pin, reset_pin and busy_pin too
+/// ```
+///
+/// Anything left out keeps the detected controller's default. Each key can
+/// also be set with a `PAPERWAVE_` environment variable, e.g.
+/// `PAPERWAVE_SPI_PATH`, which takes precedence over the file.
+///
+/// An optional `[notify]` section runs a command when updates keep failing
+/// (see [`NotifyConfig`]), and `[palette]` substitutes one panel colour for
+/// another:
+///
+/// ```toml
+/// [palette]
+/// green = "black"
+/// yellow = "white"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub display: DisplayOverrides,
+    pub notify: NotifyConfig,
+    /// Resolved against the detected panel with
+    /// [`PaletteRemap::resolve`](crate::dither::PaletteRemap::resolve).
+    pub palette_map: Vec<ColourMapping>,
+}
+
+/// Failure notifications from `[notify]`:
+///
+/// ```toml
+/// [notify]
+/// command = "curl -s -d \"$PAPERWAVE_MESSAGE\" https://ntfy.sh/my-frame"
+/// after_failures = 3
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotifyConfig {
+    /// Shell command run by [`Notifier`](crate::notify::Notifier); nothing is
+    /// sent without one.
+    pub command: Option<String>,
+    /// Consecutive failed updates before notifying.
+    pub after_failures: u32,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            after_failures: 3,
+        }
+    }
+}
+
+/// Overrides for the driver settings in `[display]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DisplayOverrides {
+    pub busy_retries: Option<u32>,
+    pub reset_timeout: Option<Duration>,
+    pub power_timeout: Option<Duration>,
+    pub refresh_timeout: Option<Duration>,
+    pub spi_path: Option<String>,
+    pub gpio_chip: Option<String>,
+    /// Chip select; on the EL133UF1 the first of its two.
+    pub cs_pin: Option<u32>,
+    /// The EL133UF1's second chip select, ignored by the UC8159.
+    pub cs1_pin: Option<u32>,
+    pub dc_pin: Option<u32>,
+    pub reset_pin: Option<u32>,
+    pub busy_pin: Option<u32>,
+}
+
+impl DisplayOverrides {
+    /// `defaults` with any configured timeouts substituted.
+    pub fn timeouts(&self, defaults: Timeouts) -> Timeouts {
+        Timeouts {
+            reset: self.reset_timeout.unwrap_or(defaults.reset),
+            power: self.power_timeout.unwrap_or(defaults.power),
+            refresh: self.refresh_timeout.unwrap_or(defaults.refresh),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+            .map_err(|err| InkyError::InvalidConfig(format!("{}: {err}", path.display())))
+    }
+
+    pub fn parse(contents: &str) -> std::result::Result<Self, String> {
+        let mut config = Config::default();
+        for section in document::parse(contents)? {
+            match (section.name.as_deref(), section.array) {
+                (None, _) if section.entries.is_empty() => {}
+                (Some("display"), false) => parse_display(&mut config.display, &section)?,
+                (Some("notify"), false) => parse_notify(&mut config.notify, &section)?,
+                (Some("palette"), false) => parse_palette(&mut config.palette_map, &section)?,
+                (Some(name), _) => {
+                    return Err(format!(
+                        "line {}: unknown section `{name}` (expected [display], [notify] or [palette])",
+                        section.line
+                    ));
+                }
+                (None, _) => {
+                    return Err(format!(
+                        "line {}: settings belong in a section such as [display]",
+                        section.entries[0].line
+                    ));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Layers `PAPERWAVE_*` environment variables over the loaded values;
+    /// command-line flags are applied after this, so they win over both.
+    pub fn apply_env(&mut self) -> Result<()> {
+        self.apply_vars(env::vars())
+    }
+
+    /// [`apply_env`](Self::apply_env) with the variables supplied by the
+    /// caller. Variables without the prefix, or naming settings that live
+    /// outside the config file such as `PAPERWAVE_CONFIG`, are ignored.
+    pub fn apply_vars(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<()> {
+        for (name, raw) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_ascii_lowercase();
+            if !DISPLAY_KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            set_display(&mut self.display, &key, &env_value(&raw))
+                .map_err(|err| InkyError::InvalidConfig(format!("{name}: {err}")))?;
+        }
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/paperwave/config.toml`, falling back to
+/// `~/.config/paperwave/config.toml`; `None` when neither variable is set.
+pub fn default_config_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").filter(|d| !d.is_empty())?).join(".config"),
+    };
+    Some(dir.join("paperwave/config.toml"))
+}
+
+const DISPLAY_KEYS: [&str; 11] = [
+    "busy_retries",
+    "reset_timeout",
+    "power_timeout",
+    "refresh_timeout",
+    "spi_path",
+    "gpio_chip",
+    "cs_pin",
+    "cs1_pin",
+    "dc_pin",
+    "reset_pin",
+    "busy_pin",
+];
+
+fn parse_display(
+    display: &mut DisplayOverrides,
+    section: &Section,
+) -> std::result::Result<(), String> {
+    for entry in &section.entries {
+        if !DISPLAY_KEYS.contains(&entry.key.as_str()) {
+            return Err(format!("line {}: unknown key `{}`", entry.line, entry.key));
+        }
+        set_display(display, &entry.key, &entry.value)
+            .map_err(|err| format!("line {}: {err}", entry.line))?;
+    }
+    Ok(())
+}
+
+fn parse_notify(notify: &mut NotifyConfig, section: &Section) -> std::result::Result<(), String> {
+    for entry in &section.entries {
+        match (entry.key.as_str(), &entry.value) {
+            ("command", StateValue::String(command)) if !command.trim().is_empty() => {
+                notify.command = Some(command.clone());
+            }
+            ("after_failures", StateValue::Integer(count))
+                if (1..=u32::MAX as i64).contains(count) =>
+            {
+                notify.after_failures = *count as u32;
+            }
+            ("command", _) => {
+                return Err(format!(
+                    "line {}: `command` must be a non-empty string",
+                    entry.line
+                ));
+            }
+            ("after_failures", _) => {
+                return Err(format!(
+                    "line {}: `after_failures` must be a positive integer",
+                    entry.line
+                ));
+            }
+            (other, _) => return Err(format!("line {}: unknown key `{other}`", entry.line)),
+        }
+    }
+    Ok(())
+}
+
+fn parse_palette(
+    palette_map: &mut Vec<ColourMapping>,
+    section: &Section,
+) -> std::result::Result<(), String> {
+    for entry in &section.entries {
+        match &entry.value {
+            StateValue::String(to) if !to.trim().is_empty() => palette_map.push(ColourMapping {
+                from: entry.key.to_ascii_lowercase(),
+                to: to.trim().to_ascii_lowercase(),
+            }),
+            _ => {
+                return Err(format!(
+                    "line {}: `{}` must name the colour to show instead, e.g. \"black\"",
+                    entry.line, entry.key
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_display(
+    display: &mut DisplayOverrides,
+    key: &str,
+    value: &StateValue,
+) -> std::result::Result<(), String> {
+    let count = || match *value {
+        StateValue::Integer(value) if (0..=u32::MAX as i64).contains(&value) => Ok(value as u32),
+        _ => Err(format!("`{key}` must be a non-negative integer")),
+    };
+    let seconds = || {
+        document::seconds(value)
+            .ok_or_else(|| format!("`{key}` must be a positive number of seconds"))
+    };
+    let path = || match value {
+        StateValue::String(value) if !value.is_empty() => Ok(value.clone()),
+        _ => Err(format!("`{key}` must be a device path")),
+    };
+    match key {
+        "busy_retries" => display.busy_retries = Some(count()?),
+        "reset_timeout" => display.reset_timeout = Some(seconds()?),
+        "power_timeout" => display.power_timeout = Some(seconds()?),
+        "refresh_timeout" => display.refresh_timeout = Some(seconds()?),
+        "spi_path" => display.spi_path = Some(path()?),
+        "gpio_chip" => display.gpio_chip = Some(path()?),
+        "cs_pin" => display.cs_pin = Some(count()?),
+        "cs1_pin" => display.cs1_pin = Some(count()?),
+        "dc_pin" => display.dc_pin = Some(count()?),
+        "reset_pin" => display.reset_pin = Some(count()?),
+        "busy_pin" => display.busy_pin = Some(count()?),
+        other => return Err(format!("unknown key `{other}`")),
+    }
+    Ok(())
+}
+
+/// Environment values are unquoted, so numbers are read as numbers and
+/// anything else as a string.
+fn env_value(raw: &str) -> StateValue {
+    let raw = raw.trim();
+    if let Ok(value) = raw.parse() {
+        StateValue::Integer(value)
+    } else if let Ok(value) = raw.parse() {
+        StateValue::Float(value)
+    } else {
+        StateValue::String(raw.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let mut config = Config::parse(
+            "[display]\nbusy_retries = 2\nspi_path = \"/dev/spidev0.0\"\ndc_pin = 22\n",
+        )
+        .unwrap();
+        config
+            .apply_vars(vars(&[
+                ("PAPERWAVE_SPI_PATH", "/dev/spidev1.0"),
+                ("PAPERWAVE_BUSY_PIN", "5"),
+                ("PAPERWAVE_REFRESH_TIMEOUT", "45.5"),
+                ("PAPERWAVE_CONFIG", "/etc/paperwave.toml"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+
+        let display = &config.display;
+        assert_eq!(display.busy_retries, Some(2));
+        assert_eq!(display.spi_path.as_deref(), Some("/dev/spidev1.0"));
+        assert_eq!(display.dc_pin, Some(22));
+        assert_eq!(display.busy_pin, Some(5));
+        assert_eq!(display.refresh_timeout, Some(Duration::from_secs_f64(45.5)));
+    }
+
+    #[test]
+    fn palette_section_lists_mappings() {
+        let config = Config::parse("[palette]\nGreen = \"black\"\nyellow = \"white\"\n").unwrap();
+        let pairs: Vec<_> = config
+            .palette_map
+            .iter()
+            .map(|mapping| (mapping.from.as_str(), mapping.to.as_str()))
+            .collect();
+        assert_eq!(pairs, [("green", "black"), ("yellow", "white")]);
+        assert!(Config::parse("[palette]\ngreen = 0\n").is_err());
+    }
+
+    #[test]
+    fn invalid_environment_names_the_variable() {
+        let err = Config::default()
+            .apply_vars(vars(&[("PAPERWAVE_CS_PIN", "eight")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("PAPERWAVE_CS_PIN"), "{err}");
+        assert!(Config::parse("[display]\ngpio_chip = 0\n").is_err());
+        assert!(Config::parse("[display]\nrefresh_timeout = 1e30\n").is_err());
+        let err = Config::default()
+            .apply_vars(vars(&[("PAPERWAVE_REFRESH_TIMEOUT", "inf")]))
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("positive number of seconds"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use super::{ContentProvider, PanelSpec, Result};
+use crate::draw;
+
+/// Renders a calibration chart: one solid swatch per palette colour, labelled
+/// with its palette index, above gradient rows that blend each colour from
+/// black through to white.
+///
+/// Give it the palette the frame will be quantized with, so every swatch maps
+/// to a single ink; photographing the panel then shows the real pigment
+/// colours, and the gradients show how the dither mixes them.
+pub struct ChartProvider {
+    palette: Vec<[u8; 3]>,
+}
+
+impl ChartProvider {
+    pub fn new(palette: Vec<[u8; 3]>) -> Self {
+        Self { palette }
+    }
+
+    pub fn palette(&self) -> &[[u8; 3]] {
+        &self.palette
+    }
+
+    pub fn render_chart(&self, width: u32, height: u32) -> RgbImage {
+        render_colour_chart(width, height, &self.palette)
+    }
+}
+
+impl ContentProvider for ChartProvider {
+    fn name(&self) -> &str {
+        "chart"
+    }
+
+    /// The chart never changes.
+    fn refresh_interval(&self) -> Duration {
+        Duration::MAX
+    }
+
+    fn render(&mut self, spec: &PanelSpec) -> Result<DynamicImage> {
+        Ok(DynamicImage::ImageRgb8(
+            self.render_chart(spec.width as u32, spec.height as u32),
+        ))
+    }
+}
+
+/// Draws the chart described on [`ChartProvider`] at `width`x`height`.
+pub fn render_colour_chart(width: u32, height: u32, palette: &[[u8; 3]]) -> RgbImage {
+    let mut image = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    if palette.is_empty() || width == 0 || height == 0 {
+        return image;
+    }
+
+    let (w, h) = (width as i64, height as i64);
+    let count = palette.len() as i64;
+    let margin = (w.min(h) / 40).max(2);
+    let swatch_h = (h * 2 / 5).max(1);
+
+    for (index, colour) in palette.iter().enumerate() {
+        let index = index as i64;
+        let x0 = margin + (w - margin) * index / count;
+        let x1 = margin + (w - margin) * (index + 1) / count - margin;
+        draw::fill_rect(
+            &mut image,
+            x0,
+            margin,
+            x1 - x0,
+            swatch_h - margin,
+            Rgb(*colour),
+        );
+        // Outline so the white swatch stands out from the background.
+        draw::stroke_rect(
+            &mut image,
+            x0,
+            margin,
+            x1 - x0,
+            swatch_h - margin,
+            (margin / 4).max(1),
+            Rgb([0, 0, 0]),
+        );
+
+        let label_h = ((swatch_h - margin) / 4).max(5);
+        let label_w = label_h * 5 / 9;
+        let thickness = (label_h / 8).max(1);
+        draw::draw_segment_digit(
+            &mut image,
+            x0 + label_w / 3,
+            margin + label_h / 4,
+            label_w,
+            label_h,
+            thickness,
+            (index % 10) as u8,
+            contrasting(*colour),
+        );
+    }
+
+    // Grey ramp first, then black -> colour -> white for every chromatic entry.
+    let mut ramps = vec![[128u8, 128, 128]];
+    ramps.extend(
+        palette
+            .iter()
+            .filter(|colour| chroma(**colour) > 32)
+            .copied(),
+    );
+    let ramp_top = swatch_h + margin;
+    let ramp_area = h - ramp_top - margin;
+    let rows = ramps.len() as i64;
+    for (row, colour) in ramps.iter().enumerate() {
+        let row = row as i64;
+        let y0 = ramp_top + ramp_area * row / rows;
+        let y1 = ramp_top + ramp_area * (row + 1) / rows - margin / 2;
+        let span = (w - margin * 2).max(1);
+        for x in 0..span {
+            let t = x as f32 / (span - 1).max(1) as f32;
+            let shade = if t < 0.5 {
+                blend([0, 0, 0], *colour, t * 2.0)
+            } else {
+                blend(*colour, [255, 255, 255], (t - 0.5) * 2.0)
+            };
+            draw::fill_rect(&mut image, margin + x, y0, 1, y1 - y0, Rgb(shade));
+        }
+    }
+
+    image
+}
+
+fn blend(from: [u8; 3], to: [u8; 3], t: f32) -> [u8; 3] {
+    let mut out = [0u8; 3];
+    for channel in 0..3 {
+        let value = from[channel] as f32 + (to[channel] as f32 - from[channel] as f32) * t;
+        out[channel] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    out
+}
+
+fn chroma(colour: [u8; 3]) -> u8 {
+    let [r, g, b] = colour;
+    r.max(g).max(b) - r.min(g).min(b)
+}
+
+/// Black or white, whichever reads better on `colour`.
+fn contrasting(colour: [u8; 3]) -> Rgb<u8> {
+    let [r, g, b] = colour.map(|channel| channel as f32);
+    if 0.299 * r + 0.587 * g + 0.114 * b > 140.0 {
+        Rgb([0, 0, 0])
+    } else {
+        Rgb([255, 255, 255])
+    }
+}
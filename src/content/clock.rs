@@ -0,0 +1,197 @@
+use std::f32::consts::PI;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use super::{ContentProvider, PanelSpec, Result};
+use crate::draw;
+use crate::scheduler::civil_from_days;
+
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const RED: Rgb<u8> = Rgb([255, 0, 0]);
+/// Date digits relative to the time's on the digital face.
+const DATE_SCALE: f32 = 0.35;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClockFace {
+    #[default]
+    Analog,
+    /// Large seven-segment `HH:MM`.
+    Digital,
+}
+
+/// Renders the current time as a full-panel clock face, with the date as
+/// `YYYY-MM-DD` underneath. There are no second hands or other fast-moving
+/// elements, so the face stays readable between infrequent full refreshes.
+///
+/// Time is UTC plus a fixed offset; there is no time zone database.
+pub struct ClockProvider {
+    face: ClockFace,
+    utc_offset: i64,
+    interval: Duration,
+}
+
+impl ClockProvider {
+    pub fn new(face: ClockFace) -> Self {
+        Self {
+            face,
+            utc_offset: 0,
+            interval: Duration::from_secs(15 * 60),
+        }
+    }
+
+    pub fn set_utc_offset(&mut self, offset_seconds: i64) {
+        self.utc_offset = offset_seconds;
+    }
+
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn render_at(&self, spec: &PanelSpec, time: SystemTime) -> RgbImage {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(_) => 0,
+        };
+        let local = secs + self.utc_offset;
+        let minute_of_day = local.rem_euclid(86_400) / 60;
+        let hour = (minute_of_day / 60) as u32;
+        let minute = (minute_of_day % 60) as u32;
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        let date = format!("{year:04}-{month:02}-{day:02}");
+
+        let mut image = RgbImage::from_pixel(spec.width as u32, spec.height as u32, WHITE);
+        match self.face {
+            ClockFace::Analog => draw_analog(&mut image, hour, minute, &date),
+            ClockFace::Digital => draw_digital(&mut image, hour, minute, &date),
+        }
+        image
+    }
+}
+
+impl ContentProvider for ClockProvider {
+    fn name(&self) -> &str {
+        "clock"
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn render(&mut self, spec: &PanelSpec) -> Result<DynamicImage> {
+        Ok(DynamicImage::ImageRgb8(
+            self.render_at(spec, SystemTime::now()),
+        ))
+    }
+}
+
+fn draw_analog(image: &mut RgbImage, hour: u32, minute: u32, date: &str) {
+    let (w, h) = (image.width() as f32, image.height() as f32);
+    // The dial takes the top 85%, the date the strip below it.
+    let dial_h = h * 0.85;
+    let size = w.min(dial_h);
+    let (cx, cy) = (w / 2.0, dial_h / 2.0);
+    let radius = size * 0.45;
+
+    draw::fill_ring(image, cx, cy, radius, radius - size * 0.02, BLACK);
+
+    for tick in 0..12 {
+        let major = tick % 3 == 0;
+        let inner = radius * if major { 0.78 } else { 0.85 };
+        let outer = radius * 0.92;
+        let thickness = size * if major { 0.022 } else { 0.012 };
+        let (dx, dy) = hand_vector(tick as f32 / 12.0);
+        draw::draw_line(
+            image,
+            (cx + dx * inner, cy + dy * inner),
+            (cx + dx * outer, cy + dy * outer),
+            thickness,
+            BLACK,
+        );
+    }
+
+    let hour_turn = ((hour % 12) as f32 + minute as f32 / 60.0) / 12.0;
+    let (hx, hy) = hand_vector(hour_turn);
+    draw::draw_line(
+        image,
+        (cx, cy),
+        (cx + hx * radius * 0.5, cy + hy * radius * 0.5),
+        size * 0.045,
+        BLACK,
+    );
+
+    let (mx, my) = hand_vector(minute as f32 / 60.0);
+    draw::draw_line(
+        image,
+        (cx, cy),
+        (cx + mx * radius * 0.78, cy + my * radius * 0.78),
+        size * 0.028,
+        BLACK,
+    );
+
+    draw::fill_circle(image, cx, cy, size * 0.035, RED);
+
+    let strip = h - dial_h;
+    let digit_w = draw::fit_segment_digit_width(date, w * 0.5).min(strip * 0.6 / 1.8);
+    draw_date(image, date, digit_w, dial_h + (strip - digit_w * 1.8) / 2.0);
+}
+
+/// Unit vector for a fraction of a clockwise turn starting at 12 o'clock.
+fn hand_vector(turn: f32) -> (f32, f32) {
+    let angle = turn * 2.0 * PI;
+    (angle.sin(), -angle.cos())
+}
+
+fn draw_digital(image: &mut RgbImage, hour: u32, minute: u32, date: &str) {
+    let (w, h) = (image.width() as f32, image.height() as f32);
+    let hours = format!("{hour:02}");
+    let minutes = format!("{minute:02}");
+    let text = format!("{hours}:{minutes}");
+    // The time, half a digit of space, then the date at DATE_SCALE, in 80%
+    // of the height.
+    let block = 1.8 + 0.5 + 1.8 * DATE_SCALE;
+    let digit_w = draw::fit_segment_digit_width(&text, w * 0.85).min(h * 0.8 / block);
+    let gap = digit_w * 0.25;
+
+    let x = (w - draw::segment_text_width(&text, digit_w)) / 2.0;
+    let y = (h - digit_w * block) / 2.0;
+
+    // Laid out exactly as `draw_segment_text` would lay out `text`, with
+    // the colon in red.
+    let colon_x = x + draw::segment_text_width(&hours, digit_w) + gap;
+    let minutes_x = x + draw::segment_text_width(&format!("{hours}:"), digit_w) + gap;
+    draw::draw_segment_text(image, &hours, x, y, digit_w, BLACK);
+    draw::draw_segment_text(image, ":", colon_x, y, digit_w, RED);
+    draw::draw_segment_text(image, &minutes, minutes_x, y, digit_w, BLACK);
+
+    draw_date(image, date, digit_w * DATE_SCALE, y + digit_w * 2.3);
+}
+
+/// Draws `date` centred across the image with its top at `y`.
+fn draw_date(image: &mut RgbImage, date: &str, digit_w: f32, y: f32) {
+    let x = (image.width() as f32 - draw::segment_text_width(date, digit_w)) / 2.0;
+    draw::draw_segment_text(image, date, x, y, digit_w, BLACK);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digital_face_is_centred() {
+        let clock = ClockProvider::new(ClockFace::Digital);
+        // 20:08: both outer digits fill their cells.
+        let time = UNIX_EPOCH + Duration::from_secs(20 * 3600 + 8 * 60);
+        let image = clock.render_at(&PanelSpec::new(600, 448, 7), time);
+
+        let inked: Vec<u32> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel != WHITE)
+            .map(|(x, _, _)| x)
+            .collect();
+        let left = *inked.iter().min().unwrap();
+        let right = image.width() - 1 - *inked.iter().max().unwrap();
+        assert!(left.abs_diff(right) <= 2, "margins {left} and {right}");
+    }
+}
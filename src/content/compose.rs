@@ -0,0 +1,542 @@
+//! Frames assembled from several independently refreshed regions, such as a
+//! photo above a status bar, or split into tiles that act as separate
+//! virtual displays.
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage, imageops};
+
+use super::{ContentError, ContentProvider, PanelSpec, Result};
+use crate::displays::clamp_aspect_resize;
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// A rectangle of the frame, in input (rotated) coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Splits a `width`x`height` frame into `columns` x `rows` tiles, in
+    /// reading order. Leftover pixels go to the last column and row.
+    pub fn grid(width: u16, height: u16, columns: u16, rows: u16) -> Vec<Rect> {
+        if columns == 0 || rows == 0 {
+            return Vec::new();
+        }
+        let (tile_w, tile_h) = (width / columns, height / rows);
+        let mut tiles = Vec::with_capacity(columns as usize * rows as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, y) = (column * tile_w, row * tile_h);
+                let w = if column + 1 == columns {
+                    width - x
+                } else {
+                    tile_w
+                };
+                let h = if row + 1 == rows { height - y } else { tile_h };
+                tiles.push(Rect::new(x, y, w, h));
+            }
+        }
+        tiles
+    }
+
+    fn right(&self) -> u32 {
+        self.x as u32 + self.width as u32
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y as u32 + self.height as u32
+    }
+
+    fn overlaps(&self, other: &Rect) -> bool {
+        (self.x as u32) < other.right()
+            && (other.x as u32) < self.right()
+            && (self.y as u32) < other.bottom()
+            && (other.y as u32) < self.bottom()
+    }
+}
+
+/// Parses `X,Y,WxH`, e.g. `0,400,600x48`.
+impl FromStr for Rect {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("expected X,Y,WxH, got `{value}`");
+        let mut parts = value.split(',').map(str::trim);
+        let (Some(x), Some(y), Some(size), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let number = |part: &str| part.trim().parse::<u16>().map_err(|_| invalid());
+        Ok(Self::new(
+            number(x)?,
+            number(y)?,
+            number(width)?,
+            number(height)?,
+        ))
+    }
+}
+
+struct Region {
+    name: String,
+    rect: Rect,
+    /// `None` for tiles, which only change through
+    /// [`Compositor::set_region_image`].
+    provider: Option<Box<dyn ContentProvider>>,
+    interval: Option<Duration>,
+    last_rendered: Option<Instant>,
+}
+
+/// When a region next needs rendering.
+enum Due {
+    /// Never rendered yet.
+    Now,
+    At(Instant),
+    /// A tile, or an interval too long to fall due, like a static chart's
+    /// [`Duration::MAX`].
+    Never,
+}
+
+impl Region {
+    fn is_due(&self, now: Instant) -> bool {
+        match self.due() {
+            Due::Now => true,
+            Due::At(due) => due <= now,
+            Due::Never => false,
+        }
+    }
+
+    fn due(&self) -> Due {
+        let Some(provider) = self.provider.as_ref() else {
+            return Due::Never;
+        };
+        let interval = self.interval.unwrap_or_else(|| provider.refresh_interval());
+        match self.last_rendered {
+            None => Due::Now,
+            Some(last) => last.checked_add(interval).map_or(Due::Never, Due::At),
+        }
+    }
+}
+
+/// What one [`Compositor::update`] did.
+#[derive(Debug, Default)]
+pub struct CompositeUpdate {
+    /// Regions whose pixels differ from what the frame held before.
+    pub changed: Vec<String>,
+    /// Regions that failed to render; they keep their previous contents and
+    /// are retried after their interval.
+    pub failed: Vec<(String, ContentError)>,
+}
+
+impl CompositeUpdate {
+    /// Whether the panel needs a refresh to show the new frame.
+    pub fn needs_refresh(&self) -> bool {
+        !self.changed.is_empty()
+    }
+}
+
+/// Named, non-overlapping regions of one frame, each filled by its own
+/// provider on its own cadence. [`update`](Self::update) renders only the
+/// regions that are due and reports whether anything visibly changed, so
+/// the panel is refreshed only when it has to be.
+///
+/// Tiles ([`add_tile`](Self::add_tile), [`tiled`](Self::tiled)) are regions
+/// without a provider: virtual displays that callers draw into with
+/// [`set_region_image`](Self::set_region_image), e.g. two dashboards side by
+/// side on one 13.3" panel.
+pub struct Compositor {
+    spec: PanelSpec,
+    frame: RgbImage,
+    regions: Vec<Region>,
+}
+
+impl Compositor {
+    /// An empty (white) frame of `spec`'s size.
+    pub fn new(spec: PanelSpec) -> Self {
+        Self {
+            spec,
+            frame: RgbImage::from_pixel(spec.width as u32, spec.height as u32, BACKGROUND),
+            regions: Vec::new(),
+        }
+    }
+
+    /// A frame split into `columns` x `rows` tiles named `1`, `2`, ... in
+    /// reading order.
+    pub fn tiled(spec: PanelSpec, columns: u16, rows: u16) -> Result<Self> {
+        if columns == 0 || rows == 0 || columns > spec.width || rows > spec.height {
+            return Err(ContentError::InvalidLayout(format!(
+                "a {columns}x{rows} grid does not fit a {}x{} frame",
+                spec.width, spec.height
+            )));
+        }
+        let mut compositor = Self::new(spec);
+        for (index, rect) in Rect::grid(spec.width, spec.height, columns, rows)
+            .into_iter()
+            .enumerate()
+        {
+            compositor.add_tile(&(index + 1).to_string(), rect)?;
+        }
+        Ok(compositor)
+    }
+
+    pub fn spec(&self) -> PanelSpec {
+        self.spec
+    }
+
+    /// Adds a region, replacing any region with the same name. The region
+    /// renders at its own size with the provider's refresh interval.
+    pub fn add_region(
+        &mut self,
+        name: &str,
+        rect: Rect,
+        provider: Box<dyn ContentProvider>,
+    ) -> Result<()> {
+        self.insert(name, rect, Some(provider))
+    }
+
+    /// Adds a tile: a region filled only by
+    /// [`set_region_image`](Self::set_region_image).
+    pub fn add_tile(&mut self, name: &str, rect: Rect) -> Result<()> {
+        self.insert(name, rect, None)
+    }
+
+    fn insert(
+        &mut self,
+        name: &str,
+        rect: Rect,
+        provider: Option<Box<dyn ContentProvider>>,
+    ) -> Result<()> {
+        if rect.width == 0
+            || rect.height == 0
+            || rect.right() > self.spec.width as u32
+            || rect.bottom() > self.spec.height as u32
+        {
+            return Err(ContentError::InvalidLayout(format!(
+                "region `{name}` ({}x{} at {},{}) does not fit a {}x{} frame",
+                rect.width, rect.height, rect.x, rect.y, self.spec.width, self.spec.height
+            )));
+        }
+        if let Some(other) = self
+            .regions
+            .iter()
+            .find(|region| region.name != name && region.rect.overlaps(&rect))
+        {
+            return Err(ContentError::InvalidLayout(format!(
+                "region `{name}` overlaps `{}`",
+                other.name
+            )));
+        }
+
+        self.remove_region(name);
+        self.regions.push(Region {
+            name: name.to_string(),
+            rect,
+            provider,
+            interval: None,
+            last_rendered: None,
+        });
+        Ok(())
+    }
+
+    /// Removes a region, blanking its part of the frame.
+    pub fn remove_region(&mut self, name: &str) -> Option<Box<dyn ContentProvider>> {
+        let index = self.regions.iter().position(|region| region.name == name)?;
+        let region = self.regions.remove(index);
+        let blank = RgbImage::from_pixel(
+            region.rect.width as u32,
+            region.rect.height as u32,
+            BACKGROUND,
+        );
+        imageops::replace(
+            &mut self.frame,
+            &blank,
+            region.rect.x as i64,
+            region.rect.y as i64,
+        );
+        region.provider
+    }
+
+    /// Draws `image` into a region, fitted to its size, and returns whether
+    /// any pixels changed.
+    pub fn set_region_image(&mut self, name: &str, image: &DynamicImage) -> Result<bool> {
+        let rect = self
+            .region(name)
+            .ok_or_else(|| ContentError::InvalidLayout(format!("no region named `{name}`")))?;
+        let image = clamp_aspect_resize(image, rect.width as u32, rect.height as u32);
+        Ok(paste(&mut self.frame, rect, &image))
+    }
+
+    /// Starts from a previously composed frame, e.g. one saved between runs,
+    /// so tiles that are not redrawn keep their contents. Returns `false`,
+    /// leaving the frame unchanged, if `frame` is not the spec's size.
+    pub fn restore(&mut self, frame: RgbImage) -> bool {
+        if frame.dimensions() != (self.spec.width as u32, self.spec.height as u32) {
+            return false;
+        }
+        self.frame = frame;
+        true
+    }
+
+    /// Refreshes a region every `interval` instead of its provider's own.
+    pub fn set_interval(&mut self, name: &str, interval: Duration) -> Result<()> {
+        let region = self
+            .regions
+            .iter_mut()
+            .find(|region| region.name == name)
+            .ok_or_else(|| ContentError::InvalidLayout(format!("no region named `{name}`")))?;
+        region.interval = Some(interval);
+        Ok(())
+    }
+
+    pub fn region_names(&self) -> Vec<&str> {
+        self.regions
+            .iter()
+            .map(|region| region.name.as_str())
+            .collect()
+    }
+
+    pub fn region(&self, name: &str) -> Option<Rect> {
+        self.regions
+            .iter()
+            .find(|region| region.name == name)
+            .map(|region| region.rect)
+    }
+
+    /// Provider regions that have never rendered or whose interval has
+    /// elapsed.
+    pub fn due(&self, now: Instant) -> Vec<&str> {
+        self.regions
+            .iter()
+            .filter(|region| region.is_due(now))
+            .map(|region| region.name.as_str())
+            .collect()
+    }
+
+    /// When the next region falls due; `None` if none ever will, e.g.
+    /// without provider regions.
+    pub fn next_due(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.regions
+            .iter()
+            .filter_map(|region| match region.due() {
+                Due::Now => Some(now),
+                Due::At(due) => Some(due),
+                Due::Never => None,
+            })
+            .min()
+    }
+
+    /// Renders every due region into the frame.
+    pub fn update(&mut self, now: Instant) -> CompositeUpdate {
+        let mut update = CompositeUpdate::default();
+        for region in &mut self.regions {
+            if !region.is_due(now) {
+                continue;
+            }
+            let Some(provider) = region.provider.as_mut() else {
+                continue;
+            };
+            region.last_rendered = Some(now);
+
+            let rect = region.rect;
+            let spec = PanelSpec::new(rect.width, rect.height, self.spec.colours);
+            let image = match provider.render(&spec) {
+                Ok(image) => clamp_aspect_resize(&image, rect.width as u32, rect.height as u32),
+                Err(err) => {
+                    update.failed.push((region.name.clone(), err));
+                    continue;
+                }
+            };
+            if paste(&mut self.frame, rect, &image) {
+                update.changed.push(region.name.clone());
+            }
+        }
+        update
+    }
+
+    /// The composed frame, at the spec's size.
+    pub fn frame(&self) -> &RgbImage {
+        &self.frame
+    }
+}
+
+/// Copies `image` into `rect` of `frame` if it differs from what is there.
+fn paste(frame: &mut RgbImage, rect: Rect, image: &RgbImage) -> bool {
+    let current = frame.view(
+        rect.x as u32,
+        rect.y as u32,
+        rect.width as u32,
+        rect.height as u32,
+    );
+    let changed = current
+        .pixels()
+        .zip(image.pixels())
+        .any(|((_, _, a), b)| a != *b);
+    if changed {
+        imageops::replace(frame, image, rect.x as i64, rect.y as i64);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fills its region with a colour that changes on every `step`th render.
+    struct Solid {
+        renders: u32,
+        step: u32,
+        interval: Duration,
+    }
+
+    impl ContentProvider for Solid {
+        fn name(&self) -> &str {
+            "solid"
+        }
+
+        fn refresh_interval(&self) -> Duration {
+            self.interval
+        }
+
+        fn render(&mut self, spec: &PanelSpec) -> Result<DynamicImage> {
+            let shade = (self.renders / self.step) as u8;
+            self.renders += 1;
+            Ok(DynamicImage::ImageRgb8(RgbImage::from_pixel(
+                spec.width as u32,
+                spec.height as u32,
+                Rgb([shade, 0, 0]),
+            )))
+        }
+    }
+
+    fn solid(step: u32, secs: u64) -> Box<dyn ContentProvider> {
+        Box::new(Solid {
+            renders: 0,
+            step,
+            interval: Duration::from_secs(secs),
+        })
+    }
+
+    #[test]
+    fn refreshes_only_changed_regions() {
+        let mut compositor = Compositor::new(PanelSpec::new(8, 6, 7));
+        compositor
+            .add_region("photo", Rect::new(0, 0, 8, 4), solid(1, 60))
+            .unwrap();
+        compositor
+            .add_region("status", Rect::new(0, 4, 8, 2), solid(2, 10))
+            .unwrap();
+
+        let start = Instant::now();
+        let update = compositor.update(start);
+        assert_eq!(update.changed, ["photo", "status"]);
+        assert_eq!(compositor.frame().get_pixel(0, 5), &Rgb([0, 0, 0]));
+        assert_eq!(compositor.frame().get_pixel(0, 0), &Rgb([0, 0, 0]));
+
+        // Only the status bar is due, and its second render is identical.
+        let later = start + Duration::from_secs(10);
+        assert_eq!(compositor.due(later), ["status"]);
+        assert!(!compositor.update(later).needs_refresh());
+
+        let update = compositor.update(later + Duration::from_secs(10));
+        assert_eq!(update.changed, ["status"]);
+        assert_eq!(compositor.frame().get_pixel(0, 5), &Rgb([1, 0, 0]));
+    }
+
+    #[test]
+    fn static_regions_render_once() {
+        // Like the calibration chart, whose interval is `Duration::MAX`.
+        let mut compositor = Compositor::new(PanelSpec::new(8, 4, 6));
+        let provider = Box::new(Solid {
+            renders: 0,
+            step: 1,
+            interval: Duration::MAX,
+        });
+        compositor
+            .add_region("chart", Rect::new(0, 0, 8, 4), provider)
+            .unwrap();
+        assert!(compositor.next_due().is_some());
+
+        let now = Instant::now();
+        assert_eq!(compositor.update(now).changed, ["chart"]);
+        assert!(compositor.next_due().is_none());
+        assert!(compositor.due(now + Duration::from_secs(86_400)).is_empty());
+    }
+
+    #[test]
+    fn parses_rects() {
+        assert_eq!("0,400,600x48".parse(), Ok(Rect::new(0, 400, 600, 48)));
+        assert!("0,400".parse::<Rect>().is_err());
+        assert!("0,400,600".parse::<Rect>().is_err());
+        assert!("0,400,600x48,1".parse::<Rect>().is_err());
+    }
+
+    #[test]
+    fn tiles_are_virtual_displays() {
+        assert_eq!(
+            Rect::grid(1600, 1200, 3, 1),
+            [
+                Rect::new(0, 0, 533, 1200),
+                Rect::new(533, 0, 533, 1200),
+                Rect::new(1066, 0, 534, 1200),
+            ]
+        );
+
+        let mut compositor = Compositor::tiled(PanelSpec::new(8, 4, 6), 2, 1).unwrap();
+        assert_eq!(compositor.region_names(), ["1", "2"]);
+        assert!(compositor.next_due().is_none());
+
+        let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([255, 0, 0])));
+        assert!(compositor.set_region_image("2", &red).unwrap());
+        assert!(!compositor.set_region_image("2", &red).unwrap());
+        assert_eq!(compositor.frame().get_pixel(3, 0), &Rgb([255, 255, 255]));
+        assert_eq!(compositor.frame().get_pixel(4, 0), &Rgb([255, 0, 0]));
+
+        assert!(compositor.set_region_image("3", &red).is_err());
+        assert!(Compositor::tiled(PanelSpec::new(8, 4, 6), 0, 1).is_err());
+        assert!(!compositor.restore(RgbImage::new(4, 4)));
+    }
+
+    #[test]
+    fn rejects_bad_layouts() {
+        let mut compositor = Compositor::new(PanelSpec::new(8, 6, 7));
+        compositor
+            .add_region("a", Rect::new(0, 0, 4, 6), solid(1, 60))
+            .unwrap();
+        assert!(
+            compositor
+                .add_region("b", Rect::new(3, 0, 4, 6), solid(1, 60))
+                .is_err()
+        );
+        assert!(
+            compositor
+                .add_region("c", Rect::new(4, 0, 5, 6), solid(1, 60))
+                .is_err()
+        );
+        assert!(
+            compositor
+                .add_region("d", Rect::new(4, 0, 0, 6), solid(1, 60))
+                .is_err()
+        );
+        // Replacing a region may reuse its own space.
+        compositor
+            .add_region("a", Rect::new(0, 0, 5, 6), solid(1, 60))
+            .unwrap();
+    }
+}
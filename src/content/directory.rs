@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use image::DynamicImage;
+
+use super::{ContentError, ContentProvider, PanelSpec, Result};
+use crate::displays::{ImageLimits, load_image};
+
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// Cycles through the images in a directory tree, such as a Syncthing or
+/// Nextcloud folder. The directory is rescanned before every render so new
+/// arrivals are picked up and deleted files are dropped from the rotation.
+pub struct DirectoryProvider {
+    root: PathBuf,
+    interval: Duration,
+    recursive: bool,
+    index: Vec<PathBuf>,
+    current: Option<PathBuf>,
+}
+
+impl DirectoryProvider {
+    pub fn new<P: Into<PathBuf>>(root: P, interval: Duration) -> Self {
+        Self {
+            root: root.into(),
+            interval,
+            recursive: true,
+            index: Vec::new(),
+            current: None,
+        }
+    }
+
+    pub fn set_recursive(&mut self, recursive: bool) {
+        self.recursive = recursive;
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Images found by the most recent scan, in display order.
+    pub fn images(&self) -> &[PathBuf] {
+        &self.index
+    }
+
+    /// Path of the image returned by the last successful render.
+    pub fn current(&self) -> Option<&Path> {
+        self.current.as_deref()
+    }
+
+    pub fn rescan(&mut self) -> Result<&[PathBuf]> {
+        let mut found = Vec::new();
+        scan_dir(&self.root, self.recursive, &mut found)?;
+        found.sort();
+        self.index = found;
+        Ok(&self.index)
+    }
+
+    fn next_start(&self) -> usize {
+        // Resume after the last shown image; if it was deleted, continue from
+        // where it would have sorted so the rotation order is preserved.
+        match &self.current {
+            Some(current) => match self.index.binary_search(current) {
+                Ok(pos) => pos + 1,
+                Err(pos) => pos,
+            },
+            None => 0,
+        }
+    }
+}
+
+impl ContentProvider for DirectoryProvider {
+    fn name(&self) -> &str {
+        "directory"
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn render(&mut self, _spec: &PanelSpec) -> Result<DynamicImage> {
+        self.rescan()?;
+        if self.index.is_empty() {
+            return Err(ContentError::NoImages(self.root.clone()));
+        }
+
+        let start = self.next_start();
+        let count = self.index.len();
+        let mut last_err = None;
+
+        // Skip files that fail to decode, e.g. ones still being synced.
+        for offset in 0..count {
+            let path = &self.index[(start + offset) % count];
+            match load_image(path, &ImageLimits::default()) {
+                Ok(image) => {
+                    self.current = Some(path.clone());
+                    return Ok(image);
+                }
+                Err(err) => last_err = Some(err.into()),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| ContentError::NoImages(self.root.clone())))
+    }
+}
+
+fn scan_dir(dir: &Path, recursive: bool, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            if recursive && !is_hidden(&path) {
+                scan_dir(&path, recursive, found)?;
+            }
+        } else if is_image(&path) && !is_hidden(&path) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+        .unwrap_or(false)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    // Sync tools stage partial downloads as dotfiles (.syncthing.*, .~tmp).
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
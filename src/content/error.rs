@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::displays::InkyError;
+
+/// Failures above the display drivers: providers, playlists, schedules and
+/// the state store. Display errors pass through unchanged.
+#[derive(Error, Debug)]
+pub enum ContentError {
+    #[error(transparent)]
+    Display(#[from] InkyError),
+
+    #[error("State store error: {0}")]
+    State(String),
+
+    #[error("Invalid playlist {0}")]
+    InvalidPlaylist(String),
+
+    #[error("Invalid schedule {0}")]
+    InvalidSchedule(String),
+
+    #[error("Invalid layout: {0}")]
+    InvalidLayout(String),
+
+    #[error("Unknown content provider: {0}")]
+    UnknownProvider(String),
+
+    #[error("No images found in {0}")]
+    NoImages(PathBuf),
+}
+
+impl From<std::io::Error> for ContentError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Display(err.into())
+    }
+}
+
+impl From<image::ImageError> for ContentError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Display(err.into())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ContentError>;
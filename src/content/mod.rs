@@ -0,0 +1,138 @@
+pub mod chart;
+pub mod clock;
+pub mod compose;
+pub mod directory;
+mod error;
+pub mod status;
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+
+use crate::displays::InkyDisplay;
+
+pub use chart::{ChartProvider, render_colour_chart};
+pub use clock::{ClockFace, ClockProvider};
+pub use compose::{CompositeUpdate, Compositor, Rect};
+pub use directory::DirectoryProvider;
+pub use error::{ContentError, Result};
+pub use status::{StatusProvider, SystemStatus, render_status};
+
+/// Dimensions and colour depth a provider should render for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PanelSpec {
+    pub width: u16,
+    pub height: u16,
+    pub colours: usize,
+}
+
+impl PanelSpec {
+    pub fn new(width: u16, height: u16, colours: usize) -> Self {
+        Self {
+            width,
+            height,
+            colours,
+        }
+    }
+
+    /// Spec matching the display's current input orientation and palette.
+    pub fn for_display(display: &dyn InkyDisplay) -> Self {
+        let (width, height) = display.input_dimensions();
+        Self::new(width, height, display.capabilities().colours)
+    }
+}
+
+impl fmt::Display for PanelSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} ({} colours)",
+            self.width, self.height, self.colours
+        )
+    }
+}
+
+/// A source of frames that can be rendered on demand at panel resolution.
+pub trait ContentProvider {
+    fn name(&self) -> &str;
+    fn refresh_interval(&self) -> Duration;
+    fn render(&mut self, spec: &PanelSpec) -> Result<DynamicImage>;
+}
+
+struct Entry {
+    provider: Box<dyn ContentProvider>,
+    last_rendered: Option<Instant>,
+}
+
+/// Named collection of providers, tracking when each was last rendered.
+#[derive(Default)]
+pub struct ContentRegistry {
+    entries: Vec<Entry>,
+}
+
+impl ContentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a provider, replacing any existing provider with the same name.
+    pub fn register(&mut self, provider: Box<dyn ContentProvider>) {
+        let entry = Entry {
+            provider,
+            last_rendered: None,
+        };
+        match self.position(entry.provider.name()) {
+            Some(index) => self.entries[index] = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn ContentProvider>> {
+        let index = self.position(name)?;
+        Some(self.entries.remove(index).provider)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.provider.name()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ContentProvider> {
+        self.position(name)
+            .map(|index| self.entries[index].provider.as_ref())
+    }
+
+    pub fn render(&mut self, name: &str, spec: &PanelSpec) -> Result<DynamicImage> {
+        let index = self
+            .position(name)
+            .ok_or_else(|| ContentError::UnknownProvider(name.to_string()))?;
+        let entry = &mut self.entries[index];
+        let image = entry.provider.render(spec)?;
+        entry.last_rendered = Some(Instant::now());
+        Ok(image)
+    }
+
+    /// Providers that have never rendered or whose refresh interval has elapsed.
+    pub fn due(&self, now: Instant) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|e| match e.last_rendered {
+                Some(last) => now.saturating_duration_since(last) >= e.provider.refresh_interval(),
+                None => true,
+            })
+            .map(|e| e.provider.name())
+            .collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e.provider.name() == name)
+    }
+}
@@ -0,0 +1,332 @@
+use std::fs;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::Duration;
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use super::{ContentProvider, PanelSpec, Result};
+use crate::draw;
+
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const GREEN: Rgb<u8> = Rgb([0, 255, 0]);
+const YELLOW: Rgb<u8> = Rgb([255, 255, 0]);
+const RED: Rgb<u8> = Rgb([255, 0, 0]);
+
+/// CPU temperatures mapped onto the empty and full ends of the gauge.
+const TEMP_RANGE: (f32, f32) = (20.0, 85.0);
+/// Legend drawn left of each gauge, in gauge order.
+const GAUGE_LABELS: [&str; 3] = ["CPU", "MEM", "DISK"];
+
+/// A snapshot of the host's own health, read from `/proc`, `/sys` and the
+/// root filesystem. Anything that cannot be read is `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemStatus {
+    pub hostname: Option<String>,
+    /// The address used to reach other networks; `None` without a route.
+    pub address: Option<Ipv4Addr>,
+    pub uptime: Option<Duration>,
+    /// Degrees Celsius.
+    pub cpu_temp: Option<f32>,
+    /// Fraction of memory in use, from 0.0 to 1.0.
+    pub memory_used: Option<f32>,
+    /// Fraction of the root filesystem in use, from 0.0 to 1.0.
+    pub disk_used: Option<f32>,
+    pub version: &'static str,
+}
+
+impl SystemStatus {
+    pub fn read() -> Self {
+        Self {
+            hostname: fs::read_to_string("/proc/sys/kernel/hostname")
+                .ok()
+                .map(|name| name.trim().to_string()),
+            address: local_address(),
+            uptime: fs::read_to_string("/proc/uptime")
+                .ok()
+                .and_then(|uptime| uptime.split_whitespace().next()?.parse().ok())
+                .map(Duration::from_secs_f64),
+            cpu_temp: fs::read_to_string("/sys/class/thermal/thermal_zone0/temp")
+                .ok()
+                .and_then(|millis| millis.trim().parse::<f32>().ok())
+                .map(|millis| millis / 1000.0),
+            memory_used: fs::read_to_string("/proc/meminfo")
+                .ok()
+                .and_then(|meminfo| memory_used(&meminfo)),
+            disk_used: disk_used(),
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+}
+
+/// Renders [`SystemStatus`] as a boot screen and diagnostics page, top to
+/// bottom:
+///
+/// - the hostname, in the small bitmap font;
+/// - the IP address, or red dashes when there is no network route;
+/// - gauges for CPU temperature (20-85 °C), memory and disk use, labelled
+///   `CPU`, `MEM` and `DISK`, with the value (°C or percent) to their
+///   right, turning yellow then red as they fill;
+/// - uptime as `DAYS-HH:MM` on the left and the paperwave version on the
+///   right.
+pub struct StatusProvider {
+    interval: Duration,
+}
+
+impl StatusProvider {
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(5 * 60),
+        }
+    }
+
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+}
+
+impl Default for StatusProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentProvider for StatusProvider {
+    fn name(&self) -> &str {
+        "status"
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn render(&mut self, spec: &PanelSpec) -> Result<DynamicImage> {
+        Ok(DynamicImage::ImageRgb8(render_status(
+            spec.width as u32,
+            spec.height as u32,
+            &SystemStatus::read(),
+        )))
+    }
+}
+
+/// Draws the page described on [`StatusProvider`] at `width`x`height`.
+pub fn render_status(width: u32, height: u32, status: &SystemStatus) -> RgbImage {
+    let mut image = RgbImage::from_pixel(width, height, WHITE);
+    let (w, h) = (width as f32, height as f32);
+    let margin = (w.min(h) / 20.0).max(2.0);
+    let inner_w = w - 2.0 * margin;
+
+    if let Some(hostname) = &status.hostname {
+        let scale = fit_label_scale(hostname, inner_w, h * 0.08);
+        draw::draw_text(
+            &mut image,
+            hostname,
+            margin as i64,
+            margin as i64,
+            scale,
+            BLACK,
+        );
+    }
+
+    let (address, colour) = match status.address {
+        Some(address) => (address.to_string(), BLACK),
+        None => ("-.-.-.-".to_string(), RED),
+    };
+    fit_text(
+        &mut image,
+        &address,
+        margin,
+        margin + h * 0.11,
+        inner_w,
+        h * 0.17,
+        colour,
+    );
+
+    let gauges = [
+        status
+            .cpu_temp
+            .map(|temp| ((temp - TEMP_RANGE.0) / (TEMP_RANGE.1 - TEMP_RANGE.0), temp)),
+        status.memory_used.map(|used| (used, used * 100.0)),
+        status.disk_used.map(|used| (used, used * 100.0)),
+    ];
+    let row_h = h * 0.45 / gauges.len() as f32;
+    let bar_h = row_h * 0.7;
+    let label_scale = fit_label_scale("DISK", inner_w * 0.2, bar_h * 0.6);
+    let bar_x = margin + (draw::text_width("DISK", label_scale) as f32) + margin / 2.0;
+    let bar_w = inner_w * 0.7 - (bar_x - margin);
+    for (index, gauge) in gauges.iter().enumerate() {
+        let y = margin + h * 0.3 + row_h * index as f32;
+        let outline = (bar_h / 12.0).max(1.0) as i64;
+        draw::draw_text(
+            &mut image,
+            GAUGE_LABELS[index],
+            margin as i64,
+            (y + (bar_h - 7.0 * label_scale as f32) / 2.0) as i64,
+            label_scale,
+            BLACK,
+        );
+        let label = match gauge {
+            Some((fraction, value)) => {
+                let fraction = fraction.clamp(0.0, 1.0);
+                let fill = if fraction < 0.6 {
+                    GREEN
+                } else if fraction < 0.85 {
+                    YELLOW
+                } else {
+                    RED
+                };
+                draw::fill_rect(
+                    &mut image,
+                    bar_x as i64,
+                    y as i64,
+                    (bar_w * fraction) as i64,
+                    bar_h as i64,
+                    fill,
+                );
+                format!("{}", value.round() as i64)
+            }
+            None => "--".to_string(),
+        };
+        draw::stroke_rect(
+            &mut image,
+            bar_x as i64,
+            y as i64,
+            bar_w as i64,
+            bar_h as i64,
+            outline,
+            BLACK,
+        );
+        let label_x = bar_x + bar_w + margin;
+        fit_text(
+            &mut image,
+            &label,
+            label_x,
+            y,
+            w - margin - label_x,
+            bar_h,
+            BLACK,
+        );
+    }
+
+    let footer_y = h - margin - h * 0.12;
+    let uptime = match status.uptime {
+        Some(uptime) => {
+            let minutes = uptime.as_secs() / 60;
+            format!(
+                "{}-{:02}:{:02}",
+                minutes / (24 * 60),
+                minutes / 60 % 24,
+                minutes % 60
+            )
+        }
+        None => "--".to_string(),
+    };
+    fit_text(
+        &mut image,
+        &uptime,
+        margin,
+        footer_y,
+        inner_w * 0.45,
+        h * 0.12,
+        BLACK,
+    );
+    let digit_w = fit_digit_width(status.version, inner_w * 0.45, h * 0.12);
+    let version_x = w - margin - draw::segment_text_width(status.version, digit_w);
+    draw::draw_segment_text(
+        &mut image,
+        status.version,
+        version_x,
+        footer_y,
+        digit_w,
+        BLACK,
+    );
+    image
+}
+
+/// Draws `text` as large as fits in the box, anchored at its top left.
+fn fit_text(
+    image: &mut RgbImage,
+    text: &str,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    colour: Rgb<u8>,
+) {
+    let digit_w = fit_digit_width(text, width, height);
+    draw::draw_segment_text(image, text, x, y, digit_w, colour);
+}
+
+/// Largest whole [`draw::draw_text`] scale at which `text` fits the box.
+fn fit_label_scale(text: &str, width: f32, height: f32) -> i64 {
+    let by_width = width as i64 / draw::text_width(text, 1).max(1);
+    let by_height = height as i64 / 7;
+    by_width.min(by_height).max(1)
+}
+
+fn fit_digit_width(text: &str, width: f32, height: f32) -> f32 {
+    let unit = draw::segment_text_width(text, 1.0).max(1.0);
+    (width / unit).min(height / 1.8).max(1.0)
+}
+
+/// Fraction of memory in use from the contents of `/proc/meminfo`.
+fn memory_used(meminfo: &str) -> Option<f32> {
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.split_whitespace().next()?.parse::<f32>().ok())
+    };
+    let total = field("MemTotal")?;
+    let available = field("MemAvailable")?;
+    (total > 0.0).then(|| 1.0 - available / total)
+}
+
+/// Fraction of the root filesystem in use, counted like `df`: blocks
+/// reserved for root are neither used nor available.
+fn disk_used() -> Option<f32> {
+    let stats = nix::sys::statvfs::statvfs("/").ok()?;
+    let used = stats.blocks().saturating_sub(stats.blocks_free()) as f64;
+    let usable = used + stats.blocks_available() as f64;
+    (usable > 0.0).then(|| (used / usable) as f32)
+}
+
+/// The source address the kernel would pick to reach the internet. Connecting
+/// a UDP socket sends nothing, it only selects a route.
+fn local_address() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(address) => Some(address),
+        std::net::IpAddr::V6(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_memory_use_and_draws_offline_status() {
+        let meminfo =
+            "MemTotal:        1000 kB\nMemFree:          100 kB\nMemAvailable:     250 kB\n";
+        assert_eq!(memory_used(meminfo), Some(0.75));
+        assert_eq!(memory_used("MemTotal: 1000 kB\n"), None);
+
+        let status = SystemStatus {
+            memory_used: Some(0.75),
+            version: "0.3.1",
+            ..Default::default()
+        };
+        let image = render_status(600, 448, &status);
+        assert!(image.pixels().any(|pixel| *pixel == RED));
+        assert!(image.pixels().any(|pixel| *pixel == YELLOW));
+        assert!(!image.pixels().any(|pixel| *pixel == GREEN));
+
+        let named = SystemStatus {
+            hostname: Some("frame-1".to_string()),
+            ..status
+        };
+        assert_ne!(render_status(600, 448, &named), image);
+    }
+}
@@ -0,0 +1,190 @@
+//! Colour correction applied to input pixels before quantization, to
+//! compensate for pigment differences between panel batches.
+
+use std::fmt;
+
+use image::RgbImage;
+
+use crate::displays::{DisplaySpec, InkyError, Result};
+use crate::state::StateStore;
+
+const STATE_PREFIX: &str = "colour_correction";
+
+/// A 3x3 matrix applied to every RGB pixel (`out = M * in`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColourMatrix {
+    rows: [[f32; 3]; 3],
+}
+
+impl Default for ColourMatrix {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl ColourMatrix {
+    pub const IDENTITY: ColourMatrix = ColourMatrix {
+        rows: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    pub fn new(rows: [[f32; 3]; 3]) -> Self {
+        Self { rows }
+    }
+
+    pub fn rows(&self) -> [[f32; 3]; 3] {
+        self.rows
+    }
+
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        self.rows
+            .map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2])
+    }
+
+    pub fn apply_in_place(&self, image: &mut RgbImage) {
+        if *self == Self::IDENTITY {
+            return;
+        }
+        for pixel in image.pixels_mut() {
+            let corrected = self.apply(pixel.0.map(|channel| channel as f32));
+            pixel.0 = corrected.map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+        }
+    }
+
+    /// Least-squares fit of the matrix mapping each `measured` colour onto
+    /// the matching `nominal` one.
+    ///
+    /// `measured` is what the panel actually shows for each palette entry
+    /// (e.g. sampled from a photo of the colour chart) and `nominal` is the
+    /// palette the quantizer assumes. Applying the result to input pixels
+    /// moves them into the quantizer's colour space, so it picks the inks
+    /// that really look closest.
+    pub fn fit(measured: &[[f32; 3]], nominal: &[[f32; 3]]) -> Result<Self> {
+        if measured.len() != nominal.len() {
+            return Err(InkyError::InvalidCalibration(format!(
+                "expected {} measured colours, got {}",
+                nominal.len(),
+                measured.len()
+            )));
+        }
+        if measured.len() < 3 {
+            return Err(InkyError::InvalidCalibration(
+                "need at least three measured colours".to_string(),
+            ));
+        }
+
+        // Normal equations: M * sum(m m^T) = sum(p m^T).
+        let mut mm = [[0.0f64; 3]; 3];
+        let mut pm = [[0.0f64; 3]; 3];
+        for (m, p) in measured.iter().zip(nominal) {
+            for row in 0..3 {
+                for col in 0..3 {
+                    mm[row][col] += m[row] as f64 * m[col] as f64;
+                    pm[row][col] += p[row] as f64 * m[col] as f64;
+                }
+            }
+        }
+        let inverse = invert(mm).ok_or_else(|| {
+            InkyError::InvalidCalibration(
+                "measured colours are too similar to fit a matrix".to_string(),
+            )
+        })?;
+
+        let mut rows = [[0.0f32; 3]; 3];
+        for (row, out) in rows.iter_mut().enumerate() {
+            for (col, value) in out.iter_mut().enumerate() {
+                *value = (0..3).map(|k| pm[row][k] * inverse[k][col]).sum::<f64>() as f32;
+            }
+        }
+        Ok(Self { rows })
+    }
+
+    /// Loads the matrix stored for `device`, if any.
+    pub fn load(store: &StateStore, device: &str) -> Option<Self> {
+        let mut rows = [[0.0f32; 3]; 3];
+        for (row, values) in rows.iter_mut().enumerate() {
+            for (col, value) in values.iter_mut().enumerate() {
+                *value = store.get_f64(&state_key(device, row, col))? as f32;
+            }
+        }
+        Some(Self { rows })
+    }
+
+    /// Stores the matrix for `device`; call [`StateStore::save`] to persist.
+    pub fn store(&self, store: &mut StateStore, device: &str) -> crate::content::Result<()> {
+        for (row, values) in self.rows.iter().enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                // Four decimals is well below what 8-bit input can resolve and
+                // keeps the state file readable.
+                let rounded = (*value as f64 * 1e4).round() / 1e4;
+                store.set_f64(&state_key(device, row, col), rounded)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the matrix stored for `device`, returning whether one existed.
+    pub fn remove(store: &mut StateStore, device: &str) -> bool {
+        let mut removed = false;
+        for row in 0..3 {
+            for col in 0..3 {
+                removed |= store.remove(&state_key(device, row, col)).is_some();
+            }
+        }
+        removed
+    }
+}
+
+impl fmt::Display for ColourMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, row) in self.rows.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "[{:>7.4} {:>7.4} {:>7.4}]", row[0], row[1], row[2])?;
+        }
+        Ok(())
+    }
+}
+
+/// State key identifying a panel. The EEPROM carries no serial number, so
+/// matrices are stored per panel model and resolution.
+pub fn device_key(spec: Option<&DisplaySpec>) -> String {
+    match spec {
+        Some(DisplaySpec::Uc8159 { width, height, .. }) => format!("uc8159_{width}x{height}"),
+        Some(DisplaySpec::El133Uf1 { width, height }) => format!("el133uf1_{width}x{height}"),
+        None => "unknown".to_string(),
+    }
+}
+
+fn state_key(device: &str, row: usize, col: usize) -> String {
+    format!("{STATE_PREFIX}.{device}.m{row}{col}")
+}
+
+fn invert(m: [[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    if det.abs() < 1e-9 {
+        return None;
+    }
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adjugate = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+    Some(adjugate.map(|row| row.map(|value| value / det)))
+}
@@ -1,9 +1,18 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek};
 use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use gpio_cdev::{Chip, Line, LineHandle, LineRequestFlags};
 use image::imageops::{self, FilterType};
-use image::{DynamicImage, GenericImageView, ImageBuffer, RgbImage};
+use image::{
+    DynamicImage, GenericImageView, ImageBuffer, ImageDecoder, ImageFormat, ImageReader, RgbImage,
+};
 
-use super::error::Result;
+use super::error::{InkyError, Result};
+use super::icc::convert_to_srgb;
+use super::render::{RenderOptions, RenderReport};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Rotation {
@@ -31,16 +40,141 @@ impl Rotation {
     }
 }
 
+/// The SPI device and GPIO chip the HATs are wired to.
+pub(crate) const SPI_DEVICE: &str = "spidev0.0";
+pub(crate) const GPIO_CHIP: &str = "gpiochip0";
+
+pub(crate) fn dev_path(dev_root: &Path, node: &str) -> String {
+    dev_root.join(node).to_string_lossy().into_owned()
+}
+
+/// Requests `offset` from `chip`, reporting [`InkyError::LinesBusy`] when
+/// another process already holds it.
+pub(crate) fn request_line(
+    chip: &mut Chip,
+    offset: u32,
+    flags: LineRequestFlags,
+    default: u8,
+    consumer: &str,
+) -> Result<LineHandle> {
+    let line = chip.get_line(offset)?;
+    line.request(flags, default, consumer)
+        .map_err(|err| line_request_error(&line, err))
+}
+
+/// The kernel only answers a conflicting request with EBUSY, so look up the
+/// line's current owner to say who holds it.
+pub(crate) fn line_request_error(line: &Line, err: gpio_cdev::Error) -> InkyError {
+    match line.info() {
+        Ok(info) if info.is_kernel() => InkyError::LinesBusy {
+            line: line.offset(),
+            consumer: info.consumer().unwrap_or("unknown").to_string(),
+        },
+        _ => err.into(),
+    }
+}
+
+/// How long a driver waits on the busy line at each stage of an update.
+/// Each controller module provides its own `DEFAULT_TIMEOUTS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Settling after a hardware reset, before initialisation.
+    pub reset: Duration,
+    /// Power on (PON) and power off (POF).
+    pub power: Duration,
+    /// The display refresh (DRF); panels refresh slower in the cold.
+    pub refresh: Duration,
+}
+
+/// Bounds applied when decoding untrusted images, checked against the header
+/// before any pixel data is allocated.
+#[derive(Clone, Debug)]
+pub struct ImageLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+    pub max_alloc: u64,
+    pub allowed_formats: Vec<ImageFormat>,
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        Self {
+            max_width: 16_384,
+            max_height: 16_384,
+            max_pixels: 64_000_000,
+            max_alloc: 512 * 1024 * 1024,
+            allowed_formats: vec![ImageFormat::Png, ImageFormat::Jpeg],
+        }
+    }
+}
+
+impl ImageLimits {
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<()> {
+        let pixels = width as u64 * height as u64;
+        if width > self.max_width || height > self.max_height || pixels > self.max_pixels {
+            return Err(InkyError::ImageTooLarge { width, height });
+        }
+        Ok(())
+    }
+}
+
+pub fn load_image(path: &Path, limits: &ImageLimits) -> Result<DynamicImage> {
+    let file = File::open(path)?;
+    decode_image(BufReader::new(file), limits)
+}
+
+pub fn decode_image<R: BufRead + Seek>(reader: R, limits: &ImageLimits) -> Result<DynamicImage> {
+    let reader = ImageReader::new(reader).with_guessed_format()?;
+    match reader.format() {
+        Some(format) if limits.allowed_formats.contains(&format) => {}
+        Some(format) => {
+            return Err(InkyError::UnsupportedImageFormat(format!("{format:?}")));
+        }
+        None => return Err(InkyError::UnsupportedImageFormat("unknown".to_string())),
+    }
+
+    let mut decoder = reader.into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    limits.check_dimensions(width, height)?;
+
+    let mut decode_limits = image::Limits::default();
+    decode_limits.max_image_width = Some(limits.max_width);
+    decode_limits.max_image_height = Some(limits.max_height);
+    decode_limits.max_alloc = Some(limits.max_alloc);
+    decoder.set_limits(decode_limits)?;
+
+    // An unreadable profile is treated like a missing one, as sRGB.
+    let icc = decoder.icc_profile().ok().flatten();
+    let image = DynamicImage::from_decoder(decoder)?;
+    Ok(match icc {
+        Some(icc) => convert_to_srgb(image, &icc),
+        None => image,
+    })
+}
+
 pub fn clamp_aspect_resize(image: &DynamicImage, target_w: u32, target_h: u32) -> RgbImage {
     let (src_w, src_h) = image.dimensions();
     if src_w == target_w && src_h == target_h {
         return image.to_rgb8();
     }
+    if src_w == 0 || src_h == 0 {
+        return RgbImage::new(target_w, target_h);
+    }
 
+    crop_to_aspect(image, target_w, target_h)
+        .resize_exact(target_w, target_h, FilterType::Triangle)
+        .to_rgb8()
+}
+
+/// The centre of `image` cropped to the aspect ratio of `target_w` x
+/// `target_h`, at the image's own bit depth.
+pub(crate) fn crop_to_aspect(image: &DynamicImage, target_w: u32, target_h: u32) -> DynamicImage {
+    let (src_w, src_h) = image.dimensions();
     let src_ratio = src_w as f32 / src_h as f32;
     let target_ratio = target_w as f32 / target_h as f32;
 
-    let crop_image: DynamicImage = if (src_ratio - target_ratio).abs() < 1e-6 {
+    if (src_ratio - target_ratio).abs() < 1e-6 {
         image.clone()
     } else if src_ratio > target_ratio {
         let desired_width = ((target_ratio * src_h as f32).round() as u32).clamp(1, src_w);
@@ -50,11 +184,34 @@ pub fn clamp_aspect_resize(image: &DynamicImage, target_w: u32, target_h: u32) -
         let desired_height = ((src_w as f32 / target_ratio).round() as u32).clamp(1, src_h);
         let y = (src_h - desired_height) / 2;
         image.crop_imm(0, y, src_w, desired_height)
-    };
+    }
+}
 
-    crop_image
-        .resize_exact(target_w, target_h, FilterType::Triangle)
-        .to_rgb8()
+/// Rejects images with no pixels, which have no aspect ratio to fit to the
+/// panel's `expected` input dimensions.
+/// Copies `data` over a panel-native `buffer` of the same size, masking each
+/// value to the controller's three colour bits.
+pub(crate) fn copy_panel_buffer(buffer: &mut [u8], data: &[u8]) -> Result<()> {
+    if data.len() != buffer.len() {
+        return Err(InkyError::InvalidBufferSize {
+            expected: buffer.len(),
+            received: data.len(),
+        });
+    }
+    for (value, &code) in buffer.iter_mut().zip(data) {
+        *value = code & 0x07;
+    }
+    Ok(())
+}
+
+pub(crate) fn ensure_not_empty(image: &DynamicImage, expected: (u16, u16)) -> Result<()> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(InkyError::InvalidImageDimensions {
+            expected,
+            received: image.dimensions(),
+        });
+    }
+    Ok(())
 }
 
 pub fn lighten_image_in_place(image: &mut RgbImage, lighten: f32) {
@@ -96,7 +253,7 @@ pub fn pack_luma_nibbles(
 }
 
 pub fn pack_buffer_nibbles(buffer: &[u8]) -> Vec<u8> {
-    let mut packed = Vec::with_capacity((buffer.len() + 1) / 2);
+    let mut packed = Vec::with_capacity(buffer.len().div_ceil(2));
     let mut iter = buffer.iter();
     while let Some(&high) = iter.next() {
         let low = iter.next().copied().unwrap_or(0);
@@ -153,14 +310,136 @@ pub fn distribute_error(
     apply(working, (x as isize) + 1, (y as isize) + 1, 1.0 / 16.0);
 }
 
+/// 64-bit FNV-1a; stable across runs and platforms, unlike `DefaultHasher`.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// What a conditional refresh did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShowOutcome {
+    Refreshed,
+    /// The buffer matched the previous frame, so the panel was left alone.
+    NotModified,
+}
+
+/// What a panel can do, for callers that drive any [`InkyDisplay`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Palette entries the panel can show.
+    pub colours: usize,
+    /// Whether a region can be updated without a full refresh.
+    pub partial_refresh: bool,
+    /// Whether the border colour can be set separately from the frame.
+    pub border: bool,
+    /// Usual duration of one full refresh at room temperature.
+    pub refresh_time: Duration,
+    /// Shortest gap between refreshes the panel is specified for.
+    pub min_refresh_interval: Duration,
+}
+
 pub trait InkyDisplay {
     fn width(&self) -> u16;
     fn height(&self) -> u16;
     fn set_rotation(&mut self, rotation: Rotation);
     fn input_dimensions(&self) -> (u16, u16);
+    /// Error-diffusion settings for subsequent `set_image` calls.
+    fn set_dither(&mut self, dither: crate::dither::DitherOptions);
     fn clear(&mut self, colour: u8);
     fn set_pixel(&mut self, x: usize, y: usize, colour: u8);
     fn set_image_from_path(&mut self, path: &Path, saturation: f32, lighten: f32) -> Result<()>;
     fn set_image(&mut self, image: &DynamicImage, saturation: f32, lighten: f32) -> Result<()>;
+
+    /// [`set_image`](Self::set_image) with the full set of render options:
+    /// fit, tone adjustments, dithering and border as well as saturation
+    /// and lighten. Replaces the dither settings from
+    /// [`set_dither`](Self::set_dither).
+    fn set_image_with(
+        &mut self,
+        image: &DynamicImage,
+        options: &RenderOptions,
+    ) -> Result<RenderReport> {
+        ensure_not_empty(image, self.input_dimensions())?;
+        let (width, height) = self.input_dimensions();
+        let started = Instant::now();
+        let rgb = options.prepare(image, width as u32, height as u32);
+        let prepare_time = started.elapsed();
+
+        self.set_dither(options.dither);
+        if let Some(colour) = options.border {
+            self.set_border(colour);
+        }
+        let started = Instant::now();
+        let rgb = DynamicImage::ImageRgb8(rgb);
+        self.set_image(&rgb, options.saturation, 0.0)?;
+
+        let mut report = self.last_report().unwrap_or_default();
+        report.quantize_time = started.elapsed();
+        report.prepare_time = prepare_time;
+        report.measure_clipping(rgb.as_rgb8().expect("built as RGB8"));
+        Ok(report)
+    }
+
+    /// Palette usage and mean error of the image last quantized by
+    /// [`set_image`](Self::set_image), for drivers that track them.
+    fn last_report(&self) -> Option<RenderReport> {
+        None
+    }
+
+    /// Sets the border colour on panels that have one; ignored otherwise.
+    fn set_border(&mut self, _colour: u8) {}
+    /// Replaces the buffer with controller colour codes, one per pixel in
+    /// the panel's native orientation and row order, ignoring rotation.
+    fn set_panel_buffer(&mut self, data: &[u8]) -> Result<()>;
     fn show(&mut self) -> Result<()>;
+    /// Sends the buffer to the controller without refreshing the panel,
+    /// which keeps showing its current image; used to time SPI transfers.
+    fn upload(&mut self) -> Result<()>;
+    /// The quantized frame buffer, one palette value per pixel.
+    fn buffer(&self) -> &[u8];
+    fn capabilities(&self) -> Capabilities;
+
+    /// Stable hash of the quantized buffer, for detecting unchanged frames.
+    fn frame_hash(&self) -> u64 {
+        fnv1a(self.buffer())
+    }
+
+    /// Refreshes the panel unless the buffer hashes to `previous`, skipping
+    /// a full refresh cycle (around 30 seconds) when nothing changed.
+    fn show_if_changed(&mut self, previous: Option<u64>) -> Result<ShowOutcome> {
+        if previous == Some(self.frame_hash()) {
+            return Ok(ShowOutcome::NotModified);
+        }
+        self.show()?;
+        Ok(ShowOutcome::Refreshed)
+    }
+
+    /// Hardware resets the last [`show`](Self::show) needed to recover from
+    /// busy timeouts; zero when the refresh went through first time.
+    fn recoveries(&self) -> u32 {
+        0
+    }
+
+    /// Pause between consecutive full refreshes during [`flush`](Self::flush),
+    /// letting the panel and its charge pumps recover.
+    fn flush_settle_time(&self) -> Duration {
+        Duration::from_secs(2)
+    }
+
+    /// Runs `cycles` alternating black/white full refreshes to clear
+    /// ghosting, leaving the panel (and buffer) white.
+    fn flush(&mut self, cycles: u32) -> Result<()> {
+        for cycle in 0..cycles {
+            for colour in [0, 1] {
+                if cycle > 0 || colour > 0 {
+                    thread::sleep(self.flush_settle_time());
+                }
+                self.clear(colour);
+                self.show()?;
+            }
+        }
+        Ok(())
+    }
 }
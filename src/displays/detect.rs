@@ -7,8 +7,17 @@ use gpio_cdev::Chip;
 use i2cdev::core::I2CDevice;
 use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
 
+use crate::sensors::{BatteryStatus, LightSensor, find_light_sensor, read_battery};
+
+const DEV_ROOT: &str = "/dev";
+/// The I2C bus on the Pi's 40-pin header.
+const HEADER_I2C_BUS: &str = "i2c-1";
 const EEPROM_ADDRESS: u16 = 0x50;
 const EEPROM_LENGTH: usize = 29;
+/// Width, height, colour, PCB variant and display variant.
+const EEPROM_HEADER_LENGTH: usize = 7;
+/// Characters of the write time string, after its length byte.
+const WRITE_TIME_CAPACITY: usize = EEPROM_LENGTH - EEPROM_HEADER_LENGTH - 1;
 
 const DISPLAY_VARIANT_NAMES: [&str; 25] = [
     "Unknown",
@@ -45,6 +54,29 @@ pub struct EepromInfo {
     pub color: u8,
     pub pcb_variant: u8,
     pub display_variant: u8,
+    /// Set when the dump parsed but looks off in a way detection can live
+    /// with.
+    pub warning: Option<EepromWarning>,
+}
+
+/// Oddities in an otherwise usable EEPROM dump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EepromWarning {
+    /// The programming time holds a byte outside printable ASCII, e.g. NUL
+    /// padding counted in its length. The time is informational only, so the
+    /// dimensions and variant are still used.
+    NonPrintableWriteTime { byte: u8, offset: usize },
+}
+
+impl fmt::Display for EepromWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonPrintableWriteTime { byte, offset } => write!(
+                f,
+                "write time has non-printable byte 0x{byte:02x} at offset {offset}"
+            ),
+        }
+    }
 }
 
 impl fmt::Display for EepromInfo {
@@ -136,6 +168,8 @@ pub enum I2cProbeStatus {
 
 #[derive(Debug, Default)]
 pub struct ProbeInfo {
+    /// Directory the device nodes below were found in, normally `/dev`.
+    pub dev_root: PathBuf,
     pub eeprom: Option<EepromInfo>,
     pub eeprom_error: Option<String>,
     pub display: Option<DisplaySpec>,
@@ -147,39 +181,113 @@ pub struct ProbeInfo {
     pub i2c_bus_results: Vec<I2cBusReport>,
 }
 
+impl ProbeInfo {
+    /// The detected display, or the default 600x448 UC8159 when the
+    /// EEPROM was missing or unreadable.
+    pub fn display_or_default(&self) -> DisplaySpec {
+        self.display.unwrap_or(DisplaySpec::Uc8159 {
+            width: 600,
+            height: 448,
+            variant: 14,
+        })
+    }
+
+    /// Reads a UPS battery gauge on the HAT's I2C bus: the bus the display's
+    /// EEPROM answered on, or the header bus without one. Like the light
+    /// sensor, this writes to the gauge addresses, so it is not part of
+    /// probing.
+    pub fn find_battery(&self, backend: &impl DeviceBackend) -> Option<BatteryStatus> {
+        let bus = self.eeprom_bus.clone().or_else(|| {
+            let bus = self.dev_root.join(HEADER_I2C_BUS);
+            self.i2c_buses.contains(&bus).then_some(bus)
+        })?;
+        backend.battery(&bus)
+    }
+
+    /// Looks for an ambient light sensor on the probed I2C buses. This is
+    /// not part of probing because it writes to the sensor addresses on
+    /// every bus; only call it when the sensor will be read.
+    pub fn find_light_sensor(&self, backend: &impl DeviceBackend) -> Option<LightSensor> {
+        self.i2c_buses
+            .iter()
+            .find_map(|bus| backend.light_sensor(bus))
+    }
+}
+
+/// How probing talks to the device nodes it finds: [`LinuxDevices`] uses
+/// the kernel interfaces, [`mock::MockDevices`] plays back canned data.
+pub trait DeviceBackend {
+    fn read_eeprom(&self, bus: &Path) -> I2cProbeStatus;
+    /// The chip's `name (label)`, if it can be opened.
+    fn gpio_chip_label(&self, chip: &Path) -> Option<String>;
+    fn battery(&self, bus: &Path) -> Option<BatteryStatus>;
+    fn light_sensor(&self, bus: &Path) -> Option<LightSensor>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LinuxDevices;
+
+impl DeviceBackend for LinuxDevices {
+    fn read_eeprom(&self, bus: &Path) -> I2cProbeStatus {
+        read_eeprom(bus)
+    }
+
+    fn gpio_chip_label(&self, chip: &Path) -> Option<String> {
+        let chip = Chip::new(chip.to_string_lossy().as_ref()).ok()?;
+        Some(format!("{} ({})", chip.name(), chip.label()))
+    }
+
+    fn battery(&self, bus: &Path) -> Option<BatteryStatus> {
+        read_battery(bus)
+    }
+
+    fn light_sensor(&self, bus: &Path) -> Option<LightSensor> {
+        find_light_sensor(bus)
+    }
+}
+
 pub fn probe_system() -> ProbeInfo {
-    let mut info = ProbeInfo::default();
+    probe_system_at(Path::new(DEV_ROOT), &LinuxDevices)
+}
+
+/// Probes the device nodes under `dev_root` through `backend`.
+pub fn probe_system_at(dev_root: &Path, backend: &impl DeviceBackend) -> ProbeInfo {
+    let mut info = ProbeInfo {
+        dev_root: dev_root.to_path_buf(),
+        ..ProbeInfo::default()
+    };
 
-    info.spi_devices = list_matching("/dev", "spidev");
-    info.gpio_chips = list_matching("/dev", "gpiochip");
-    info.i2c_buses = list_matching("/dev", "i2c-");
-    info.gpio_chip_labels = list_gpio_chip_labels(&info.gpio_chips);
+    info.spi_devices = list_matching(dev_root, "spidev");
+    info.gpio_chips = list_matching(dev_root, "gpiochip");
+    info.i2c_buses = list_matching(dev_root, "i2c-");
+    info.gpio_chip_labels = info
+        .gpio_chips
+        .iter()
+        .filter_map(|path| {
+            let label = backend.gpio_chip_label(path)?;
+            Some(format!("{} -> {label}", path.display()))
+        })
+        .collect();
 
     for bus in &info.i2c_buses {
-        let status = read_eeprom(bus);
+        let status = backend.read_eeprom(bus);
         info.i2c_bus_results.push(I2cBusReport {
             path: bus.clone(),
             status: status.clone(),
         });
 
         match status {
-            I2cProbeStatus::Found(eeprom) => {
-                if info.eeprom.is_none() {
-                    info.display = eeprom.display_spec();
-                    info.eeprom = Some(eeprom);
-                    info.eeprom_bus = Some(bus.clone());
-                    info.eeprom_error = None;
-                }
+            I2cProbeStatus::Found(eeprom) if info.eeprom.is_none() => {
+                info.display = eeprom.display_spec();
+                info.eeprom = Some(eeprom);
+                info.eeprom_bus = Some(bus.clone());
+                info.eeprom_error = None;
             }
-            I2cProbeStatus::Invalid(reason) => {
-                if info.eeprom_error.is_none() {
-                    info.eeprom_error = Some(format!("invalid data: {reason}"));
-                }
+            I2cProbeStatus::Invalid(reason) if info.eeprom_error.is_none() => {
+                info.eeprom_error = Some(format!("invalid data: {reason}"));
             }
-            I2cProbeStatus::Error(err) => {
-                if info.eeprom_error.is_none() {
-                    info.eeprom_error = Some(err);
-                }
+            I2cProbeStatus::Error(err) if info.eeprom_error.is_none() => {
+                info.eeprom_error = Some(err);
             }
             _ => {}
         }
@@ -204,22 +312,32 @@ pub fn read_eeprom<P: AsRef<Path>>(path: P) -> I2cProbeStatus {
         return map_i2c_error(err);
     }
 
-    if is_blank_eeprom(&buf) {
+    eeprom_status(&buf)
+}
+
+fn eeprom_status(data: &[u8]) -> I2cProbeStatus {
+    if is_blank_eeprom(data) {
         return I2cProbeStatus::Blank;
     }
 
-    match parse_eeprom(&buf) {
+    match parse_eeprom(data) {
         Ok(parsed) => I2cProbeStatus::Found(parsed),
         Err(reason) => I2cProbeStatus::Invalid(reason),
     }
 }
 
+/// Parses the Pimoroni EEPROM layout: width and height (u16, little
+/// endian), colour, PCB variant and display variant (u8 each), then the
+/// programming time as a Pascal string in the remaining 22 bytes.
 fn parse_eeprom(data: &[u8]) -> Result<EepromInfo, String> {
-    let width = u16::from_le_bytes([data[0], data[1]]);
-    let height = u16::from_le_bytes([data[2], data[3]]);
-    let color = data[4];
-    let pcb_variant = data[5];
-    let display_variant = data[6];
+    let mut reader = EepromReader { data, offset: 0 };
+    let width = reader.u16("width")?;
+    let height = reader.u16("height")?;
+    let color = reader.u8("colour")?;
+    let pcb_variant = reader.u8("pcb variant")?;
+    let display_variant = reader.u8("display variant")?;
+    let write_time_len = reader.u8("write time length")? as usize;
+    let write_time = reader.bytes("write time", WRITE_TIME_CAPACITY)?;
 
     if width == 0 || height == 0 || width == u16::MAX || height == u16::MAX {
         return Err(format!(
@@ -231,24 +349,68 @@ fn parse_eeprom(data: &[u8]) -> Result<EepromInfo, String> {
         return Err("display variant invalid (255)".to_string());
     }
 
+    if write_time_len > WRITE_TIME_CAPACITY {
+        return Err(format!(
+            "write time length {write_time_len} exceeds {WRITE_TIME_CAPACITY} bytes"
+        ));
+    }
+
+    let warning = write_time[..write_time_len]
+        .iter()
+        .position(|byte| !byte.is_ascii_graphic() && *byte != b' ')
+        .map(|offset| EepromWarning::NonPrintableWriteTime {
+            byte: write_time[offset],
+            offset: EEPROM_HEADER_LENGTH + 1 + offset,
+        });
+
     Ok(EepromInfo {
         width,
         height,
         color,
         pcb_variant,
         display_variant,
+        warning,
     })
 }
 
-fn list_matching(dir: &str, prefix: &str) -> Vec<PathBuf> {
+/// Length-checked cursor over an EEPROM dump.
+struct EepromReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> EepromReader<'a> {
+    fn bytes(&mut self, field: &str, len: usize) -> Result<&'a [u8], String> {
+        let end = self.offset + len;
+        let bytes = self.data.get(self.offset..end).ok_or_else(|| {
+            format!(
+                "truncated at {field}: need {end} bytes, got {}",
+                self.data.len()
+            )
+        })?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self, field: &str) -> Result<u8, String> {
+        Ok(self.bytes(field, 1)?[0])
+    }
+
+    fn u16(&mut self, field: &str) -> Result<u16, String> {
+        let bytes = self.bytes(field, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+fn list_matching(dir: &Path, prefix: &str) -> Vec<PathBuf> {
     let mut entries = Vec::new();
     if let Ok(read_dir) = fs::read_dir(dir) {
         for entry in read_dir.flatten() {
             let path = entry.path();
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                if name.starts_with(prefix) {
-                    entries.push(path);
-                }
+            if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                && name.starts_with(prefix)
+            {
+                entries.push(path);
             }
         }
     }
@@ -256,21 +418,6 @@ fn list_matching(dir: &str, prefix: &str) -> Vec<PathBuf> {
     entries
 }
 
-fn list_gpio_chip_labels(chips: &[PathBuf]) -> Vec<String> {
-    let mut labels = Vec::new();
-    for path in chips {
-        if let Ok(chip) = Chip::new(path.to_string_lossy().as_ref()) {
-            labels.push(format!(
-                "{} -> {} ({})",
-                path.display(),
-                chip.name(),
-                chip.label()
-            ));
-        }
-    }
-    labels
-}
-
 fn map_i2c_error(err: LinuxI2CError) -> I2cProbeStatus {
     match err {
         LinuxI2CError::Io(io_err) => handle_io_error(io_err),
@@ -310,3 +457,279 @@ fn handle_errno(code: i32) -> I2cProbeStatus {
 fn is_blank_eeprom(data: &[u8]) -> bool {
     data.iter().all(|&b| b == 0xFF || b == 0x00)
 }
+
+/// A [`DeviceBackend`] answering from canned data, keyed by device node
+/// name (`i2c-1`, `gpiochip0`); pair it with a directory of placeholder
+/// nodes and [`probe_system_at`].
+pub mod mock {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use super::{BatteryStatus, DeviceBackend, I2cProbeStatus, LightSensor, eeprom_status};
+
+    #[derive(Clone, Debug, Default)]
+    pub struct MockDevices {
+        eeproms: HashMap<String, I2cProbeStatus>,
+        gpio_labels: HashMap<String, String>,
+        batteries: HashMap<String, BatteryStatus>,
+        light_sensors: HashMap<String, LightSensor>,
+    }
+
+    impl MockDevices {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Answers EEPROM reads on `bus` with `data`, which goes through the
+        /// same blank check and parser as a real read.
+        pub fn with_eeprom(mut self, bus: &str, data: &[u8]) -> Self {
+            self.eeproms.insert(bus.to_string(), eeprom_status(data));
+            self
+        }
+
+        /// Fails EEPROM reads on `bus`, e.g. with a permission error.
+        pub fn with_eeprom_error(mut self, bus: &str, err: &str) -> Self {
+            self.eeproms
+                .insert(bus.to_string(), I2cProbeStatus::Error(err.to_string()));
+            self
+        }
+
+        pub fn with_gpio_label(mut self, chip: &str, label: &str) -> Self {
+            self.gpio_labels.insert(chip.to_string(), label.to_string());
+            self
+        }
+
+        pub fn with_battery(mut self, bus: &str, battery: BatteryStatus) -> Self {
+            self.batteries.insert(bus.to_string(), battery);
+            self
+        }
+
+        pub fn with_light_sensor(mut self, bus: &str, sensor: LightSensor) -> Self {
+            self.light_sensors.insert(bus.to_string(), sensor);
+            self
+        }
+    }
+
+    fn node_name(path: &Path) -> String {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    impl DeviceBackend for MockDevices {
+        /// Buses without canned data have nothing at the EEPROM address.
+        fn read_eeprom(&self, bus: &Path) -> I2cProbeStatus {
+            self.eeproms
+                .get(&node_name(bus))
+                .cloned()
+                .unwrap_or(I2cProbeStatus::Unavailable)
+        }
+
+        fn gpio_chip_label(&self, chip: &Path) -> Option<String> {
+            self.gpio_labels.get(&node_name(chip)).cloned()
+        }
+
+        fn battery(&self, bus: &Path) -> Option<BatteryStatus> {
+            self.batteries.get(&node_name(bus)).cloned()
+        }
+
+        fn light_sensor(&self, bus: &Path) -> Option<LightSensor> {
+            self.light_sensors.get(&node_name(bus)).cloned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dumps laid out the way the Pimoroni programming script writes them.
+    fn dump(header: [u8; EEPROM_HEADER_LENGTH], write_time: &str) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.push(write_time.len() as u8);
+        data.extend_from_slice(write_time.as_bytes());
+        data.resize(EEPROM_LENGTH, 0);
+        data
+    }
+
+    fn impression_57() -> Vec<u8> {
+        dump([0x58, 0x02, 0xc0, 0x01, 5, 12, 14], "2021-06-08 14:02:51.3")
+    }
+
+    fn impression_4() -> Vec<u8> {
+        dump([0x80, 0x02, 0x90, 0x01, 5, 12, 16], "2022-01-19 09:47:10.9")
+    }
+
+    fn impression_133() -> Vec<u8> {
+        dump([0x40, 0x06, 0xb0, 0x04, 7, 10, 21], "2024-11-27 16:20:05.5")
+    }
+
+    /// Width, height, colour, PCB variant and display variant.
+    type Header = (u16, u16, u8, u8, u8);
+
+    /// Raw 29-byte reads kept as binary files, so the parser sees exactly
+    /// what `read_eeprom` gets back from the bus.
+    const RAW_DUMPS: [(&str, &[u8], Header); 4] = [
+        (
+            "5.7in UC8159",
+            include_bytes!("../../tests/fixtures/eeprom/impression_5_7.bin"),
+            (600, 448, 5, 12, 14),
+        ),
+        (
+            "4in UC8159",
+            include_bytes!("../../tests/fixtures/eeprom/impression_4.bin"),
+            (640, 400, 5, 12, 16),
+        ),
+        (
+            "7.3in AC073TC1A, erased tail",
+            include_bytes!("../../tests/fixtures/eeprom/impression_7_3.bin"),
+            (800, 480, 5, 10, 20),
+        ),
+        (
+            "13.3in Spectra, NUL-padded write time",
+            include_bytes!("../../tests/fixtures/eeprom/impression_13_3.bin"),
+            (1600, 1200, 7, 10, 21),
+        ),
+    ];
+
+    fn with(mut data: Vec<u8>, offset: usize, byte: u8) -> Vec<u8> {
+        data[offset] = byte;
+        data
+    }
+
+    #[test]
+    fn parses_valid_dumps() {
+        let cases = [
+            ("5.7in UC8159", impression_57(), (600, 448, 5, 12, 14)),
+            ("4in UC8159", impression_4(), (640, 400, 5, 12, 16)),
+            ("13.3in Spectra", impression_133(), (1600, 1200, 7, 10, 21)),
+            (
+                "no write time",
+                dump([0x58, 0x02, 0xc0, 0x01, 5, 12, 14], ""),
+                (600, 448, 5, 12, 14),
+            ),
+            (
+                "unknown variant",
+                dump([0x20, 0x03, 0xe0, 0x01, 6, 10, 40], "2025-02-02 10:00:00.0"),
+                (800, 480, 6, 10, 40),
+            ),
+        ];
+        for (name, data, (width, height, color, pcb_variant, display_variant)) in cases {
+            let info = parse_eeprom(&data).unwrap_or_else(|err| panic!("{name}: {err}"));
+            assert_eq!(
+                (
+                    info.width,
+                    info.height,
+                    info.color,
+                    info.pcb_variant,
+                    info.display_variant
+                ),
+                (width, height, color, pcb_variant, display_variant),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_raw_dumps() {
+        for (name, data, (width, height, color, pcb_variant, display_variant)) in RAW_DUMPS {
+            assert_eq!(data.len(), EEPROM_LENGTH, "{name}");
+            let I2cProbeStatus::Found(info) = eeprom_status(data) else {
+                panic!("{name}: not detected");
+            };
+            assert_eq!(
+                (
+                    info.width,
+                    info.height,
+                    info.color,
+                    info.pcb_variant,
+                    info.display_variant
+                ),
+                (width, height, color, pcb_variant, display_variant),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn odd_write_times_only_warn() {
+        let (_, padded, _) = RAW_DUMPS[3];
+        let info = parse_eeprom(padded).unwrap();
+        assert_eq!(
+            info.warning,
+            Some(EepromWarning::NonPrintableWriteTime {
+                byte: 0x00,
+                offset: 24
+            })
+        );
+        assert!(matches!(
+            info.display_spec(),
+            Some(DisplaySpec::El133Uf1 {
+                width: 1600,
+                height: 1200
+            })
+        ));
+
+        let info = parse_eeprom(&with(impression_133(), 12, 0x07)).unwrap();
+        assert_eq!(
+            info.warning.map(|warning| warning.to_string()).as_deref(),
+            Some("write time has non-printable byte 0x07 at offset 12")
+        );
+        assert!(parse_eeprom(&impression_57()).unwrap().warning.is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_dumps() {
+        let cases = [
+            (
+                "empty",
+                Vec::new(),
+                "truncated at width: need 2 bytes, got 0",
+            ),
+            ("one byte", vec![0x58], "truncated at width"),
+            (
+                "header only",
+                impression_57()[..EEPROM_HEADER_LENGTH].to_vec(),
+                "truncated at write time length: need 8 bytes, got 7",
+            ),
+            (
+                "short write time",
+                impression_57()[..20].to_vec(),
+                "truncated at write time: need 29 bytes, got 20",
+            ),
+            (
+                "zero width",
+                with(with(impression_57(), 0, 0x00), 1, 0x00),
+                "width/height out of range (width=0, height=448)",
+            ),
+            (
+                "erased height",
+                with(with(impression_57(), 2, 0xff), 3, 0xff),
+                "width/height out of range (width=600, height=65535)",
+            ),
+            (
+                "variant 255",
+                with(impression_57(), 6, 0xff),
+                "display variant invalid (255)",
+            ),
+            (
+                "write time length",
+                with(impression_57(), 7, 30),
+                "write time length 30 exceeds 21 bytes",
+            ),
+        ];
+        for (name, data, reason) in cases {
+            match parse_eeprom(&data) {
+                Ok(info) => panic!("{name}: parsed as {info}"),
+                Err(err) => assert!(err.contains(reason), "{name}: {err}"),
+            }
+        }
+    }
+
+    #[test]
+    fn blank_dumps_are_not_parsed() {
+        assert!(is_blank_eeprom(&[0xff; EEPROM_LENGTH]));
+        assert!(is_blank_eeprom(&[0x00; EEPROM_LENGTH]));
+        assert!(!is_blank_eeprom(&impression_57()));
+    }
+}
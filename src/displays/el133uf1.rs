@@ -1,6 +1,4 @@
-use std::io::Write;
 use std::path::Path;
-use std::thread;
 use std::time::{Duration, Instant};
 
 use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
@@ -9,10 +7,14 @@ use image::{DynamicImage, GenericImageView, ImageBuffer, RgbImage};
 use spidev::{SpiModeFlags, Spidev, SpidevOptions};
 
 use super::common::{
-    InkyDisplay, Rotation, clamp_aspect_resize, distribute_error, lighten_image_in_place,
-    nearest_colour, pack_luma_nibbles,
+    Capabilities, GPIO_CHIP, ImageLimits, InkyDisplay, Rotation, SPI_DEVICE, Timeouts,
+    clamp_aspect_resize, copy_panel_buffer, dev_path, ensure_not_empty, lighten_image_in_place,
+    load_image, pack_luma_nibbles, request_line,
 };
 use super::error::{InkyError, Result};
+use super::hal::{Delay, InputPin, OutputPin, SpiBus, StdDelay};
+use super::render::RenderReport;
+use crate::dither::{DitherOptions, dither_with_stats};
 
 const RESET_PIN_DEFAULT: u32 = 27;
 const BUSY_PIN_DEFAULT: u32 = 17;
@@ -50,6 +52,24 @@ const EL133UF1_TFT_VCOM_POWER: u8 = 0xB1;
 
 const SPI_CHUNK_SIZE: usize = 4096;
 
+pub const DEFAULT_TIMEOUTS: Timeouts = Timeouts {
+    reset: Duration::from_millis(300),
+    power: Duration::from_millis(200),
+    refresh: Duration::from_secs(32),
+};
+
+/// What the Spectra 6 panel supports, as reported by [`InkyDisplay::capabilities`].
+pub const CAPABILITIES: Capabilities = Capabilities {
+    colours: 6,
+    partial_refresh: false,
+    border: false,
+    refresh_time: Duration::from_secs(40),
+    min_refresh_interval: Duration::from_secs(180),
+};
+
+/// Names of the panel colours, in palette order (before `REMAP`).
+pub const COLOUR_NAMES: [&str; 6] = ["black", "white", "yellow", "red", "blue", "green"];
+
 const DESATURATED_PALETTE: [[u8; 3]; 6] = [
     [0, 0, 0],
     [255, 255, 255],
@@ -70,7 +90,8 @@ const SATURATED_PALETTE: [[u8; 3]; 6] = [
     [18, 95, 32],    // Green
 ];
 
-const REMAP: [u8; 6] = [0, 1, 2, 3, 5, 6];
+/// Buffer value the controller expects for each palette entry.
+pub const REMAP: [u8; 6] = [0, 1, 2, 3, 5, 6];
 
 pub struct SpectraPins {
     pub cs0: u32,
@@ -99,6 +120,12 @@ pub struct InkyEl133Uf1Config {
     pub gpio_chip: String,
     pub pins: SpectraPins,
     pub rotation: Rotation,
+    pub dither: DitherOptions,
+    /// Times to hardware-reset, re-initialise and resend a frame after the
+    /// busy line times out during a refresh. Long ribbon cables make
+    /// transient busy glitches common; 0 fails on the first timeout.
+    pub busy_retries: u32,
+    pub timeouts: Timeouts,
 }
 
 impl Default for InkyEl133Uf1Config {
@@ -110,45 +137,93 @@ impl Default for InkyEl133Uf1Config {
             gpio_chip: "/dev/gpiochip0".to_string(),
             pins: SpectraPins::default(),
             rotation: Rotation::Deg0,
+            dither: DitherOptions::default(),
+            busy_retries: 1,
+            timeouts: DEFAULT_TIMEOUTS,
         }
     }
 }
 
-pub struct InkyEl133Uf1 {
-    spi: Spidev,
-    cs0: LineHandle,
-    cs1: LineHandle,
-    dc: LineHandle,
-    reset: LineHandle,
-    busy: LineHandle,
+impl InkyEl133Uf1Config {
+    /// Points the SPI device and GPIO chip at `dev_root` instead of `/dev`,
+    /// e.g. the root a [`ProbeInfo`](super::ProbeInfo) was taken from.
+    pub fn with_dev_root(mut self, dev_root: &Path) -> Self {
+        self.spi_path = dev_path(dev_root, SPI_DEVICE);
+        self.gpio_chip = dev_path(dev_root, GPIO_CHIP);
+        self
+    }
+}
+
+/// The buses an EL133UF1 is driven through; see [`InkyEl133Uf1::from_hal`].
+pub struct El133Uf1Hal<S, O, I, D> {
+    pub spi: S,
+    pub cs0: O,
+    pub cs1: O,
+    pub dc: O,
+    pub reset: O,
+    pub busy: I,
+    pub delay: D,
+}
+
+pub struct InkyEl133Uf1<S = Spidev, O = LineHandle, I = LineHandle, D = StdDelay> {
+    spi: S,
+    cs0: O,
+    cs1: O,
+    dc: O,
+    reset: O,
+    busy: I,
+    delay: D,
     width: u16,
     height: u16,
     rotation: Rotation,
+    dither: DitherOptions,
+    report: Option<RenderReport>,
     buffer: Vec<u8>,
     initialised: bool,
+    busy_retries: u32,
+    recoveries: u32,
+    timeouts: Timeouts,
 }
 
 impl InkyEl133Uf1 {
     pub fn new(config: InkyEl133Uf1Config) -> Result<Self> {
         let mut chip = Chip::new(&config.gpio_chip)?;
 
-        let cs0 = chip
-            .get_line(config.pins.cs0)?
-            .request(LineRequestFlags::OUTPUT, 1, "paperwave-cs0")?;
-        let cs1 = chip
-            .get_line(config.pins.cs1)?
-            .request(LineRequestFlags::OUTPUT, 1, "paperwave-cs1")?;
-        let dc = chip
-            .get_line(config.pins.dc)?
-            .request(LineRequestFlags::OUTPUT, 0, "paperwave-dc")?;
-        let reset = chip.get_line(config.pins.reset)?.request(
+        let cs0 = request_line(
+            &mut chip,
+            config.pins.cs0,
+            LineRequestFlags::OUTPUT,
+            1,
+            "paperwave-cs0",
+        )?;
+        let cs1 = request_line(
+            &mut chip,
+            config.pins.cs1,
+            LineRequestFlags::OUTPUT,
+            1,
+            "paperwave-cs1",
+        )?;
+        let dc = request_line(
+            &mut chip,
+            config.pins.dc,
+            LineRequestFlags::OUTPUT,
+            0,
+            "paperwave-dc",
+        )?;
+        let reset = request_line(
+            &mut chip,
+            config.pins.reset,
             LineRequestFlags::OUTPUT,
             1,
             "paperwave-reset",
         )?;
-        let busy =
-            chip.get_line(config.pins.busy)?
-                .request(LineRequestFlags::INPUT, 1, "paperwave-busy")?;
+        let busy = request_line(
+            &mut chip,
+            config.pins.busy,
+            LineRequestFlags::INPUT,
+            1,
+            "paperwave-busy",
+        )?;
 
         drop(chip);
 
@@ -160,20 +235,53 @@ impl InkyEl133Uf1 {
             .build();
         spi.configure(&options)?;
 
+        Self::from_hal(
+            config,
+            El133Uf1Hal {
+                spi,
+                cs0,
+                cs1,
+                dc,
+                reset,
+                busy,
+                delay: StdDelay,
+            },
+        )
+    }
+}
+
+impl<S: SpiBus, O: OutputPin, I: InputPin, D: Delay> InkyEl133Uf1<S, O, I, D> {
+    /// Builds a driver over any bus implementation, e.g. the
+    /// [`mock`](super::hal::mock) ones. Nothing is sent until the first
+    /// refresh.
+    pub fn from_hal(config: InkyEl133Uf1Config, hal: El133Uf1Hal<S, O, I, D>) -> Result<Self> {
+        if config.width == 0 || config.height == 0 {
+            return Err(InkyError::UnsupportedResolution(
+                config.width,
+                config.height,
+            ));
+        }
+
         let buffer = vec![0; (config.width as usize) * (config.height as usize)];
 
         Ok(Self {
-            spi,
-            cs0,
-            cs1,
-            dc,
-            reset,
-            busy,
+            spi: hal.spi,
+            cs0: hal.cs0,
+            cs1: hal.cs1,
+            dc: hal.dc,
+            reset: hal.reset,
+            busy: hal.busy,
+            delay: hal.delay,
             width: config.width,
             height: config.height,
             rotation: config.rotation,
+            dither: config.dither,
+            report: None,
             buffer,
             initialised: false,
+            busy_retries: config.busy_retries,
+            recoveries: 0,
+            timeouts: config.timeouts,
         })
     }
 
@@ -192,38 +300,34 @@ impl InkyEl133Uf1 {
     }
 
     fn quantize_into_buffer(&mut self, rgb: &RgbImage, palette: &[[f32; 3]; 6]) {
-        let width = self.width as usize;
-        let height = self.height as usize;
-        let mut working: Vec<[f32; 3]> = rgb
-            .pixels()
-            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
-            .collect();
-
-        for y in 0..height {
-            for x in 0..width {
-                let idx = y * width + x;
-                let old_pixel = working[idx];
-                let (closest_index, closest_colour) = nearest_colour(palette, old_pixel);
-                self.buffer[idx] = REMAP[closest_index as usize];
-
-                let error = [
-                    old_pixel[0] - closest_colour[0],
-                    old_pixel[1] - closest_colour[1],
-                    old_pixel[2] - closest_colour[2],
-                ];
-
-                distribute_error(&mut working, width, height, x, y, error);
-            }
+        let (indices, stats) = dither_with_stats(rgb, palette, self.dither);
+        self.report = Some(RenderReport::from_dither(&COLOUR_NAMES, palette, &stats));
+        for (value, index) in self.buffer.iter_mut().zip(indices) {
+            *value = REMAP[index as usize];
         }
     }
 
-    fn initialise(&mut self) -> Result<()> {
+    /// Resets and powers off a controller that never went idle, so it is not
+    /// left driving the panel and the next show starts from a clean
+    /// initialise instead of waiting out another timeout.
+    fn abandon_refresh(&mut self) {
+        self.initialised = false;
+        let _ = self.hardware_reset();
+        let _ = self.send_command(EL133UF1_POF, CS_BOTH_SEL, &[0x00]);
+    }
+
+    fn hardware_reset(&mut self) -> Result<()> {
         self.reset.set_value(0)?;
-        thread::sleep(Duration::from_millis(30));
+        self.delay.delay(Duration::from_millis(30));
         self.reset.set_value(1)?;
-        thread::sleep(Duration::from_millis(30));
+        self.delay.delay(Duration::from_millis(30));
+        Ok(())
+    }
+
+    fn initialise(&mut self) -> Result<()> {
+        self.hardware_reset()?;
 
-        self.busy_wait(Duration::from_millis(300)).ok();
+        self.busy_wait(self.timeouts.reset).ok();
 
         self.send_command(
             EL133UF1_ANTM,
@@ -255,35 +359,84 @@ impl InkyEl133Uf1 {
         Ok(())
     }
 
+    /// Sends a frame, re-initialising (which resets the controller) and
+    /// retrying up to `busy_retries` times when the busy line times out.
+    fn send_frame_with_recovery(&mut self, buf_a: &[u8], buf_b: &[u8]) -> Result<()> {
+        self.recoveries = 0;
+        loop {
+            if !self.initialised {
+                self.initialise()?;
+                self.initialised = true;
+            }
+            match self.send_frame(buf_a, buf_b) {
+                Err(InkyError::Timeout(..)) if self.recoveries < self.busy_retries => {
+                    self.initialised = false;
+                    self.recoveries += 1;
+                }
+                Err(err @ InkyError::Timeout(..)) => {
+                    self.abandon_refresh();
+                    return Err(err);
+                }
+                result => return result,
+            }
+        }
+    }
+
     fn send_frame(&mut self, buf_a: &[u8], buf_b: &[u8]) -> Result<()> {
-        self.send_command(EL133UF1_DTM, CS0_SEL, buf_a)?;
-        self.send_command(EL133UF1_DTM, CS1_SEL, buf_b)?;
+        self.send_data(buf_a, buf_b)?;
 
         self.send_command(EL133UF1_PON, CS_BOTH_SEL, &[])?;
-        self.busy_wait(Duration::from_millis(200)).ok();
+        self.busy_wait(self.timeouts.power).ok();
 
         self.send_command(EL133UF1_DRF, CS_BOTH_SEL, &[0x00])?;
-        self.busy_wait(Duration::from_secs(32))?;
+        self.busy_wait(self.timeouts.refresh)?;
 
         self.send_command(EL133UF1_POF, CS_BOTH_SEL, &[0x00])?;
-        self.busy_wait(Duration::from_millis(200)).ok();
+        self.busy_wait(self.timeouts.power).ok();
 
         Ok(())
     }
 
+    /// Writes each half of the frame to its controller.
+    fn send_data(&mut self, buf_a: &[u8], buf_b: &[u8]) -> Result<()> {
+        self.send_command(EL133UF1_DTM, CS0_SEL, buf_a)?;
+        self.send_command(EL133UF1_DTM, CS1_SEL, buf_b)
+    }
+
+    /// The buffer packed for the two controllers, left half first.
+    fn packed_halves(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let expected = self.width as usize * self.height as usize;
+        let received = self.buffer.len();
+        let mut image = ImageBuffer::<image::Luma<u8>, _>::from_raw(
+            self.width as u32,
+            self.height as u32,
+            self.buffer.clone(),
+        )
+        .ok_or(InkyError::InvalidBufferSize { expected, received })?;
+
+        image = imageops::rotate270(&image);
+        let width = image.width() as usize;
+        let split = width / 2;
+
+        Ok((
+            pack_luma_nibbles(&image, 0, split),
+            pack_luma_nibbles(&image, split, width),
+        ))
+    }
+
     fn busy_wait(&mut self, timeout: Duration) -> Result<()> {
         let start = Instant::now();
         // Fallback behavior: if BUSY reads high, assume no signal and sleep out the timeout
         let busy_val = self.busy.get_value()?;
         if busy_val != 0 {
-            thread::sleep(timeout);
+            self.delay.delay(timeout);
             return Ok(());
         }
         while start.elapsed() < timeout {
             if self.busy.get_value()? == 0 {
                 return Ok(());
             }
-            thread::sleep(Duration::from_millis(10));
+            self.delay.delay(Duration::from_millis(10));
         }
         Err(InkyError::Timeout("busy", timeout))
     }
@@ -298,7 +451,7 @@ impl InkyEl133Uf1 {
 
         self.dc.set_value(0)?;
         // Match Python driver behavior: settle before command
-        thread::sleep(Duration::from_millis(300));
+        self.delay.delay(Duration::from_millis(300));
         self.spi.write(&[command])?;
 
         if !data.is_empty() {
@@ -334,7 +487,8 @@ impl InkyEl133Uf1 {
     }
 }
 
-fn blend_palette(saturation: f32) -> [[f32; 3]; 6] {
+/// The palette images are quantized against at `saturation`.
+pub fn blend_palette(saturation: f32) -> [[f32; 3]; 6] {
     let sat = saturation.clamp(0.0, 1.0);
     let mut palette = [[0.0f32; 3]; 6];
     for i in 0..6 {
@@ -347,7 +501,7 @@ fn blend_palette(saturation: f32) -> [[f32; 3]; 6] {
     palette
 }
 
-impl InkyDisplay for InkyEl133Uf1 {
+impl<S: SpiBus, O: OutputPin, I: InputPin, D: Delay> InkyDisplay for InkyEl133Uf1<S, O, I, D> {
     fn width(&self) -> u16 {
         self.width
     }
@@ -364,6 +518,26 @@ impl InkyDisplay for InkyEl133Uf1 {
         self.rotation.target_dimensions(self.width, self.height)
     }
 
+    fn set_dither(&mut self, dither: DitherOptions) {
+        self.dither = dither;
+    }
+
+    fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
+
+    fn last_report(&self) -> Option<RenderReport> {
+        self.report.clone()
+    }
+
+    fn set_panel_buffer(&mut self, data: &[u8]) -> Result<()> {
+        copy_panel_buffer(&mut self.buffer, data)
+    }
+
     fn clear(&mut self, colour: u8) {
         self.buffer.fill(colour & 0x07);
     }
@@ -378,11 +552,12 @@ impl InkyDisplay for InkyEl133Uf1 {
     }
 
     fn set_image_from_path(&mut self, path: &Path, saturation: f32, lighten: f32) -> Result<()> {
-        let image = image::open(path)?;
+        let image = load_image(path, &ImageLimits::default())?;
         self.set_image(&image, saturation, lighten)
     }
 
     fn set_image(&mut self, image: &DynamicImage, saturation: f32, lighten: f32) -> Result<()> {
+        ensure_not_empty(image, self.input_dimensions())?;
         let mut rgb = self.prepare_image(image);
         lighten_image_in_place(&mut rgb, lighten);
         let palette = blend_palette(saturation);
@@ -390,27 +565,207 @@ impl InkyDisplay for InkyEl133Uf1 {
         Ok(())
     }
 
+    /// The 13.3" panel draws considerably more current per refresh, so
+    /// give it longer to recover between flush passes.
+    fn flush_settle_time(&self) -> Duration {
+        Duration::from_secs(5)
+    }
+
     fn show(&mut self) -> Result<()> {
+        let (buf_a, buf_b) = self.packed_halves()?;
+        self.send_frame_with_recovery(&buf_a, &buf_b)
+    }
+
+    fn upload(&mut self) -> Result<()> {
+        let (buf_a, buf_b) = self.packed_halves()?;
         if !self.initialised {
             self.initialise()?;
             self.initialised = true;
         }
+        self.send_data(&buf_a, &buf_b)
+    }
 
-        let image_buf = self.buffer.clone();
-        let mut image = ImageBuffer::<image::Luma<u8>, _>::from_raw(
-            self.width as u32,
-            self.height as u32,
-            image_buf,
-        )
-        .expect("valid buffer dimensions");
+    fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+}
 
-        image = imageops::rotate270(&image);
-        let width = image.width() as usize;
-        let split = width / 2;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::displays::hal::mock::{Event, Log, MockInputPin, MockOutputPin, MockSpi, NoDelay};
+
+    type MockEl133Uf1 = InkyEl133Uf1<MockSpi, MockOutputPin, MockInputPin, NoDelay>;
+
+    /// The busy line idles low (ready) on this panel.
+    fn mock_hal(log: &Log) -> El133Uf1Hal<MockSpi, MockOutputPin, MockInputPin, NoDelay> {
+        El133Uf1Hal {
+            spi: MockSpi::new(log),
+            cs0: MockOutputPin::new("cs0", log),
+            cs1: MockOutputPin::new("cs1", log),
+            dc: MockOutputPin::new("dc", log),
+            reset: MockOutputPin::new("reset", log),
+            busy: MockInputPin::constant(0),
+            delay: NoDelay,
+        }
+    }
+
+    fn mock_display(config: InkyEl133Uf1Config) -> (MockEl133Uf1, Log) {
+        let log = Log::new();
+        let display = InkyEl133Uf1::from_hal(config, mock_hal(&log)).unwrap();
+        (display, log)
+    }
+
+    /// Groups the log into `(chip selects, command, data)` using the DC and
+    /// CS lines.
+    fn transactions(events: &[Event]) -> Vec<(u8, u8, Vec<u8>)> {
+        let (mut cs0, mut cs1, mut dc) = (1, 1, 0);
+        let mut out: Vec<(u8, u8, Vec<u8>)> = Vec::new();
+        for event in events {
+            match event {
+                Event::Pin("cs0", level) => cs0 = *level,
+                Event::Pin("cs1", level) => cs1 = *level,
+                Event::Pin("dc", level) => dc = *level,
+                Event::Pin(..) => {}
+                Event::Spi(bytes) => {
+                    let mut selected = 0;
+                    if cs0 == 0 {
+                        selected |= CS0_SEL;
+                    }
+                    if cs1 == 0 {
+                        selected |= CS1_SEL;
+                    }
+                    assert_ne!(selected, 0, "SPI write with no chip selected");
+                    if dc == 0 {
+                        assert_eq!(bytes.len(), 1, "commands are a single byte");
+                        out.push((selected, bytes[0], Vec::new()));
+                    } else {
+                        out.last_mut().expect("data before command").2.extend(bytes);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn refresh_sequence(colour: u8) -> Vec<(u8, u8, Vec<u8>)> {
+        let half = vec![(colour << 4) | colour; 1600 * 1200 / 4];
+        vec![
+            (CS0_SEL, EL133UF1_DTM, half.clone()),
+            (CS1_SEL, EL133UF1_DTM, half),
+            (CS_BOTH_SEL, EL133UF1_PON, vec![]),
+            (CS_BOTH_SEL, EL133UF1_DRF, vec![0x00]),
+            (CS_BOTH_SEL, EL133UF1_POF, vec![0x00]),
+        ]
+    }
+
+    #[test]
+    fn first_show_resets_initialises_and_refreshes() {
+        let (mut display, log) = mock_display(InkyEl133Uf1Config::default());
+        display.clear(2);
+        display.show().unwrap();
+
+        let events = log.events();
+        assert_eq!(
+            events[..2],
+            [Event::Pin("reset", 0), Event::Pin("reset", 1)]
+        );
+
+        let mut expected = vec![
+            (
+                CS0_SEL,
+                EL133UF1_ANTM,
+                vec![0xC0, 0x1C, 0x1C, 0xCC, 0xCC, 0xCC, 0x15, 0x15, 0x55],
+            ),
+            (
+                CS_BOTH_SEL,
+                EL133UF1_CMD66,
+                vec![0x49, 0x55, 0x13, 0x5D, 0x05, 0x10],
+            ),
+            (CS_BOTH_SEL, EL133UF1_PSR, vec![0xDF, 0x69]),
+            (CS_BOTH_SEL, EL133UF1_PLL, vec![0x08]),
+            (CS_BOTH_SEL, EL133UF1_CDI, vec![0xF7]),
+            (CS_BOTH_SEL, EL133UF1_TCON, vec![0x03, 0x03]),
+            (CS_BOTH_SEL, EL133UF1_AGID, vec![0x10]),
+            (CS_BOTH_SEL, EL133UF1_PWS, vec![0x22]),
+            (CS_BOTH_SEL, EL133UF1_CCSET, vec![0x01]),
+            (CS_BOTH_SEL, EL133UF1_TRES, vec![0x04, 0xB0, 0x03, 0x20]),
+            (
+                CS0_SEL,
+                EL133UF1_PWR,
+                vec![0x0F, 0x00, 0x28, 0x2C, 0x28, 0x38],
+            ),
+            (CS0_SEL, EL133UF1_EN_BUF, vec![0x07]),
+            (CS0_SEL, EL133UF1_BTST_P, vec![0xD8, 0x18]),
+            (CS0_SEL, EL133UF1_BOOST_VDDP_EN, vec![0x01]),
+            (CS0_SEL, EL133UF1_BTST_N, vec![0xD8, 0x18]),
+            (CS0_SEL, EL133UF1_BUCK_BOOST_VDDN, vec![0x01]),
+            (CS0_SEL, EL133UF1_TFT_VCOM_POWER, vec![0x02]),
+        ];
+        expected.extend(refresh_sequence(2));
+        assert_eq!(transactions(&events), expected);
+        assert_eq!(display.recoveries(), 0);
+    }
+
+    #[test]
+    fn later_shows_skip_initialisation() {
+        let (mut display, log) = mock_display(InkyEl133Uf1Config::default());
+        display.show().unwrap();
+        log.clear();
+
+        display.clear(5);
+        display.show().unwrap();
+        assert_eq!(transactions(&log.events()), refresh_sequence(5));
+    }
 
-        let buf_a = pack_luma_nibbles(&image, 0, split);
-        let buf_b = pack_luma_nibbles(&image, split, width);
+    #[test]
+    fn panel_halves_go_to_their_own_controller() {
+        let (mut display, log) = mock_display(InkyEl133Uf1Config::default());
+        display.clear(1);
+        // The frame is rotated 270 degrees on the way out (1200 columns,
+        // 600 per controller), so the top-left pixel starts the last row
+        // sent to the first controller.
+        display.set_pixel(0, 0, 3);
+        display.show().unwrap();
+
+        let frames: Vec<_> = transactions(&log.events())
+            .into_iter()
+            .filter(|(_, command, _)| *command == EL133UF1_DTM)
+            .collect();
+        assert_eq!(frames.len(), 2);
+        let last_row = 1599 * 300;
+        for (index, &byte) in frames[0].2.iter().enumerate() {
+            let expected = if index == last_row { 0x31 } else { 0x11 };
+            assert_eq!(byte, expected, "byte {index}");
+        }
+        assert!(frames[1].2.iter().all(|&byte| byte == 0x11));
+    }
+
+    #[test]
+    fn empty_resolution_is_rejected() {
+        let config = InkyEl133Uf1Config {
+            width: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            InkyEl133Uf1::from_hal(config, mock_hal(&Log::new())),
+            Err(InkyError::UnsupportedResolution(0, 1200))
+        ));
+    }
 
-        self.send_frame(&buf_a, &buf_b)
+    #[test]
+    fn empty_image_is_an_error() {
+        let (mut display, log) = mock_display(InkyEl133Uf1Config::default());
+        display.clear(2);
+        let result = display.set_image(&DynamicImage::new_rgb8(0, 0), 0.5, 0.0);
+        assert!(matches!(
+            result,
+            Err(InkyError::InvalidImageDimensions {
+                expected: (1600, 1200),
+                received: (0, 0)
+            })
+        ));
+        assert!(display.buffer().iter().all(|&value| value == 2));
+        assert!(log.events().is_empty());
     }
 }
@@ -10,6 +10,9 @@ pub enum InkyError {
     #[error("GPIO error: {0}")]
     Gpio(#[from] gpio_cdev::errors::Error),
 
+    #[error("GPIO line {line} is already in use by `{consumer}`")]
+    LinesBusy { line: u32, consumer: String },
+
     #[error("Timed out waiting for {0} after {1:?}")]
     Timeout(&'static str, Duration),
 
@@ -27,6 +30,36 @@ pub enum InkyError {
         expected: (u16, u16),
         received: (u32, u32),
     },
+
+    #[error("Image too large: {width}x{height} exceeds the configured limits")]
+    ImageTooLarge { width: u32, height: u32 },
+
+    #[error("Unsupported image format: {0}")]
+    UnsupportedImageFormat(String),
+
+    #[error("Invalid config {0}")]
+    InvalidConfig(String),
+
+    #[error("Invalid button binding: {0}")]
+    InvalidButton(String),
+
+    #[error("Invalid dither method: {0}")]
+    InvalidDither(String),
+
+    #[error("Invalid render option: {0}")]
+    InvalidRenderOption(String),
+
+    #[error("Invalid palette map: {0}")]
+    InvalidPaletteMap(String),
+
+    #[error("Invalid calibration: {0}")]
+    InvalidCalibration(String),
+
+    #[error("Invalid Pimoroni frame: {0}")]
+    InvalidFrame(String),
+
+    #[error("Notification failed: {0}")]
+    Notify(String),
 }
 
 pub type Result<T> = std::result::Result<T, InkyError>;
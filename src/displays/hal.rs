@@ -0,0 +1,179 @@
+//! The handful of bus operations the drivers need, so they can run against
+//! spidev/gpio_cdev on a Pi or the in-memory [`mock`] implementations in
+//! tests.
+
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+use gpio_cdev::LineHandle;
+use spidev::Spidev;
+
+use super::error::Result;
+
+/// Write-only SPI; chip select is driven separately through an [`OutputPin`].
+pub trait SpiBus {
+    fn write(&mut self, data: &[u8]) -> Result<()>;
+}
+
+pub trait OutputPin {
+    /// Drives the pin low (0) or high (1).
+    fn set_value(&mut self, value: u8) -> Result<()>;
+}
+
+pub trait InputPin {
+    /// Reads the pin level, 0 or 1.
+    fn get_value(&mut self) -> Result<u8>;
+}
+
+pub trait Delay {
+    fn delay(&mut self, duration: Duration);
+}
+
+impl SpiBus for Spidev {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.write_all(data)?;
+        Ok(())
+    }
+}
+
+impl OutputPin for LineHandle {
+    fn set_value(&mut self, value: u8) -> Result<()> {
+        LineHandle::set_value(self, value)?;
+        Ok(())
+    }
+}
+
+impl InputPin for LineHandle {
+    fn get_value(&mut self) -> Result<u8> {
+        Ok(LineHandle::get_value(self)?)
+    }
+}
+
+/// Blocks the calling thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdDelay;
+
+impl Delay for StdDelay {
+    fn delay(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// In-memory implementations that record every bus operation, in order,
+/// into a shared [`Log`].
+pub mod mock {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::{Delay, InputPin, OutputPin, SpiBus};
+    use crate::displays::error::Result;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Event {
+        Spi(Vec<u8>),
+        Pin(&'static str, u8),
+    }
+
+    /// Event log shared by the mocks of one device.
+    #[derive(Clone, Debug, Default)]
+    pub struct Log(Rc<RefCell<Vec<Event>>>);
+
+    impl Log {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn events(&self) -> Vec<Event> {
+            self.0.borrow().clone()
+        }
+
+        pub fn clear(&self) {
+            self.0.borrow_mut().clear();
+        }
+
+        fn push(&self, event: Event) {
+            self.0.borrow_mut().push(event);
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct MockSpi {
+        log: Log,
+    }
+
+    impl MockSpi {
+        pub fn new(log: &Log) -> Self {
+            Self { log: log.clone() }
+        }
+    }
+
+    impl SpiBus for MockSpi {
+        fn write(&mut self, data: &[u8]) -> Result<()> {
+            self.log.push(Event::Spi(data.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct MockOutputPin {
+        name: &'static str,
+        log: Log,
+    }
+
+    impl MockOutputPin {
+        pub fn new(name: &'static str, log: &Log) -> Self {
+            Self {
+                name,
+                log: log.clone(),
+            }
+        }
+    }
+
+    impl OutputPin for MockOutputPin {
+        fn set_value(&mut self, value: u8) -> Result<()> {
+            self.log.push(Event::Pin(self.name, value));
+            Ok(())
+        }
+    }
+
+    /// Plays back a fixed pattern of levels, cycling forever.
+    #[derive(Debug)]
+    pub struct MockInputPin {
+        levels: Vec<u8>,
+        next: usize,
+    }
+
+    impl MockInputPin {
+        pub fn constant(level: u8) -> Self {
+            Self::pattern(&[level])
+        }
+
+        /// An empty pattern reads as constantly low.
+        pub fn pattern(levels: &[u8]) -> Self {
+            let levels = if levels.is_empty() {
+                vec![0]
+            } else {
+                levels.to_vec()
+            };
+            Self { levels, next: 0 }
+        }
+    }
+
+    impl InputPin for MockInputPin {
+        fn get_value(&mut self) -> Result<u8> {
+            let level = self.levels[self.next];
+            self.next = (self.next + 1) % self.levels.len();
+            Ok(level)
+        }
+    }
+
+    /// Returns immediately.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct NoDelay;
+
+    impl Delay for NoDelay {
+        fn delay(&mut self, _duration: Duration) {}
+    }
+}
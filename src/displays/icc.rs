@@ -0,0 +1,78 @@
+//! Embedded colour profiles. Photos from phones and cameras are often
+//! tagged Display P3 or Adobe RGB; read as sRGB they come out dull and
+//! shifted before they ever reach the palette, so they are converted to
+//! sRGB when decoded.
+
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+use moxcms::{ColorProfile, Layout, TransformOptions};
+
+/// Converts `image` from the colour space described by the ICC profile
+/// `icc` to sRGB. Greyscale images, and profiles that cannot be parsed or
+/// converted from, leave the image as decoded: a slight colour shift is
+/// better than refusing to show it.
+pub fn convert_to_srgb(image: DynamicImage, icc: &[u8]) -> DynamicImage {
+    let Ok(source) = ColorProfile::new_from_slice(icc) else {
+        return image;
+    };
+    let srgb = ColorProfile::new_srgb();
+    let options = TransformOptions::default();
+    let (width, height) = (image.width(), image.height());
+
+    match image {
+        DynamicImage::ImageRgb8(ref rgb) => source
+            .create_transform_8bit(Layout::Rgb, &srgb, Layout::Rgb, options)
+            .ok()
+            .and_then(|transform| {
+                let mut out = vec![0u8; rgb.len()];
+                transform.transform(rgb.as_raw(), &mut out).ok()?;
+                ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, out)
+            })
+            .map_or(image, DynamicImage::ImageRgb8),
+        DynamicImage::ImageRgba8(ref rgba) => source
+            .create_transform_8bit(Layout::Rgba, &srgb, Layout::Rgba, options)
+            .ok()
+            .and_then(|transform| {
+                let mut out = vec![0u8; rgba.len()];
+                transform.transform(rgba.as_raw(), &mut out).ok()?;
+                ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, out)
+            })
+            .map_or(image, DynamicImage::ImageRgba8),
+        DynamicImage::ImageRgb16(ref rgb) => source
+            .create_transform_16bit(Layout::Rgb, &srgb, Layout::Rgb, options)
+            .ok()
+            .and_then(|transform| {
+                let mut out = vec![0u16; rgb.len()];
+                transform.transform(rgb.as_raw(), &mut out).ok()?;
+                ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, out)
+            })
+            .map_or(image, DynamicImage::ImageRgb16),
+        DynamicImage::ImageRgba16(ref rgba) => source
+            .create_transform_16bit(Layout::Rgba, &srgb, Layout::Rgba, options)
+            .ok()
+            .and_then(|transform| {
+                let mut out = vec![0u16; rgba.len()];
+                transform.transform(rgba.as_raw(), &mut out).ok()?;
+                ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, out)
+            })
+            .map_or(image, DynamicImage::ImageRgba16),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_p3_red_is_clipped_into_srgb() {
+        let icc = ColorProfile::new_display_p3().encode().unwrap();
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(2, 2, Rgb([200, 30, 30])));
+
+        let converted = convert_to_srgb(image.clone(), &icc).to_rgb8();
+        let [r, g, b] = converted.get_pixel(0, 0).0;
+        // P3 red is more saturated than sRGB can show: red saturates and
+        // green drops further.
+        assert!(r > 200 && g < 30, "{r} {g} {b}");
+        assert_eq!(convert_to_srgb(image.clone(), b"not a profile"), image);
+    }
+}
@@ -13,23 +13,54 @@ pub mod common;
 #[cfg(target_os = "linux")]
 pub mod el133uf1;
 
+#[cfg(target_os = "linux")]
+pub mod hal;
+
+#[cfg(target_os = "linux")]
+pub mod icc;
+
+#[cfg(target_os = "linux")]
+pub mod open;
+
+#[cfg(target_os = "linux")]
+pub mod render;
+
+#[cfg(target_os = "linux")]
+pub mod shared;
+
 #[cfg(target_os = "linux")]
 pub use common::{
-    InkyDisplay, Rotation, clamp_aspect_resize, distribute_error, nearest_colour,
-    pack_buffer_nibbles, pack_luma_nibbles,
+    Capabilities, ImageLimits, InkyDisplay, Rotation, ShowOutcome, Timeouts, clamp_aspect_resize,
+    decode_image, distribute_error, load_image, nearest_colour, pack_buffer_nibbles,
+    pack_luma_nibbles,
 };
 
 #[cfg(target_os = "linux")]
 pub use detect::{
-    DisplaySpec, EepromInfo, I2cBusReport, I2cProbeStatus, ProbeInfo, probe_system,
-    uc8159_resolution_from_probe,
+    DeviceBackend, DisplaySpec, EepromInfo, EepromWarning, I2cBusReport, I2cProbeStatus,
+    LinuxDevices, ProbeInfo, probe_system, probe_system_at, uc8159_resolution_from_probe,
 };
 
 #[cfg(target_os = "linux")]
-pub use uc8159::{InkyUc8159, InkyUc8159Config, Pins};
+pub use uc8159::{InkyUc8159, InkyUc8159Config, Pins, Uc8159Hal};
 
 #[cfg(target_os = "linux")]
-pub use el133uf1::{InkyEl133Uf1, InkyEl133Uf1Config, SpectraPins};
+pub use el133uf1::{El133Uf1Hal, InkyEl133Uf1, InkyEl133Uf1Config, SpectraPins};
 
 #[cfg(target_os = "linux")]
 pub use error::{InkyError, Result};
+
+#[cfg(target_os = "linux")]
+pub use open::open_display;
+
+#[cfg(target_os = "linux")]
+pub use icc::convert_to_srgb;
+
+#[cfg(target_os = "linux")]
+pub use hal::{Delay, InputPin, OutputPin, SpiBus, StdDelay};
+
+#[cfg(target_os = "linux")]
+pub use render::{Fit, RenderOptions, RenderReport};
+
+#[cfg(target_os = "linux")]
+pub use shared::{SendDisplay, SharedDisplay};
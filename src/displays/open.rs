@@ -0,0 +1,77 @@
+//! Opening whichever driver matches a probe.
+
+use super::common::{InkyDisplay, Rotation};
+use super::detect::{DisplaySpec, ProbeInfo};
+use super::el133uf1::{self, InkyEl133Uf1, InkyEl133Uf1Config, SpectraPins};
+use super::error::Result;
+use super::uc8159::{self, InkyUc8159, InkyUc8159Config, Pins};
+use crate::config::DisplayOverrides;
+
+/// Opens the display `probe` found (see [`ProbeInfo::display_or_default`])
+/// under its device root, applying `overrides` on top of the driver's
+/// defaults. Explicit device paths in `overrides` replace the ones under the
+/// device root.
+pub fn open_display(
+    probe: &ProbeInfo,
+    rotation: Rotation,
+    overrides: &DisplayOverrides,
+) -> Result<Box<dyn InkyDisplay + Send>> {
+    match probe.display_or_default() {
+        DisplaySpec::El133Uf1 { width, height } => {
+            let defaults = InkyEl133Uf1Config::default();
+            let config = InkyEl133Uf1Config {
+                width,
+                height,
+                rotation,
+                busy_retries: overrides.busy_retries.unwrap_or(defaults.busy_retries),
+                timeouts: overrides.timeouts(el133uf1::DEFAULT_TIMEOUTS),
+                ..defaults
+            }
+            .with_dev_root(&probe.dev_root);
+            let pins = &config.pins;
+            let pins = SpectraPins {
+                cs0: overrides.cs_pin.unwrap_or(pins.cs0),
+                cs1: overrides.cs1_pin.unwrap_or(pins.cs1),
+                dc: overrides.dc_pin.unwrap_or(pins.dc),
+                reset: overrides.reset_pin.unwrap_or(pins.reset),
+                busy: overrides.busy_pin.unwrap_or(pins.busy),
+            };
+            let config = InkyEl133Uf1Config {
+                spi_path: overrides.spi_path.clone().unwrap_or(config.spi_path),
+                gpio_chip: overrides.gpio_chip.clone().unwrap_or(config.gpio_chip),
+                pins,
+                ..config
+            };
+            let mut display = InkyEl133Uf1::new(config)?;
+            display.set_rotation(rotation);
+            Ok(Box::new(display))
+        }
+        DisplaySpec::Uc8159 { width, height, .. } => {
+            let defaults = InkyUc8159Config::default();
+            let config = InkyUc8159Config {
+                width,
+                height,
+                rotation,
+                busy_retries: overrides.busy_retries.unwrap_or(defaults.busy_retries),
+                timeouts: overrides.timeouts(uc8159::DEFAULT_TIMEOUTS),
+                ..defaults
+            }
+            .with_dev_root(&probe.dev_root);
+            let pins = Pins {
+                cs: overrides.cs_pin.unwrap_or(config.pins.cs),
+                dc: overrides.dc_pin.unwrap_or(config.pins.dc),
+                reset: overrides.reset_pin.unwrap_or(config.pins.reset),
+                busy: overrides.busy_pin.unwrap_or(config.pins.busy),
+            };
+            let config = InkyUc8159Config {
+                spi_path: overrides.spi_path.clone().unwrap_or(config.spi_path),
+                gpio_chip: overrides.gpio_chip.clone().unwrap_or(config.gpio_chip),
+                pins,
+                ..config
+            };
+            let mut display = InkyUc8159::new(config)?;
+            display.set_rotation(rotation);
+            Ok(Box::new(display))
+        }
+    }
+}
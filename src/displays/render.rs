@@ -0,0 +1,317 @@
+//! Everything that controls how an image becomes a frame, in one place, so
+//! new settings don't keep growing the positional `(saturation, lighten)`
+//! arguments of [`InkyDisplay::set_image`](super::InkyDisplay::set_image).
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgb, Rgb32FImage, RgbImage};
+
+use super::common::{crop_to_aspect, lighten_image_in_place};
+use super::error::InkyError;
+use crate::analysis::{HIGHLIGHT_CLIP, PaletteUsage, SHADOW_CLIP};
+use crate::dither::{DitherOptions, DitherStats};
+
+/// Fraction of pixels left to clip at each end when tone mapping, so a few
+/// hot pixels or stars don't set the white point.
+const TONE_MAP_CLIP: f32 = 0.001;
+
+/// How an image whose aspect ratio differs from the panel's is fitted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fit {
+    /// Fill the panel, cropping the centre of the image.
+    #[default]
+    Crop,
+    /// Show the whole image, letterboxed in white.
+    Contain,
+    /// Fill the panel, distorting the image.
+    Stretch,
+}
+
+impl Fit {
+    pub fn apply(self, image: &DynamicImage, width: u32, height: u32) -> RgbImage {
+        self.resize(image, width, height).to_rgb8()
+    }
+
+    /// [`apply`](Self::apply) without the conversion to 8 bits, so 16-bit
+    /// images keep their precision until they are tone mapped.
+    pub fn resize(self, image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        if image.dimensions() == (width, height) {
+            return image.clone();
+        }
+        if image.width() == 0 || image.height() == 0 {
+            return DynamicImage::new_rgb8(width, height);
+        }
+        match self {
+            Fit::Crop => crop_to_aspect(image, width, height).resize_exact(
+                width,
+                height,
+                FilterType::Triangle,
+            ),
+            Fit::Stretch => image.resize_exact(width, height, FilterType::Triangle),
+            Fit::Contain => {
+                let scaled = image
+                    .resize(width, height, FilterType::Triangle)
+                    .to_rgb32f();
+                let mut canvas = Rgb32FImage::from_pixel(width, height, Rgb([1.0; 3]));
+                let x = (width - scaled.width().min(width)) / 2;
+                let y = (height - scaled.height().min(height)) / 2;
+                imageops::replace(&mut canvas, &scaled, x as i64, y as i64);
+                DynamicImage::ImageRgb32F(canvas)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fit::Crop => f.write_str("crop"),
+            Fit::Contain => f.write_str("contain"),
+            Fit::Stretch => f.write_str("stretch"),
+        }
+    }
+}
+
+impl FromStr for Fit {
+    type Err = InkyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "crop" | "cover" => Ok(Fit::Crop),
+            "contain" | "letterbox" => Ok(Fit::Contain),
+            "stretch" => Ok(Fit::Stretch),
+            _ => Err(InkyError::InvalidRenderOption(format!(
+                "unknown fit `{value}` (expected crop, contain or stretch)"
+            ))),
+        }
+    }
+}
+
+/// Settings for turning an image into a frame, used with
+/// [`InkyDisplay::set_image_with`](super::InkyDisplay::set_image_with).
+/// Fitting and tone mapping happen at the image's own bit depth; the
+/// adjustments then run in the order gamma, contrast, lighten.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// Palette saturation from 0.0 (desaturated) to 1.0 (saturated).
+    pub saturation: f32,
+    /// Lighten strength from 0.0 (none) to 1.0 (strongest).
+    pub lighten: f32,
+    /// Error diffusion, including any palette substitutions.
+    pub dither: DitherOptions,
+    pub fit: Fit,
+    /// Stretch the darkest and brightest tones to black and white before
+    /// the image is reduced to 8 bits, for dim or low-contrast sources such
+    /// as astrophotography and high-bit-depth scans.
+    pub tone_map: bool,
+    /// Gamma applied to each channel; above 1.0 brightens midtones.
+    pub gamma: f32,
+    /// Contrast around mid grey; 1.0 leaves the image alone.
+    pub contrast: f32,
+    /// Border colour on panels that have one (see
+    /// [`Capabilities::border`](super::Capabilities::border)).
+    pub border: Option<u8>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            saturation: 0.5,
+            lighten: 0.0,
+            dither: DitherOptions::default(),
+            fit: Fit::default(),
+            tone_map: false,
+            gamma: 1.0,
+            contrast: 1.0,
+            border: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Fits `image` to `width`x`height` and applies the adjustments.
+    pub fn prepare(&self, image: &DynamicImage, width: u32, height: u32) -> RgbImage {
+        let mut rgb = self.fit_image(image, width, height);
+        self.adjust_in_place(&mut rgb);
+        rgb
+    }
+
+    /// Fits `image` to `width`x`height` and reduces it to 8 bits, tone
+    /// mapping it on the way if enabled.
+    pub fn fit_image(&self, image: &DynamicImage, width: u32, height: u32) -> RgbImage {
+        let fitted = self.fit.resize(image, width, height);
+        if self.tone_map {
+            tone_map(&fitted.to_rgb32f())
+        } else {
+            fitted.to_rgb8()
+        }
+    }
+
+    /// Applies gamma, contrast and lighten to an already fitted image.
+    pub fn adjust_in_place(&self, rgb: &mut RgbImage) {
+        let gamma = self.gamma.max(0.01);
+        if gamma != 1.0 || self.contrast != 1.0 {
+            let mut lut = [0u8; 256];
+            for (value, out) in lut.iter_mut().enumerate() {
+                let v = (value as f32 / 255.0).powf(1.0 / gamma);
+                let v = (v - 0.5) * self.contrast.max(0.0) + 0.5;
+                *out = (v * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+            for pixel in rgb.pixels_mut() {
+                pixel.0 = pixel.0.map(|channel| lut[channel as usize]);
+            }
+        }
+        lighten_image_in_place(rgb, self.lighten);
+    }
+
+    /// These options with tone mapping and the adjustments cleared, for an
+    /// image that has already been through [`fit_image`](Self::fit_image)
+    /// and [`adjust_in_place`](Self::adjust_in_place).
+    pub fn without_adjustments(&self) -> Self {
+        Self {
+            tone_map: false,
+            lighten: 0.0,
+            gamma: 1.0,
+            contrast: 1.0,
+            ..*self
+        }
+    }
+}
+
+/// Linearly maps the luma range of `rgb`, less [`TONE_MAP_CLIP`] at each
+/// end, onto 0-255. Flat images are only converted.
+fn tone_map(rgb: &Rgb32FImage) -> RgbImage {
+    let mut luma: Vec<f32> = rgb
+        .pixels()
+        .map(|p| 0.299 * p[0] + 0.587 * p[1] + 0.114 * p[2])
+        .collect();
+    luma.sort_by(f32::total_cmp);
+    let at = |fraction: f32| luma[((luma.len() - 1) as f32 * fraction).round() as usize];
+    let (black, white) = match luma.len() {
+        0 => (0.0, 0.0),
+        _ => (at(TONE_MAP_CLIP), at(1.0 - TONE_MAP_CLIP)),
+    };
+    if white - black < 1.0 / 255.0 {
+        return DynamicImage::ImageRgb32F(rgb.clone()).to_rgb8();
+    }
+    let map = |value: f32| {
+        ((value - black) / (white - black) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    RgbImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+        Rgb(rgb.get_pixel(x, y).0.map(map))
+    })
+}
+
+/// What [`InkyDisplay::set_image_with`](super::InkyDisplay::set_image_with)
+/// did to an image, to explain why a frame looks washed out or grainy.
+#[derive(Clone, Debug, Default)]
+pub struct RenderReport {
+    /// Palette usage of the quantized frame; empty if the driver does not
+    /// report it.
+    pub palette: Vec<PaletteUsage>,
+    /// See [`DitherStats::mean_error`].
+    pub mean_error: f32,
+    /// Fraction of prepared pixels at (or within a few levels of) white.
+    pub clipped_highlights: f32,
+    /// Fraction of prepared pixels at (or within a few levels of) black.
+    pub clipped_shadows: f32,
+    /// Fitting and tone adjustments.
+    pub prepare_time: Duration,
+    /// Dithering and packing into the frame buffer.
+    pub quantize_time: Duration,
+}
+
+impl RenderReport {
+    /// The palette part of a report, from a driver's dither pass over
+    /// `palette`, whose entries are named by `names`.
+    pub(crate) fn from_dither(
+        names: &[&'static str],
+        palette: &[[f32; 3]],
+        stats: &DitherStats,
+    ) -> Self {
+        let total = stats.counts.iter().sum::<usize>().max(1);
+        Self {
+            palette: names
+                .iter()
+                .zip(palette)
+                .zip(&stats.counts)
+                .map(|((&name, colour), &count)| PaletteUsage {
+                    name,
+                    colour: colour.map(|channel| channel.round() as u8),
+                    fraction: count as f32 / total as f32,
+                })
+                .collect(),
+            mean_error: stats.mean_error,
+            ..Default::default()
+        }
+    }
+
+    /// Fills in the clipping fractions from the prepared image.
+    pub(crate) fn measure_clipping(&mut self, rgb: &RgbImage) {
+        let total = (rgb.width() as usize * rgb.height() as usize).max(1) as f32;
+        let (mut highlights, mut shadows) = (0usize, 0usize);
+        for pixel in rgb.pixels() {
+            let [r, g, b] = pixel.0;
+            highlights += (r.min(g).min(b) >= HIGHLIGHT_CLIP) as usize;
+            shadows += (r.max(g).max(b) <= SHADOW_CLIP) as usize;
+        }
+        self.clipped_highlights = highlights as f32 / total;
+        self.clipped_shadows = shadows as f32 / total;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contain_letterboxes_in_white() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 10, Rgb([0, 0, 0])));
+        let rgb = Fit::Contain.apply(&image, 20, 20);
+        assert_eq!(rgb.dimensions(), (20, 20));
+        assert_eq!(rgb.get_pixel(10, 1), &Rgb([255, 255, 255]));
+        assert_eq!(rgb.get_pixel(10, 10), &Rgb([0, 0, 0]));
+        assert_eq!(
+            Fit::Crop.apply(&image, 20, 20).get_pixel(10, 1),
+            &Rgb([0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn tone_mapping_keeps_16_bit_shadow_detail() {
+        // A dim 16-bit gradient that 8 bits would flatten to three levels.
+        let dim = image::ImageBuffer::from_fn(256, 4, |x, _| Rgb([(x * 2) as u16; 3]));
+        let image = DynamicImage::ImageRgb16(dim);
+        assert_eq!(Fit::Crop.apply(&image, 256, 4).get_pixel(255, 0)[0], 2);
+
+        let options = RenderOptions {
+            tone_map: true,
+            ..Default::default()
+        };
+        let rgb = options.fit_image(&image, 256, 4);
+        assert_eq!(rgb.get_pixel(0, 0)[0], 0);
+        assert_eq!(rgb.get_pixel(255, 0)[0], 255);
+        assert!((120..136).contains(&rgb.get_pixel(128, 0)[0]));
+    }
+
+    #[test]
+    fn adjustments_default_to_identity() {
+        let mut rgb = RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 77]));
+        let original = rgb.clone();
+        RenderOptions::default().adjust_in_place(&mut rgb);
+        assert_eq!(rgb, original);
+
+        let options = RenderOptions {
+            contrast: 2.0,
+            ..Default::default()
+        };
+        options.adjust_in_place(&mut rgb);
+        assert_eq!(rgb.get_pixel(1, 0)[0], 0);
+        assert_eq!(rgb.get_pixel(15, 0)[0], 255);
+    }
+}
@@ -0,0 +1,99 @@
+//! A display handle that several threads can hold at once.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use image::DynamicImage;
+
+use super::common::{Capabilities, InkyDisplay, ShowOutcome};
+use super::error::Result;
+use super::render::{RenderOptions, RenderReport};
+
+/// The boxed display behind a [`SharedDisplay`].
+pub type SendDisplay = Box<dyn InkyDisplay + Send>;
+
+/// Cheaply cloneable handle to one display. Every call takes the display's
+/// lock for its duration, so commands from different threads queue up and
+/// run one at a time; a refresh holds the lock until the panel is idle.
+///
+/// Use [`lock`](Self::lock) to run several commands without another thread
+/// getting in between, e.g. setting an image and showing it.
+#[derive(Clone)]
+pub struct SharedDisplay {
+    inner: Arc<Mutex<SendDisplay>>,
+}
+
+impl SharedDisplay {
+    pub fn new(display: SendDisplay) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(display)),
+        }
+    }
+
+    /// Waits for any queued command and returns exclusive access. A thread
+    /// that panicked mid-command leaves the buffer as it was, so a poisoned
+    /// lock is recovered rather than propagated.
+    pub fn lock(&self) -> MutexGuard<'_, SendDisplay> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn width(&self) -> u16 {
+        self.lock().width()
+    }
+
+    pub fn height(&self) -> u16 {
+        self.lock().height()
+    }
+
+    pub fn input_dimensions(&self) -> (u16, u16) {
+        self.lock().input_dimensions()
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.lock().capabilities()
+    }
+
+    pub fn clear(&self, colour: u8) {
+        self.lock().clear(colour);
+    }
+
+    pub fn set_image(&self, image: &DynamicImage, saturation: f32, lighten: f32) -> Result<()> {
+        self.lock().set_image(image, saturation, lighten)
+    }
+
+    pub fn set_image_with(
+        &self,
+        image: &DynamicImage,
+        options: &RenderOptions,
+    ) -> Result<RenderReport> {
+        self.lock().set_image_with(image, options)
+    }
+
+    pub fn set_image_from_path(&self, path: &Path, saturation: f32, lighten: f32) -> Result<()> {
+        self.lock().set_image_from_path(path, saturation, lighten)
+    }
+
+    pub fn show(&self) -> Result<()> {
+        self.lock().show()
+    }
+
+    pub fn upload(&self) -> Result<()> {
+        self.lock().upload()
+    }
+
+    pub fn show_if_changed(&self, previous: Option<u64>) -> Result<ShowOutcome> {
+        self.lock().show_if_changed(previous)
+    }
+
+    pub fn frame_hash(&self) -> u64 {
+        self.lock().frame_hash()
+    }
+
+    /// Copy of the quantized buffer; the display itself stays locked only
+    /// while copying.
+    pub fn buffer(&self) -> Vec<u8> {
+        self.lock().buffer().to_vec()
+    }
+}
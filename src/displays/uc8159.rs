@@ -1,6 +1,4 @@
-use std::io::Write;
 use std::path::Path;
-use std::thread;
 use std::time::{Duration, Instant};
 
 use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
@@ -8,10 +6,14 @@ use image::{DynamicImage, GenericImageView, RgbImage};
 use spidev::{SpiModeFlags, Spidev, SpidevOptions};
 
 use super::common::{
-    InkyDisplay, Rotation, clamp_aspect_resize, distribute_error, lighten_image_in_place,
-    nearest_colour, pack_buffer_nibbles,
+    Capabilities, GPIO_CHIP, ImageLimits, InkyDisplay, Rotation, SPI_DEVICE, Timeouts,
+    clamp_aspect_resize, copy_panel_buffer, dev_path, ensure_not_empty, lighten_image_in_place,
+    load_image, pack_buffer_nibbles, request_line,
 };
 use super::error::{InkyError, Result};
+use super::hal::{Delay, InputPin, OutputPin, SpiBus, StdDelay};
+use super::render::RenderReport;
+use crate::dither::{DitherOptions, dither_with_stats};
 
 const UC8159_PSR: u8 = 0x00;
 const UC8159_PWR: u8 = 0x01;
@@ -30,6 +32,24 @@ const UC8159_PWS: u8 = 0xE3;
 
 const SPI_CHUNK_SIZE: usize = 4096;
 
+pub const DEFAULT_TIMEOUTS: Timeouts = Timeouts {
+    reset: Duration::from_secs(1),
+    power: Duration::from_millis(200),
+    refresh: Duration::from_secs(32),
+};
+
+/// What the 7-colour ACeP panel supports, as reported by [`InkyDisplay::capabilities`].
+pub const CAPABILITIES: Capabilities = Capabilities {
+    colours: 7,
+    partial_refresh: false,
+    border: true,
+    refresh_time: Duration::from_secs(30),
+    min_refresh_interval: Duration::from_secs(180),
+};
+
+/// Names of the panel colours, in palette (and buffer value) order.
+pub const COLOUR_NAMES: [&str; 7] = ["black", "white", "green", "blue", "red", "yellow", "orange"];
+
 const DESATURATED_PALETTE: [[u8; 3]; 7] = [
     [0, 0, 0],
     [255, 255, 255],
@@ -77,6 +97,12 @@ pub struct InkyUc8159Config {
     pub pins: Pins,
     pub border_colour: u8,
     pub rotation: Rotation,
+    pub dither: DitherOptions,
+    /// Times to hardware-reset, re-initialise and resend a frame after the
+    /// busy line times out during a refresh. Long ribbon cables make
+    /// transient busy glitches common; 0 fails on the first timeout.
+    pub busy_retries: u32,
+    pub timeouts: Timeouts,
 }
 
 impl Default for InkyUc8159Config {
@@ -89,16 +115,40 @@ impl Default for InkyUc8159Config {
             pins: Pins::default(),
             border_colour: 1,
             rotation: Rotation::Deg0,
+            dither: DitherOptions::default(),
+            busy_retries: 1,
+            timeouts: DEFAULT_TIMEOUTS,
         }
     }
 }
 
-pub struct InkyUc8159 {
-    spi: Spidev,
-    cs: LineHandle,
-    dc: LineHandle,
-    reset: LineHandle,
-    busy: LineHandle,
+impl InkyUc8159Config {
+    /// Points the SPI device and GPIO chip at `dev_root` instead of `/dev`,
+    /// e.g. the root a [`ProbeInfo`](super::ProbeInfo) was taken from.
+    pub fn with_dev_root(mut self, dev_root: &Path) -> Self {
+        self.spi_path = dev_path(dev_root, SPI_DEVICE);
+        self.gpio_chip = dev_path(dev_root, GPIO_CHIP);
+        self
+    }
+}
+
+/// The buses a UC8159 is driven through; see [`InkyUc8159::from_hal`].
+pub struct Uc8159Hal<S, O, I, D> {
+    pub spi: S,
+    pub cs: O,
+    pub dc: O,
+    pub reset: O,
+    pub busy: I,
+    pub delay: D,
+}
+
+pub struct InkyUc8159<S = Spidev, O = LineHandle, I = LineHandle, D = StdDelay> {
+    spi: S,
+    cs: O,
+    dc: O,
+    reset: O,
+    busy: I,
+    delay: D,
     width: u16,
     height: u16,
     resolution_setting: u8,
@@ -106,30 +156,49 @@ pub struct InkyUc8159 {
     border_colour: u8,
     initialised: bool,
     rotation: Rotation,
+    dither: DitherOptions,
+    report: Option<RenderReport>,
+    busy_retries: u32,
+    recoveries: u32,
+    timeouts: Timeouts,
 }
 
 impl InkyUc8159 {
     pub fn new(config: InkyUc8159Config) -> Result<Self> {
         let mut chip = Chip::new(&config.gpio_chip)?;
 
-        let cs = chip
-            .get_line(config.pins.cs)?
-            .request(LineRequestFlags::OUTPUT, 1, "paperwave-cs")?;
-        let dc = chip
-            .get_line(config.pins.dc)?
-            .request(LineRequestFlags::OUTPUT, 0, "paperwave-dc")?;
-        let reset = chip.get_line(config.pins.reset)?.request(
+        let cs = request_line(
+            &mut chip,
+            config.pins.cs,
+            LineRequestFlags::OUTPUT,
+            1,
+            "paperwave-cs",
+        )?;
+        let dc = request_line(
+            &mut chip,
+            config.pins.dc,
+            LineRequestFlags::OUTPUT,
+            0,
+            "paperwave-dc",
+        )?;
+        let reset = request_line(
+            &mut chip,
+            config.pins.reset,
             LineRequestFlags::OUTPUT,
             1,
             "paperwave-reset",
         )?;
-        let busy =
-            chip.get_line(config.pins.busy)?
-                .request(LineRequestFlags::INPUT, 0, "paperwave-busy")?;
+        let busy = request_line(
+            &mut chip,
+            config.pins.busy,
+            LineRequestFlags::INPUT,
+            0,
+            "paperwave-busy",
+        )?;
 
         drop(chip);
 
-        let mut spi = Spidev::open(config.spi_path)?;
+        let mut spi = Spidev::open(&config.spi_path)?;
         let options = SpidevOptions::new()
             .bits_per_word(8)
             .max_speed_hz(3_000_000)
@@ -137,6 +206,25 @@ impl InkyUc8159 {
             .build();
         spi.configure(&options)?;
 
+        Self::from_hal(
+            config,
+            Uc8159Hal {
+                spi,
+                cs,
+                dc,
+                reset,
+                busy,
+                delay: StdDelay,
+            },
+        )
+    }
+}
+
+impl<S: SpiBus, O: OutputPin, I: InputPin, D: Delay> InkyUc8159<S, O, I, D> {
+    /// Builds a driver over any bus implementation, e.g. the
+    /// [`mock`](super::hal::mock) ones. Nothing is sent until the first
+    /// [`show`](Self::show).
+    pub fn from_hal(config: InkyUc8159Config, hal: Uc8159Hal<S, O, I, D>) -> Result<Self> {
         let resolution_setting = match (config.width, config.height) {
             (600, 448) => 0b11,
             (640, 400) => 0b10,
@@ -151,11 +239,12 @@ impl InkyUc8159 {
         let buffer = vec![0; (config.width as usize) * (config.height as usize)];
 
         Ok(Self {
-            spi,
-            cs,
-            dc,
-            reset,
-            busy,
+            spi: hal.spi,
+            cs: hal.cs,
+            dc: hal.dc,
+            reset: hal.reset,
+            busy: hal.busy,
+            delay: hal.delay,
             width: config.width,
             height: config.height,
             resolution_setting,
@@ -163,6 +252,11 @@ impl InkyUc8159 {
             border_colour: config.border_colour & 0x07,
             initialised: false,
             rotation: config.rotation,
+            dither: config.dither,
+            report: None,
+            busy_retries: config.busy_retries,
+            recoveries: 0,
+            timeouts: config.timeouts,
         })
     }
 
@@ -186,6 +280,10 @@ impl InkyUc8159 {
         self.rotation.target_dimensions(self.width, self.height)
     }
 
+    pub fn set_dither(&mut self, dither: DitherOptions) {
+        self.dither = dither;
+    }
+
     pub fn buffer(&self) -> &[u8] {
         &self.buffer
     }
@@ -210,11 +308,12 @@ impl InkyUc8159 {
     }
 
     pub fn set_image_from_path(&mut self, path: &Path, saturation: f32, lighten: f32) -> Result<()> {
-        let image = image::open(path)?;
+        let image = load_image(path, &ImageLimits::default())?;
         self.set_image(&image, saturation, lighten)
     }
 
     pub fn set_image(&mut self, image: &DynamicImage, saturation: f32, lighten: f32) -> Result<()> {
+        ensure_not_empty(image, self.input_dimensions())?;
         let mut rgb = self.prepare_image(image);
         lighten_image_in_place(&mut rgb, lighten);
         let palette = build_palette(saturation);
@@ -251,30 +350,69 @@ impl InkyUc8159 {
     }
 
     pub fn show(&mut self) -> Result<()> {
+        self.recoveries = 0;
+        loop {
+            match self.refresh() {
+                Err(InkyError::Timeout(..)) if self.recoveries < self.busy_retries => {
+                    // initialise() starts with a hardware reset.
+                    self.initialised = false;
+                    self.recoveries += 1;
+                }
+                Err(err @ InkyError::Timeout(..)) => {
+                    self.abandon_refresh();
+                    return Err(err);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Hardware resets the last [`show`](Self::show) needed after busy
+    /// timeouts.
+    pub fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+
+    /// Sends the buffer to the controller without refreshing the panel, which
+    /// keeps showing its current image.
+    pub fn upload(&mut self) -> Result<()> {
         if !self.initialised {
             self.initialise()?;
             self.initialised = true;
         }
 
         let packed = pack_buffer_nibbles(&self.buffer);
-        self.send_command_data(UC8159_DTM1, &packed)?;
+        self.send_command_data(UC8159_DTM1, &packed)
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        self.upload()?;
 
         self.send_command(UC8159_PON)?;
-        let _ = self.busy_wait(Duration::from_millis(200));
+        let _ = self.busy_wait(self.timeouts.power);
 
         self.send_command(UC8159_DRF)?;
-        self.busy_wait(Duration::from_secs(32))?;
+        self.busy_wait(self.timeouts.refresh)?;
 
         self.send_command(UC8159_POF)?;
-        let _ = self.busy_wait(Duration::from_millis(200));
+        let _ = self.busy_wait(self.timeouts.power);
 
         Ok(())
     }
 
+    /// Resets and powers off a controller that never went idle, so it is not
+    /// left driving the panel and the next show starts from a clean
+    /// initialise instead of waiting out another timeout.
+    fn abandon_refresh(&mut self) {
+        self.initialised = false;
+        let _ = self.hardware_reset();
+        let _ = self.send_command(UC8159_POF);
+    }
+
     fn initialise(&mut self) -> Result<()> {
         self.hardware_reset()?;
 
-        self.busy_wait(Duration::from_secs(1)).ok();
+        self.busy_wait(self.timeouts.reset).ok();
 
         let mut tres = [0u8; 4];
         tres[..2].copy_from_slice(&self.width.to_be_bytes());
@@ -308,9 +446,9 @@ impl InkyUc8159 {
 
     fn hardware_reset(&mut self) -> Result<()> {
         self.reset.set_value(0)?;
-        thread::sleep(Duration::from_millis(100));
+        self.delay.delay(Duration::from_millis(100));
         self.reset.set_value(1)?;
-        thread::sleep(Duration::from_millis(100));
+        self.delay.delay(Duration::from_millis(100));
         Ok(())
     }
 
@@ -318,7 +456,7 @@ impl InkyUc8159 {
         let start = Instant::now();
 
         if self.busy.get_value()? != 0 {
-            thread::sleep(timeout);
+            self.delay.delay(timeout);
             return Ok(());
         }
 
@@ -326,7 +464,7 @@ impl InkyUc8159 {
             if self.busy.get_value()? != 0 {
                 return Ok(());
             }
-            thread::sleep(Duration::from_millis(10));
+            self.delay.delay(Duration::from_millis(10));
         }
 
         Err(InkyError::Timeout("busy", timeout))
@@ -375,28 +513,10 @@ impl InkyUc8159 {
     }
 
     fn quantize_into_buffer(&mut self, rgb: &RgbImage, palette: &[[f32; 3]; 7]) {
-        let width = self.width as usize;
-        let height = self.height as usize;
-        let mut working: Vec<[f32; 3]> = rgb
-            .pixels()
-            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
-            .collect();
-
-        for y in 0..height {
-            for x in 0..width {
-                let idx = y * width + x;
-                let old_pixel = working[idx];
-                let (closest_index, closest_colour) = nearest_colour(palette, old_pixel);
-                self.buffer[idx] = closest_index as u8;
-
-                let error = [
-                    old_pixel[0] - closest_colour[0],
-                    old_pixel[1] - closest_colour[1],
-                    old_pixel[2] - closest_colour[2],
-                ];
-
-                distribute_error(&mut working, width, height, x, y, error);
-            }
+        let (indices, stats) = dither_with_stats(rgb, palette, self.dither);
+        self.report = Some(RenderReport::from_dither(&COLOUR_NAMES, palette, &stats));
+        for (value, index) in self.buffer.iter_mut().zip(indices) {
+            *value = index;
         }
     }
 
@@ -420,7 +540,8 @@ impl InkyUc8159 {
     }
 }
 
-fn build_palette(saturation: f32) -> [[f32; 3]; 7] {
+/// The palette images are quantized against at `saturation`.
+pub fn build_palette(saturation: f32) -> [[f32; 3]; 7] {
     let sat = saturation.clamp(0.0, 1.0);
     let mut palette = [[0.0f32; 3]; 7];
     for i in 0..7 {
@@ -433,7 +554,7 @@ fn build_palette(saturation: f32) -> [[f32; 3]; 7] {
     palette
 }
 
-impl InkyDisplay for InkyUc8159 {
+impl<S: SpiBus, O: OutputPin, I: InputPin, D: Delay> InkyDisplay for InkyUc8159<S, O, I, D> {
     fn width(&self) -> u16 {
         self.width
     }
@@ -450,6 +571,26 @@ impl InkyDisplay for InkyUc8159 {
         InkyUc8159::input_dimensions(self)
     }
 
+    fn set_dither(&mut self, dither: DitherOptions) {
+        InkyUc8159::set_dither(self, dither);
+    }
+
+    fn set_border(&mut self, colour: u8) {
+        InkyUc8159::set_border(self, colour);
+    }
+
+    fn buffer(&self) -> &[u8] {
+        InkyUc8159::buffer(self)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        CAPABILITIES
+    }
+
+    fn set_panel_buffer(&mut self, data: &[u8]) -> Result<()> {
+        copy_panel_buffer(&mut self.buffer, data)
+    }
+
     fn clear(&mut self, colour: u8) {
         InkyUc8159::clear(self, colour)
     }
@@ -469,4 +610,182 @@ impl InkyDisplay for InkyUc8159 {
     fn show(&mut self) -> Result<()> {
         InkyUc8159::show(self)
     }
+
+    fn upload(&mut self) -> Result<()> {
+        InkyUc8159::upload(self)
+    }
+
+    fn recoveries(&self) -> u32 {
+        InkyUc8159::recoveries(self)
+    }
+
+    fn last_report(&self) -> Option<RenderReport> {
+        self.report.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::displays::hal::mock::{Event, Log, MockInputPin, MockOutputPin, MockSpi, NoDelay};
+
+    type MockUc8159 = InkyUc8159<MockSpi, MockOutputPin, MockInputPin, NoDelay>;
+
+    fn mock_display(config: InkyUc8159Config, busy: MockInputPin) -> (MockUc8159, Log) {
+        let log = Log::new();
+        let hal = Uc8159Hal {
+            spi: MockSpi::new(&log),
+            cs: MockOutputPin::new("cs", &log),
+            dc: MockOutputPin::new("dc", &log),
+            reset: MockOutputPin::new("reset", &log),
+            busy,
+            delay: NoDelay,
+        };
+        (InkyUc8159::from_hal(config, hal).unwrap(), log)
+    }
+
+    /// Reads busy (low) as each wait starts, then ready (high).
+    fn busy_then_ready() -> MockInputPin {
+        MockInputPin::pattern(&[0, 1])
+    }
+
+    /// Groups the log into `(command, data)` pairs using the DC line,
+    /// checking every byte is sent with CS asserted.
+    fn transactions(events: &[Event]) -> Vec<(u8, Vec<u8>)> {
+        let (mut cs, mut dc) = (1, 0);
+        let mut out: Vec<(u8, Vec<u8>)> = Vec::new();
+        for event in events {
+            match event {
+                Event::Pin("cs", level) => cs = *level,
+                Event::Pin("dc", level) => dc = *level,
+                Event::Pin(..) => {}
+                Event::Spi(bytes) => {
+                    assert_eq!(cs, 0, "SPI write with CS deasserted");
+                    if dc == 0 {
+                        assert_eq!(bytes.len(), 1, "commands are a single byte");
+                        out.push((bytes[0], Vec::new()));
+                    } else {
+                        out.last_mut().expect("data before command").1.extend(bytes);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn refresh_sequence(colour: u8) -> Vec<(u8, Vec<u8>)> {
+        vec![
+            (UC8159_DTM1, vec![(colour << 4) | colour; 600 * 448 / 2]),
+            (UC8159_PON, vec![]),
+            (UC8159_DRF, vec![]),
+            (UC8159_POF, vec![]),
+        ]
+    }
+
+    #[test]
+    fn first_show_resets_initialises_and_refreshes() {
+        let (mut display, log) = mock_display(InkyUc8159Config::default(), busy_then_ready());
+        display.clear(3);
+        display.show().unwrap();
+
+        let events = log.events();
+        assert_eq!(
+            events[..2],
+            [Event::Pin("reset", 0), Event::Pin("reset", 1)]
+        );
+
+        let mut expected = vec![
+            (UC8159_TRES, vec![0x02, 0x58, 0x01, 0xC0]),
+            (UC8159_PSR, vec![0xEF, 0x08]),
+            (UC8159_PWR, vec![0x37, 0x00, 0x23, 0x23]),
+            (UC8159_PLL, vec![0x3C]),
+            (UC8159_TSE, vec![0x00]),
+            (UC8159_CDI, vec![0x37]),
+            (UC8159_TCON, vec![0x22]),
+            (UC8159_DAM, vec![0x00]),
+            (UC8159_PWS, vec![0xAA]),
+            (UC8159_PFS, vec![0x00]),
+        ];
+        expected.extend(refresh_sequence(3));
+        assert_eq!(transactions(&events), expected);
+        assert_eq!(display.recoveries(), 0);
+    }
+
+    #[test]
+    fn later_shows_skip_initialisation() {
+        let (mut display, log) = mock_display(InkyUc8159Config::default(), busy_then_ready());
+        display.show().unwrap();
+        log.clear();
+
+        display.clear(5);
+        display.show().unwrap();
+        let events = log.events();
+        assert!(!events.contains(&Event::Pin("reset", 0)));
+        assert_eq!(transactions(&events), refresh_sequence(5));
+    }
+
+    #[test]
+    fn small_panel_uses_its_resolution_setting() {
+        let config = InkyUc8159Config {
+            width: 640,
+            height: 400,
+            ..Default::default()
+        };
+        let (mut display, log) = mock_display(config, busy_then_ready());
+        display.show().unwrap();
+
+        let sent = transactions(&log.events());
+        assert_eq!(sent[0], (UC8159_TRES, vec![0x02, 0x80, 0x01, 0x90]));
+        assert_eq!(sent[1], (UC8159_PSR, vec![0xAF, 0x08]));
+    }
+
+    #[test]
+    fn busy_timeout_resets_and_retries() {
+        let config = InkyUc8159Config {
+            busy_retries: 1,
+            timeouts: Timeouts {
+                reset: Duration::from_millis(1),
+                power: Duration::from_millis(1),
+                refresh: Duration::from_millis(1),
+            },
+            ..Default::default()
+        };
+        // Never reports ready.
+        let (mut display, log) = mock_display(config, MockInputPin::constant(0));
+
+        let err = display.show().unwrap_err();
+        assert!(matches!(err, InkyError::Timeout("busy", _)), "{err:?}");
+        assert_eq!(display.recoveries(), 1);
+        let resets = log
+            .events()
+            .iter()
+            .filter(|event| **event == Event::Pin("reset", 0))
+            .count();
+        // Two attempts, then a reset and power-off once retries run out.
+        assert_eq!(resets, 3);
+        let sent = transactions(&log.events());
+        assert_eq!(sent.last(), Some(&(UC8159_POF, vec![])));
+    }
+
+    #[test]
+    fn unsupported_resolution_is_rejected() {
+        let config = InkyUc8159Config {
+            width: 800,
+            height: 480,
+            ..Default::default()
+        };
+        let log = Log::new();
+        let hal = Uc8159Hal {
+            spi: MockSpi::new(&log),
+            cs: MockOutputPin::new("cs", &log),
+            dc: MockOutputPin::new("dc", &log),
+            reset: MockOutputPin::new("reset", &log),
+            busy: MockInputPin::constant(1),
+            delay: NoDelay,
+        };
+        assert!(matches!(
+            InkyUc8159::from_hal(config, hal),
+            Err(InkyError::UnsupportedResolution(800, 480))
+        ));
+    }
 }
@@ -0,0 +1,316 @@
+//! Palette quantization with a choice of error-diffusion method, for frames
+//! assembled outside the drivers (comparisons, charts, analysis).
+
+use std::fmt;
+use std::str::FromStr;
+
+use image::RgbImage;
+
+use crate::displays::{InkyError, nearest_colour};
+use crate::tone::ToneCurve;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DitherMethod {
+    /// Floyd-Steinberg, as used by the drivers.
+    #[default]
+    FloydSteinberg,
+    /// Atkinson: diffuses only 3/4 of the error, giving cleaner flat areas
+    /// and more contrast at the cost of some shadow/highlight detail.
+    Atkinson,
+    /// Nearest palette colour, no diffusion.
+    None,
+}
+
+impl fmt::Display for DitherMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DitherMethod::FloydSteinberg => f.write_str("floyd"),
+            DitherMethod::Atkinson => f.write_str("atkinson"),
+            DitherMethod::None => f.write_str("none"),
+        }
+    }
+}
+
+impl FromStr for DitherMethod {
+    type Err = InkyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "floyd" | "floyd-steinberg" | "fs" => Ok(DitherMethod::FloydSteinberg),
+            "atkinson" => Ok(DitherMethod::Atkinson),
+            "none" | "nearest" => Ok(DitherMethod::None),
+            _ => Err(InkyError::InvalidDither(format!(
+                "unknown method `{value}` (expected floyd, atkinson or none)"
+            ))),
+        }
+    }
+}
+
+/// Error-diffusion settings used when quantizing a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DitherOptions {
+    pub method: DitherMethod,
+    /// `None` scans every row left to right, so identical input always gives
+    /// an identical buffer. With a seed, each row's scan direction is picked
+    /// pseudo-randomly, which stops frames that barely change (clocks,
+    /// dashboards) from building up the same dither texture every refresh.
+    pub seed: Option<u64>,
+    /// Palette entries to substitute for others, e.g. black for a green the
+    /// panel renders poorly.
+    pub remap: PaletteRemap,
+    /// Run a first pass that fits the image's tones to the palette's
+    /// brightness levels (see [`ToneCurve`]) before diffusing.
+    pub adaptive_tone: bool,
+}
+
+/// Most entries any supported palette has.
+const MAX_COLOURS: usize = 8;
+
+/// One `from=to` substitution of a named palette colour, as given to
+/// `--map` (e.g. `green=black`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColourMapping {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for ColourMapping {
+    type Err = InkyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => Ok(Self {
+                from: from.trim().to_ascii_lowercase(),
+                to: to.trim().to_ascii_lowercase(),
+            }),
+            _ => Err(InkyError::InvalidPaletteMap(format!(
+                "`{value}` is not of the form FROM=TO, e.g. green=black"
+            ))),
+        }
+    }
+}
+
+/// Palette substitutions applied while quantizing: a pixel that would use
+/// entry `i` uses entry `targets[i]` instead, and error diffusion works
+/// with the substituted colour so the rest of the image compensates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PaletteRemap {
+    targets: [u8; MAX_COLOURS],
+}
+
+impl PaletteRemap {
+    /// Every entry maps to itself.
+    pub const IDENTITY: Self = Self {
+        targets: [0, 1, 2, 3, 4, 5, 6, 7],
+    };
+
+    /// Resolves `mappings` against the colour `names` of a panel palette,
+    /// in palette order. Substitutions do not chain: with `green=black` and
+    /// `black=white`, green pixels are shown black.
+    pub fn resolve(mappings: &[ColourMapping], names: &[&str]) -> Result<Self, InkyError> {
+        let index = |name: &str| {
+            names
+                .iter()
+                .position(|candidate| candidate.eq_ignore_ascii_case(name))
+                .filter(|&index| index < MAX_COLOURS)
+                .ok_or_else(|| {
+                    InkyError::InvalidPaletteMap(format!(
+                        "unknown colour `{name}` (this panel has {})",
+                        names.join(", ")
+                    ))
+                })
+        };
+        let mut remap = Self::IDENTITY;
+        for mapping in mappings {
+            remap.targets[index(&mapping.from)?] = index(&mapping.to)? as u8;
+        }
+        Ok(remap)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// The entry shown in place of `index`.
+    pub fn target(&self, index: usize) -> usize {
+        self.targets
+            .get(index)
+            .map_or(index, |&target| target as usize)
+    }
+}
+
+impl Default for PaletteRemap {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Diffusion kernels as `(dx, dy, weight)` for a left-to-right scan.
+const FLOYD_STEINBERG: [(isize, isize, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+/// Atkinson spreads 1/8 to each of six neighbours and drops the remaining 1/4.
+const ATKINSON: [(isize, isize, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Quantizes `rgb` to `palette` with `method`, deterministically.
+pub fn dither(rgb: &RgbImage, palette: &[[f32; 3]], method: DitherMethod) -> Vec<u8> {
+    dither_with(
+        rgb,
+        palette,
+        DitherOptions {
+            method,
+            ..Default::default()
+        },
+    )
+}
+
+/// Quantizes `rgb` to `palette`, returning one palette index per pixel in
+/// row-major order.
+pub fn dither_with(rgb: &RgbImage, palette: &[[f32; 3]], options: DitherOptions) -> Vec<u8> {
+    dither_with_stats(rgb, palette, options).0
+}
+
+/// What quantizing one frame did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DitherStats {
+    /// Pixels given each palette entry, in palette order.
+    pub counts: Vec<usize>,
+    /// Mean RGB distance between each input pixel and the colour it was
+    /// given; diffusion keeps areas right on average, so this measures how
+    /// grainy the frame is rather than how far off its colours are.
+    pub mean_error: f32,
+}
+
+/// [`dither_with`], also returning [`DitherStats`].
+pub fn dither_with_stats(
+    rgb: &RgbImage,
+    palette: &[[f32; 3]],
+    options: DitherOptions,
+) -> (Vec<u8>, DitherStats) {
+    let width = rgb.width() as usize;
+    let height = rgb.height() as usize;
+    let curve = if options.adaptive_tone {
+        ToneCurve::for_palette(rgb, palette)
+    } else {
+        ToneCurve::identity()
+    };
+    let mut working: Vec<[f32; 3]> = rgb
+        .pixels()
+        .map(|p| curve.apply([p[0] as f32, p[1] as f32, p[2] as f32]))
+        .collect();
+    let mut indices = vec![0u8; working.len()];
+    let kernel: &[(isize, isize, f32)] = match options.method {
+        DitherMethod::FloydSteinberg => &FLOYD_STEINBERG,
+        DitherMethod::Atkinson => &ATKINSON,
+        DitherMethod::None => &[],
+    };
+    let mut rng = options.seed.map(XorShift::new);
+    let mut counts = vec![0usize; palette.len()];
+    let mut error_sum = 0.0f64;
+    // Substituted entries take their target's colour; ties in
+    // `nearest_colour` may then pick either, so indices are mapped too.
+    let remapped: Vec<[f32; 3]>;
+    let palette = if options.remap.is_identity() {
+        palette
+    } else {
+        remapped = (0..palette.len())
+            .map(|index| palette[options.remap.target(index)])
+            .collect();
+        &remapped
+    };
+
+    for y in 0..height {
+        let reverse = rng.as_mut().is_some_and(|rng| rng.next() >> 63 == 1);
+        for step in 0..width {
+            let x = if reverse { width - 1 - step } else { step };
+            let idx = y * width + x;
+            let old = working[idx];
+            let (index, colour) = nearest_colour(palette, old);
+            let index = options.remap.target(index);
+            indices[idx] = index as u8;
+            counts[index] += 1;
+            let source = rgb.as_raw()[idx * 3..idx * 3 + 3].iter();
+            error_sum += source
+                .zip(colour)
+                .map(|(&channel, target)| (channel as f32 - target).powi(2))
+                .sum::<f32>()
+                .sqrt() as f64;
+
+            let error = [old[0] - colour[0], old[1] - colour[1], old[2] - colour[2]];
+            for &(dx, dy, weight) in kernel {
+                // Mirror the kernel when scanning right to left.
+                let nx = x as isize + if reverse { -dx } else { dx };
+                let ny = y + dy as usize;
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    continue;
+                }
+                let target = &mut working[ny * width + nx as usize];
+                for channel in 0..3 {
+                    target[channel] = (target[channel] + error[channel] * weight).clamp(0.0, 255.0);
+                }
+            }
+        }
+    }
+    let stats = DitherStats {
+        counts,
+        mean_error: (error_sum / indices.len().max(1) as f64) as f32,
+    };
+    (indices, stats)
+}
+
+/// xorshift64*: tiny, fast and plenty for picking scan directions.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift, so mix the seed first.
+        Self((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 3] = ["black", "white", "green"];
+    const PALETTE: [[f32; 3]; 3] = [[0.0; 3], [255.0; 3], [0.0, 255.0, 0.0]];
+
+    #[test]
+    fn remapped_colours_are_never_used() {
+        let rgb = RgbImage::from_fn(16, 16, |x, _| image::Rgb([0, (x * 16) as u8, 0]));
+        let mappings = ["green=black".parse().unwrap()];
+        let options = DitherOptions {
+            remap: PaletteRemap::resolve(&mappings, &NAMES).unwrap(),
+            ..Default::default()
+        };
+
+        let indices = dither_with(&rgb, &PALETTE, options);
+        assert!(indices.contains(&0));
+        assert!(!indices.contains(&2));
+    }
+
+    #[test]
+    fn rejects_unknown_colours() {
+        assert!("green".parse::<ColourMapping>().is_err());
+        let mappings = ["orange=black".parse().unwrap()];
+        let err = PaletteRemap::resolve(&mappings, &NAMES).unwrap_err();
+        assert!(err.to_string().contains("black, white, green"), "{err}");
+    }
+}
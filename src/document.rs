@@ -0,0 +1,212 @@
+//! Minimal TOML subset shared by the state store and playlist files: comments,
+//! `key = value` pairs holding strings, integers, floats or booleans, and
+//! `[table]` / `[[array]]` headers. Nested values (inline tables, arrays) are
+//! not supported.
+
+use std::time::Duration;
+
+use crate::state::StateValue;
+
+#[derive(Debug)]
+pub(crate) struct Section {
+    /// `None` for keys that appear before the first header.
+    pub name: Option<String>,
+    pub array: bool,
+    pub line: usize,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Entry {
+    pub key: String,
+    pub value: StateValue,
+    pub line: usize,
+}
+
+impl Section {
+    fn new(name: Option<String>, array: bool, line: usize) -> Self {
+        Self {
+            name,
+            array,
+            line,
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// Parses `contents` into sections; errors are `line N: reason` strings for
+/// the caller to wrap in its own error variant.
+pub(crate) fn parse(contents: &str) -> Result<Vec<Section>, String> {
+    let mut sections = Vec::new();
+    let mut current = Section::new(None, false, 0);
+
+    for (index, raw) in contents.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |reason: &str| format!("line {line_no}: {reason}");
+
+        if let Some(header) = line.strip_prefix('[') {
+            let (name, array) = match header.strip_prefix('[') {
+                Some(inner) => (inner.split_once("]]"), true),
+                None => (header.split_once(']'), false),
+            };
+            let (name, rest) = name.ok_or_else(|| err("unterminated table header"))?;
+            if !is_comment_or_empty(rest) {
+                return Err(err("unexpected text after table header"));
+            }
+            let name = name.trim();
+            if !is_valid_key(name) {
+                return Err(err("invalid table name"));
+            }
+            let next = Section::new(Some(name.to_string()), array, line_no);
+            sections.push(std::mem::replace(&mut current, next));
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| err("expected `key = value`"))?;
+        let key = key.trim();
+        if !is_valid_key(key) {
+            return Err(err("invalid key"));
+        }
+        let value = parse_value(value.trim()).ok_or_else(|| err("invalid value"))?;
+
+        if current.entries.iter().any(|entry| entry.key == key) {
+            return Err(err(&format!("duplicate key `{key}`")));
+        }
+        current.entries.push(Entry {
+            key: key.to_string(),
+            value,
+            line: line_no,
+        });
+    }
+
+    sections.push(current);
+    Ok(sections)
+}
+
+pub(crate) fn format_value(value: &StateValue) -> String {
+    match value {
+        StateValue::String(value) => format!("\"{}\"", escape(value)),
+        StateValue::Integer(value) => format!("{value}"),
+        StateValue::Float(value) => format!("{value:?}"),
+        StateValue::Boolean(value) => format!("{value}"),
+    }
+}
+
+/// A positive number of seconds, integer or float.
+pub(crate) fn duration_value(entry: &Entry) -> Result<Duration, String> {
+    seconds(&entry.value).ok_or_else(|| {
+        format!(
+            "line {}: `{}` must be a positive number of seconds",
+            entry.line, entry.key
+        )
+    })
+}
+
+/// Longest duration [`seconds`] returns. Callers add these to the current
+/// time, which would overflow for values like `1e19`.
+const MAX_SECONDS: Duration = Duration::from_secs(366 * 86_400);
+
+/// A positive number of seconds, capped at [`MAX_SECONDS`].
+pub(crate) fn seconds(value: &StateValue) -> Option<Duration> {
+    let seconds = match *value {
+        StateValue::Integer(value) => value as f64,
+        StateValue::Float(value) => value,
+        _ => return None,
+    };
+    // Rejects NaN, negatives and anything too large for a Duration.
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .map(|duration| duration.min(MAX_SECONDS))
+}
+
+pub(crate) fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+fn is_comment_or_empty(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+fn parse_value(raw: &str) -> Option<StateValue> {
+    if let Some(inner) = raw.strip_prefix('"') {
+        let end = closing_quote(inner)?;
+        if !is_comment_or_empty(&inner[end + 1..]) {
+            return None;
+        }
+        return unescape(&inner[..end]).map(StateValue::String);
+    }
+
+    let raw = raw.split_once('#').map_or(raw, |(value, _)| value).trim();
+    match raw {
+        "true" => return Some(StateValue::Boolean(true)),
+        "false" => return Some(StateValue::Boolean(false)),
+        _ => {}
+    }
+    if let Ok(value) = raw.parse::<i64>() {
+        return Some(StateValue::Integer(value));
+    }
+    raw.parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .map(StateValue::Float)
+}
+
+/// Byte index of the first unescaped `"` in `value`.
+fn closing_quote(value: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                _ => return None,
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,385 @@
+//! Basic shape rasterisation for generated frames (clock faces, charts,
+//! overlays, short labels). Everything is clipped to the image bounds and drawn without
+//! anti-aliasing, since output is quantized to the panel palette anyway.
+
+use image::{Rgb, RgbImage};
+
+pub fn fill_rect(image: &mut RgbImage, x: i64, y: i64, width: i64, height: i64, colour: Rgb<u8>) {
+    let (img_w, img_h) = (image.width() as i64, image.height() as i64);
+    let x0 = x.clamp(0, img_w);
+    let y0 = y.clamp(0, img_h);
+    let x1 = (x + width).clamp(0, img_w);
+    let y1 = (y + height).clamp(0, img_h);
+    for py in y0..y1 {
+        for px in x0..x1 {
+            image.put_pixel(px as u32, py as u32, colour);
+        }
+    }
+}
+
+/// Outline of a rectangle, drawn inside the given bounds.
+pub fn stroke_rect(
+    image: &mut RgbImage,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+    thickness: i64,
+    colour: Rgb<u8>,
+) {
+    fill_rect(image, x, y, width, thickness, colour);
+    fill_rect(image, x, y + height - thickness, width, thickness, colour);
+    fill_rect(image, x, y, thickness, height, colour);
+    fill_rect(image, x + width - thickness, y, thickness, height, colour);
+}
+
+pub fn fill_circle(image: &mut RgbImage, cx: f32, cy: f32, radius: f32, colour: Rgb<u8>) {
+    fill_ring(image, cx, cy, radius, 0.0, colour);
+}
+
+/// Annulus between `inner` and `outer` radii.
+pub fn fill_ring(image: &mut RgbImage, cx: f32, cy: f32, outer: f32, inner: f32, colour: Rgb<u8>) {
+    let (img_w, img_h) = (image.width() as i64, image.height() as i64);
+    let x0 = ((cx - outer).floor() as i64).clamp(0, img_w);
+    let x1 = ((cx + outer).ceil() as i64 + 1).clamp(0, img_w);
+    let y0 = ((cy - outer).floor() as i64).clamp(0, img_h);
+    let y1 = ((cy + outer).ceil() as i64 + 1).clamp(0, img_h);
+    let outer_sq = outer * outer;
+    let inner_sq = inner * inner;
+
+    for py in y0..y1 {
+        for px in x0..x1 {
+            let dx = px as f32 - cx;
+            let dy = py as f32 - cy;
+            let dist_sq = dx * dx + dy * dy;
+            if dist_sq <= outer_sq && dist_sq >= inner_sq {
+                image.put_pixel(px as u32, py as u32, colour);
+            }
+        }
+    }
+}
+
+/// Line with round caps, `thickness` pixels wide.
+pub fn draw_line(
+    image: &mut RgbImage,
+    from: (f32, f32),
+    to: (f32, f32),
+    thickness: f32,
+    colour: Rgb<u8>,
+) {
+    let radius = (thickness / 2.0).max(0.5);
+    let dx = to.0 - from.0;
+    let dy = to.1 - from.1;
+    let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        fill_circle(image, from.0 + dx * t, from.1 + dy * t, radius, colour);
+    }
+}
+
+/// Segments lit for each digit, in order a (top), b, c, d (bottom), e, f, g
+/// (middle), clockwise from the top like a standard seven-segment display.
+const SEGMENTS: [[bool; 7]; 10] = [
+    [true, true, true, true, true, true, false],
+    [false, true, true, false, false, false, false],
+    [true, true, false, true, true, false, true],
+    [true, true, true, true, false, false, true],
+    [false, true, true, false, false, true, true],
+    [true, false, true, true, false, true, true],
+    [true, false, true, true, true, true, true],
+    [true, true, true, false, false, false, false],
+    [true, true, true, true, true, true, true],
+    [true, true, true, true, false, true, true],
+];
+
+/// Draws `digit` (0-9) as a seven-segment glyph filling the given box.
+/// Values above 9 draw nothing.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_segment_digit(
+    image: &mut RgbImage,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+    thickness: i64,
+    digit: u8,
+    colour: Rgb<u8>,
+) {
+    let Some(lit) = SEGMENTS.get(digit as usize) else {
+        return;
+    };
+    let t = thickness;
+    let half = height / 2;
+    let rects = [
+        (x, y, width, t),
+        (x + width - t, y, t, half + t / 2),
+        (x + width - t, y + half - t / 2, t, height - half + t / 2),
+        (x, y + height - t, width, t),
+        (x, y + half - t / 2, t, height - half + t / 2),
+        (x, y, t, half + t / 2),
+        (x, y + half - t / 2, width, t),
+    ];
+    for (on, (rx, ry, rw, rh)) in lit.iter().zip(rects) {
+        if *on {
+            fill_rect(image, rx, ry, rw, rh, colour);
+        }
+    }
+}
+
+/// Width of `text` drawn by [`draw_segment_text`] with `digit_w`-wide
+/// digits.
+pub fn segment_text_width(text: &str, digit_w: f32) -> f32 {
+    let gap = digit_w * 0.25;
+    let total: f32 = text
+        .chars()
+        .map(|c| segment_advance(c, digit_w) + gap)
+        .sum();
+    (total - gap).max(0.0)
+}
+
+/// Widest digits with which `text` drawn by [`draw_segment_text`] fits in
+/// `width`. Dots and colons are a whole number of pixels, at least one, so
+/// the drawn width is not proportional to the digit width; the proportional
+/// estimate is refined against the width actually drawn.
+pub fn fit_segment_digit_width(text: &str, width: f32) -> f32 {
+    let mut digit_w = width * 100.0 / segment_text_width(text, 100.0).max(1.0);
+    for _ in 0..8 {
+        let drawn = segment_text_width(text, digit_w);
+        if drawn <= width {
+            break;
+        }
+        digit_w *= width / drawn;
+    }
+    digit_w.max(1.0)
+}
+
+/// Draws digits, `.`, `:` and `-` as seven-segment glyphs from (`x`, `y`),
+/// with digits `digit_w` wide and 1.8 times as tall. Any other character
+/// leaves a half-width space.
+pub fn draw_segment_text(
+    image: &mut RgbImage,
+    text: &str,
+    x: f32,
+    y: f32,
+    digit_w: f32,
+    colour: Rgb<u8>,
+) {
+    let digit_h = digit_w * 1.8;
+    let gap = digit_w * 0.25;
+    let thickness = segment_thickness(digit_w);
+    let (mut x, top) = (x, y as i64);
+    for c in text.chars() {
+        let left = x as i64;
+        match c {
+            '0'..='9' => draw_segment_digit(
+                image,
+                left,
+                top,
+                digit_w as i64,
+                digit_h as i64,
+                thickness,
+                c as u8 - b'0',
+                colour,
+            ),
+            '.' => fill_rect(
+                image,
+                left,
+                top + digit_h as i64 - thickness,
+                thickness,
+                thickness,
+                colour,
+            ),
+            ':' => {
+                let upper = top + (digit_h * 0.3) as i64;
+                let lower = top + (digit_h * 0.7) as i64 - thickness;
+                fill_rect(image, left, upper, thickness, thickness, colour);
+                fill_rect(image, left, lower, thickness, thickness, colour);
+            }
+            '-' => fill_rect(
+                image,
+                left,
+                top + (digit_h / 2.0) as i64 - thickness / 2,
+                digit_w as i64,
+                thickness,
+                colour,
+            ),
+            _ => {}
+        }
+        x += segment_advance(c, digit_w) + gap;
+    }
+}
+
+fn segment_thickness(digit_w: f32) -> i64 {
+    (digit_w * 0.18).max(1.0) as i64
+}
+
+fn segment_advance(c: char, digit_w: f32) -> f32 {
+    match c {
+        '0'..='9' | '-' => digit_w,
+        '.' | ':' => segment_thickness(digit_w) as f32,
+        _ => digit_w * 0.5,
+    }
+}
+
+/// 5x7 bitmap glyphs for `A`-`Z`, one row per byte with the leftmost pixel
+/// in bit 4.
+const LETTERS: [[u8; 7]; 26] = [
+    [
+        0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ],
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+    ],
+    [
+        0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+    ],
+    [
+        0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+    ],
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+    ],
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+    ],
+    [
+        0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+    ],
+    [
+        0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ],
+    [
+        0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ],
+    [
+        0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+    ],
+    [
+        0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+    ],
+    [
+        0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+    ],
+    [
+        0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+    ],
+    [
+        0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+    ],
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ],
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+    ],
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+    ],
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+    ],
+    [
+        0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+    ],
+    [
+        0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+    ],
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ],
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+    ],
+    [
+        0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+    ],
+    [
+        0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+    ],
+    [
+        0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+    ],
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+    ],
+];
+
+const DIGITS: [[u8; 7]; 10] = [
+    [
+        0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+    ],
+    [
+        0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ],
+    [
+        0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+    ],
+    [
+        0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+    ],
+    [
+        0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+    ],
+    [
+        0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+    ],
+    [
+        0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+    ],
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+    ],
+    [
+        0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+    ],
+    [
+        0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+    ],
+];
+
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        c @ 'A'..='Z' => LETTERS[c as usize - 'A' as usize],
+        c @ '0'..='9' => DIGITS[c as usize - '0' as usize],
+        '-' => [
+            0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+        ],
+        '.' => [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+        ],
+        '_' => [
+            0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+        ],
+        _ => [0; 7],
+    }
+}
+
+/// Width of `text` drawn by [`draw_text`] at `scale`.
+pub fn text_width(text: &str, scale: i64) -> i64 {
+    (text.chars().count() as i64 * 6 - 1).max(0) * scale
+}
+
+/// Draws `text` in a 5x7 bitmap font with its top left at (`x`, `y`), each
+/// font pixel `scale` pixels square (so 7 * `scale` tall). Covers letters,
+/// drawn in upper case, digits, `-`, `.` and `_`; anything else leaves a
+/// space. Meant for short labels such as host names, where seven-segment
+/// digits cannot spell.
+pub fn draw_text(image: &mut RgbImage, text: &str, x: i64, y: i64, scale: i64, colour: Rgb<u8>) {
+    for (index, c) in text.chars().enumerate() {
+        let left = x + index as i64 * 6 * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0b10000 >> col) != 0 {
+                    fill_rect(
+                        image,
+                        left + col * scale,
+                        y + row as i64 * scale,
+                        scale,
+                        scale,
+                        colour,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Update events. Refresh accounting, health tracking and notifications
+//! subscribe to an [`EventBus`] rather than being called from every place
+//! that updates the panel, and library consumers can subscribe their own
+//! handlers (metrics, an audit log) the same way.
+
+use std::time::{Duration, Instant};
+
+use crate::displays::{InkyDisplay, InkyError, ProbeInfo, Result, ShowOutcome};
+
+/// An update is about to be sent to `panel`.
+#[derive(Clone, Copy, Debug)]
+pub struct UpdateStarted<'a> {
+    /// The panel's state key, e.g. `uc8159_600x448`.
+    pub panel: &'a str,
+}
+
+/// An update finished without error.
+#[derive(Clone, Copy, Debug)]
+pub struct UpdateCompleted<'a> {
+    pub panel: &'a str,
+    pub outcome: ShowOutcome,
+    /// Full refreshes the update took; zero when the frame was unchanged.
+    pub refreshes: u32,
+    pub elapsed: Duration,
+    /// Hash of the frame now on the panel, see [`InkyDisplay::frame_hash`].
+    pub frame_hash: u64,
+    /// Hardware resets needed to recover from busy timeouts.
+    pub recoveries: u32,
+}
+
+/// An update was given up on. Emitted once per failed update by whoever
+/// gives up, since a failure may come from loading the frame rather than
+/// from the panel.
+#[derive(Clone, Copy, Debug)]
+pub struct UpdateError<'a> {
+    pub panel: &'a str,
+    pub error: &'a InkyError,
+}
+
+type ProbeHandler = Box<dyn FnMut(&ProbeInfo) + Send>;
+type StartedHandler = Box<dyn for<'a> FnMut(&UpdateStarted<'a>) + Send>;
+type CompletedHandler = Box<dyn for<'a> FnMut(&UpdateCompleted<'a>) + Send>;
+type ErrorHandler = Box<dyn for<'a> FnMut(&UpdateError<'a>) + Send>;
+
+/// Handlers for each kind of event, called in the order they subscribed.
+#[derive(Default)]
+pub struct EventBus {
+    probe: Vec<ProbeHandler>,
+    update_started: Vec<StartedHandler>,
+    update_completed: Vec<CompletedHandler>,
+    error: Vec<ErrorHandler>,
+}
+
+impl EventBus {
+    pub const fn new() -> Self {
+        Self {
+            probe: Vec::new(),
+            update_started: Vec::new(),
+            update_completed: Vec::new(),
+            error: Vec::new(),
+        }
+    }
+
+    pub fn on_probe(&mut self, handler: impl FnMut(&ProbeInfo) + Send + 'static) -> &mut Self {
+        self.probe.push(Box::new(handler));
+        self
+    }
+
+    pub fn on_update_started(
+        &mut self,
+        handler: impl FnMut(&UpdateStarted<'_>) + Send + 'static,
+    ) -> &mut Self {
+        self.update_started.push(Box::new(handler));
+        self
+    }
+
+    pub fn on_update_completed(
+        &mut self,
+        handler: impl FnMut(&UpdateCompleted<'_>) + Send + 'static,
+    ) -> &mut Self {
+        self.update_completed.push(Box::new(handler));
+        self
+    }
+
+    pub fn on_error(
+        &mut self,
+        handler: impl FnMut(&UpdateError<'_>) + Send + 'static,
+    ) -> &mut Self {
+        self.error.push(Box::new(handler));
+        self
+    }
+
+    pub fn probe(&mut self, info: &ProbeInfo) {
+        self.probe.iter_mut().for_each(|handler| handler(info));
+    }
+
+    pub fn update_started(&mut self, event: UpdateStarted<'_>) {
+        self.update_started
+            .iter_mut()
+            .for_each(|handler| handler(&event));
+    }
+
+    pub fn update_completed(&mut self, event: UpdateCompleted<'_>) {
+        self.update_completed
+            .iter_mut()
+            .for_each(|handler| handler(&event));
+    }
+
+    pub fn error(&mut self, panel: &str, error: &InkyError) {
+        let event = UpdateError { panel, error };
+        self.error.iter_mut().for_each(|handler| handler(&event));
+    }
+
+    /// [`show_if_changed`](InkyDisplay::show_if_changed), surrounded by the
+    /// started and completed events. Errors are returned for the caller to
+    /// emit with [`error`](Self::error) once it gives up.
+    pub fn show(
+        &mut self,
+        display: &mut dyn InkyDisplay,
+        panel: &str,
+        previous: Option<u64>,
+    ) -> Result<ShowOutcome> {
+        self.run(display, panel, |display| {
+            let outcome = display.show_if_changed(previous)?;
+            let refreshes = match outcome {
+                ShowOutcome::Refreshed => 1,
+                ShowOutcome::NotModified => 0,
+            };
+            Ok((outcome, refreshes))
+        })
+    }
+
+    /// [`flush`](InkyDisplay::flush), surrounded by the started and
+    /// completed events. Zero cycles touch nothing and emit no events.
+    pub fn flush(&mut self, display: &mut dyn InkyDisplay, panel: &str, cycles: u32) -> Result<()> {
+        if cycles == 0 {
+            return Ok(());
+        }
+        self.run(display, panel, |display| {
+            display.flush(cycles)?;
+            Ok((ShowOutcome::Refreshed, cycles * 2))
+        })
+        .map(|_| ())
+    }
+
+    fn run(
+        &mut self,
+        display: &mut dyn InkyDisplay,
+        panel: &str,
+        update: impl FnOnce(&mut dyn InkyDisplay) -> Result<(ShowOutcome, u32)>,
+    ) -> Result<ShowOutcome> {
+        self.update_started(UpdateStarted { panel });
+        let started = Instant::now();
+        let (outcome, refreshes) = update(display)?;
+        self.update_completed(UpdateCompleted {
+            panel,
+            outcome,
+            refreshes,
+            elapsed: started.elapsed(),
+            frame_hash: display.frame_hash(),
+            recoveries: display.recoveries(),
+        });
+        Ok(outcome)
+    }
+}
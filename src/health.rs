@@ -0,0 +1,270 @@
+//! Refresh accounting. E-paper panels wear out with the number of refreshes
+//! rather than time powered, so every refresh is counted per panel and
+//! schedules can be checked against a daily budget.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::content::Result;
+use crate::scheduler::Scheduler;
+use crate::state::StateStore;
+
+const STATE_PREFIX: &str = "refresh";
+const BUDGET_KEY: &str = "refresh.budget_per_day";
+const DAY: Duration = Duration::from_secs(86_400);
+/// Days looked ahead when finding a schedule's busiest day; covers weekly
+/// patterns such as weekday-only dashboards.
+const BUDGET_DAYS: u32 = 7;
+
+/// Lifetime refresh counters for one panel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RefreshStats {
+    pub count: u64,
+    /// Time spent in refresh cycles, from sending the frame to the panel
+    /// reporting idle.
+    pub total_time: Duration,
+    pub last_refresh: Option<SystemTime>,
+}
+
+impl RefreshStats {
+    /// Loads the counters stored for `device` (see
+    /// [`device_key`](crate::correction::device_key)); zero if none.
+    pub fn load(store: &StateStore, device: &str) -> Self {
+        let count = store.get_i64(&state_key(device, "count")).unwrap_or(0);
+        let millis = store.get_i64(&state_key(device, "millis")).unwrap_or(0);
+        let last = store.get_i64(&state_key(device, "last"));
+        Self {
+            count: count.max(0) as u64,
+            total_time: Duration::from_millis(millis.max(0) as u64),
+            last_refresh: last.map(|secs| UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)),
+        }
+    }
+
+    /// Adds `refreshes` refreshes taking `elapsed` in total, finishing at
+    /// `at`, and stores the result; call [`StateStore::save`] to persist.
+    pub fn record(
+        store: &mut StateStore,
+        device: &str,
+        refreshes: u32,
+        elapsed: Duration,
+        at: SystemTime,
+    ) -> Result<Self> {
+        let mut stats = Self::load(store, device);
+        stats.count += refreshes as u64;
+        stats.total_time += elapsed;
+        stats.last_refresh = Some(at);
+
+        store.set_i64(&state_key(device, "count"), stats.count as i64)?;
+        store.set_i64(
+            &state_key(device, "millis"),
+            stats.total_time.as_millis() as i64,
+        )?;
+        if let Ok(since) = at.duration_since(UNIX_EPOCH) {
+            store.set_i64(&state_key(device, "last"), since.as_secs() as i64)?;
+        }
+        Ok(stats)
+    }
+
+    /// Mean duration of one refresh, if any were recorded.
+    pub fn average(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.total_time / self.count.min(u32::MAX as u64) as u32)
+    }
+}
+
+impl fmt::Display for RefreshStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.count == 0 {
+            return f.write_str("none recorded");
+        }
+        let total = self.total_time.as_secs();
+        write!(
+            f,
+            "{} ({}h {:02}m refreshing",
+            self.count,
+            total / 3600,
+            total / 60 % 60
+        )?;
+        if let Some(average) = self.average() {
+            write!(f, ", {:.1}s average", average.as_secs_f32())?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Maximum refreshes a panel should see per day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RefreshBudget {
+    pub per_day: u32,
+}
+
+impl Default for RefreshBudget {
+    /// One refresh every half hour, around the clock.
+    fn default() -> Self {
+        Self { per_day: 48 }
+    }
+}
+
+/// A schedule that would refresh more often than the budget allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BudgetOverrun {
+    /// Refreshes on the busiest day.
+    pub planned: u32,
+    pub budget: u32,
+}
+
+impl fmt::Display for BudgetOverrun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} refreshes per day, over the budget of {}",
+            self.planned, self.budget
+        )
+    }
+}
+
+impl RefreshBudget {
+    /// The budget stored under `refresh.budget_per_day`, or the default.
+    pub fn load(store: &StateStore) -> Self {
+        match store.get_i64(BUDGET_KEY) {
+            Some(per_day) if per_day > 0 => Self {
+                per_day: per_day.min(u32::MAX as i64) as u32,
+            },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn store(&self, store: &mut StateStore) -> Result<()> {
+        store.set_i64(BUDGET_KEY, self.per_day as i64)
+    }
+
+    /// Checks the busiest of the next seven days of `scheduler`'s jobs,
+    /// assuming every job refreshes the panel.
+    pub fn check_scheduler(&self, scheduler: &Scheduler, now: SystemTime) -> Option<BudgetOverrun> {
+        let planned = (0..BUDGET_DAYS)
+            .map(|day| {
+                let from = now + DAY * day;
+                scheduler.occurrences_between(from, from + DAY)
+            })
+            .max()
+            .unwrap_or(0);
+        self.check(planned)
+    }
+
+    /// Checks a fixed refresh `interval`, as used by slideshows.
+    pub fn check_interval(&self, interval: Duration) -> Option<BudgetOverrun> {
+        let planned = if interval.is_zero() {
+            u32::MAX
+        } else {
+            (DAY.as_secs_f64() / interval.as_secs_f64()).ceil() as u32
+        };
+        self.check(planned)
+    }
+
+    fn check(&self, planned: u32) -> Option<BudgetOverrun> {
+        (planned > self.per_day).then_some(BudgetOverrun {
+            planned,
+            budget: self.per_day,
+        })
+    }
+}
+
+/// Updates that have failed in a row for one panel, whether the frame could
+/// not be loaded or the refresh itself failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FailureStreak {
+    pub count: u32,
+}
+
+impl FailureStreak {
+    pub fn load(store: &StateStore, device: &str) -> Self {
+        let count = store.get_i64(&state_key(device, "failures")).unwrap_or(0);
+        Self {
+            count: count.clamp(0, u32::MAX as i64) as u32,
+        }
+    }
+
+    /// Adds a failure; call [`StateStore::save`] to persist.
+    pub fn record(store: &mut StateStore, device: &str) -> Result<Self> {
+        let streak = Self {
+            count: Self::load(store, device).count.saturating_add(1),
+        };
+        store.set_i64(&state_key(device, "failures"), streak.count as i64)?;
+        Ok(streak)
+    }
+
+    /// Ends the streak after a successful update, returning it.
+    pub fn clear(store: &mut StateStore, device: &str) -> Self {
+        let streak = Self::load(store, device);
+        store.remove(&state_key(device, "failures"));
+        streak
+    }
+}
+
+/// A panel whose controller stopped going idle: the busy line stayed
+/// asserted through every retry, and the driver gave up with a reset and
+/// power-off. Cleared by the next refresh that completes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Degraded {
+    pub since: SystemTime,
+    pub reason: String,
+}
+
+impl Degraded {
+    pub fn load(store: &StateStore, device: &str) -> Option<Self> {
+        let since = store.get_i64(&state_key(device, "degraded_since"))?;
+        Some(Self {
+            since: UNIX_EPOCH + Duration::from_secs(since.max(0) as u64),
+            reason: store
+                .get_str(&state_key(device, "degraded_reason"))
+                .unwrap_or_default()
+                .to_string(),
+        })
+    }
+
+    /// Marks `device` degraded at `at`, keeping the original time if it
+    /// already was; call [`StateStore::save`] to persist. Returns whether it
+    /// was newly marked.
+    pub fn mark(
+        store: &mut StateStore,
+        device: &str,
+        reason: &str,
+        at: SystemTime,
+    ) -> Result<bool> {
+        let since_key = state_key(device, "degraded_since");
+        let new = store.get_i64(&since_key).is_none();
+        if new {
+            let secs = at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+            store.set_i64(&since_key, secs as i64)?;
+        }
+        store.set_str(&state_key(device, "degraded_reason"), reason)?;
+        Ok(new)
+    }
+
+    /// Returns whether `device` was marked degraded.
+    pub fn clear(store: &mut StateStore, device: &str) -> bool {
+        store.remove(&state_key(device, "degraded_reason"));
+        store.remove(&state_key(device, "degraded_since")).is_some()
+    }
+}
+
+impl fmt::Display for Degraded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let age = SystemTime::now()
+            .duration_since(self.since)
+            .unwrap_or_default()
+            .as_secs();
+        write!(
+            f,
+            "degraded for {}h {:02}m ({})",
+            age / 3600,
+            age / 60 % 60,
+            self.reason
+        )
+    }
+}
+
+fn state_key(device: &str, field: &str) -> String {
+    format!("{STATE_PREFIX}.{device}.{field}")
+}
@@ -1,10 +1,123 @@
 #[cfg(target_os = "linux")]
 pub mod displays;
 
+#[cfg(target_os = "linux")]
+pub mod analysis;
+
+#[cfg(target_os = "linux")]
+pub mod buttons;
+
+#[cfg(target_os = "linux")]
+pub mod config;
+
+#[cfg(target_os = "linux")]
+pub mod content;
+
+#[cfg(target_os = "linux")]
+pub mod correction;
+
+#[cfg(target_os = "linux")]
+pub mod dither;
+
+#[cfg(target_os = "linux")]
+mod document;
+
+#[cfg(target_os = "linux")]
+pub mod draw;
+
+#[cfg(target_os = "linux")]
+pub mod events;
+
+#[cfg(target_os = "linux")]
+pub mod health;
+
+#[cfg(target_os = "linux")]
+pub mod notify;
+
+#[cfg(target_os = "linux")]
+pub mod pimoroni;
+
+#[cfg(target_os = "linux")]
+pub mod playlist;
+
+#[cfg(target_os = "linux")]
+pub mod saliency;
+
+#[cfg(target_os = "linux")]
+pub mod scheduler;
+
+#[cfg(target_os = "linux")]
+pub mod sensors;
+
+#[cfg(target_os = "linux")]
+pub mod state;
+
+#[cfg(target_os = "linux")]
+pub mod tone;
+
 #[cfg(target_os = "linux")]
 pub use displays::{
-    DisplaySpec, EepromInfo, I2cBusReport, I2cProbeStatus, InkyDisplay, InkyEl133Uf1,
-    InkyEl133Uf1Config, InkyError, InkyUc8159, InkyUc8159Config, Pins, ProbeInfo, Result, Rotation,
-    SpectraPins, clamp_aspect_resize, pack_buffer_nibbles, pack_luma_nibbles, probe_system,
-    uc8159_resolution_from_probe,
+    Capabilities, DeviceBackend, DisplaySpec, EepromInfo, EepromWarning, Fit, I2cBusReport,
+    I2cProbeStatus, ImageLimits, InkyDisplay, InkyEl133Uf1, InkyEl133Uf1Config, InkyError,
+    InkyUc8159, InkyUc8159Config, LinuxDevices, Pins, ProbeInfo, RenderOptions, RenderReport,
+    Result, Rotation, SharedDisplay, ShowOutcome, SpectraPins, Timeouts, clamp_aspect_resize,
+    convert_to_srgb, decode_image, load_image, open_display, pack_buffer_nibbles,
+    pack_luma_nibbles, probe_system, probe_system_at, uc8159_resolution_from_probe,
+};
+
+#[cfg(target_os = "linux")]
+pub use analysis::{
+    AnalysisReport, PaletteUsage, PanelColour, analyze, analyze_prepared, auto_saturation,
+    panel_dimensions, panel_palette,
+};
+
+#[cfg(target_os = "linux")]
+pub use buttons::{Button, ButtonAction, ButtonEvents, ButtonMap, ButtonPins};
+
+#[cfg(target_os = "linux")]
+pub use config::{Config, DisplayOverrides, NotifyConfig};
+
+#[cfg(target_os = "linux")]
+pub use content::{
+    ChartProvider, ClockFace, ClockProvider, CompositeUpdate, Compositor, ContentError,
+    ContentProvider, ContentRegistry, DirectoryProvider, PanelSpec, Rect, StatusProvider,
+    SystemStatus, render_colour_chart, render_status,
 };
+
+#[cfg(target_os = "linux")]
+pub use correction::ColourMatrix;
+
+#[cfg(target_os = "linux")]
+pub use dither::{
+    ColourMapping, DitherMethod, DitherOptions, DitherStats, PaletteRemap, dither, dither_with,
+    dither_with_stats,
+};
+
+#[cfg(target_os = "linux")]
+pub use events::{EventBus, UpdateCompleted, UpdateError, UpdateStarted};
+
+#[cfg(target_os = "linux")]
+pub use health::{BudgetOverrun, Degraded, FailureStreak, RefreshBudget, RefreshStats};
+
+#[cfg(target_os = "linux")]
+pub use notify::{Notifier, NotifyEvent};
+
+#[cfg(target_os = "linux")]
+pub use playlist::{Playlist, PlaylistItem, PlaylistSource};
+
+#[cfg(target_os = "linux")]
+pub use saliency::{SaliencyMap, lighten_weighted_in_place};
+
+#[cfg(target_os = "linux")]
+pub use scheduler::{CatchUp, CronSchedule, DueJob, ScheduledJob, Scheduler};
+
+#[cfg(target_os = "linux")]
+pub use sensors::{
+    AmbientPolicy, BatteryChip, BatteryStatus, LightChip, LightSensor, draw_battery_glyph,
+};
+
+#[cfg(target_os = "linux")]
+pub use state::{StateStore, StateValue};
+
+#[cfg(target_os = "linux")]
+pub use tone::ToneCurve;
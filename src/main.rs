@@ -1,7 +1,13 @@
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 #[cfg(target_os = "linux")]
 use image::{DynamicImage, Rgb, RgbImage};
+#[cfg(target_os = "linux")]
+use std::path::Path;
 use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+#[cfg(target_os = "linux")]
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -9,20 +15,104 @@ use std::path::PathBuf;
     about = "CLI tool to display images on Inky displays"
 )]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Optional PNG to display
     #[arg(value_name = "IMAGE")]
     image: Option<PathBuf>,
 
-    /// Palette saturation from 0.0 (desaturated) to 1.0 (saturated)
-    #[arg(short, long, value_name = "SAT", default_value_t = 1.0)]
-    saturation: f32,
+    /// Palette saturation from 0.0 (desaturated) to 1.0 (saturated), or `auto` to pick per image
+    #[arg(
+        short,
+        long,
+        value_name = "SAT",
+        default_value = "1.0",
+        env = "PAPERWAVE_SATURATION",
+        global = true
+    )]
+    saturation: SaturationArg,
 
     /// Lighten image before quantization (0.0 = none, 1.0 = strongest)
-    #[arg(short = 'l', long, value_name = "LIGHTEN", default_value_t = 0.0)]
+    #[arg(
+        short = 'l',
+        long,
+        value_name = "LIGHTEN",
+        default_value_t = 0.0,
+        env = "PAPERWAVE_LIGHTEN",
+        global = true
+    )]
     lighten: f32,
 
+    /// Randomise the dither scan order: a number for a repeatable pattern, or `random` for a new one every frame
+    #[arg(
+        long,
+        value_name = "SEED",
+        env = "PAPERWAVE_DITHER_SEED",
+        global = true
+    )]
+    dither_seed: Option<DitherSeedArg>,
+
+    /// Show one palette colour in place of another, e.g. `green=black`; repeatable, and added to `[palette]` in the config
+    #[arg(long = "map", value_name = "FROM=TO", global = true)]
+    palette_map: Vec<String>,
+
+    /// Fit the image's tones to the panel's brightness levels before dithering, widening the range of little-used colours
+    #[arg(long, env = "PAPERWAVE_ADAPTIVE_TONE", global = true)]
+    adaptive_tone: bool,
+
+    /// How to fit images whose aspect ratio differs from the panel's; contain letterboxes in white
+    #[arg(
+        long,
+        value_name = "FIT",
+        value_enum,
+        default_value_t = FitArg::Crop,
+        env = "PAPERWAVE_FIT",
+        global = true
+    )]
+    fit: FitArg,
+
+    /// Stretch dim or flat images (astrophotography, 16-bit scans) to the full tonal range before reducing them to 8 bits
+    #[arg(long, env = "PAPERWAVE_TONE_MAP", global = true)]
+    tone_map: bool,
+
+    /// Gamma applied before quantization; above 1.0 brightens midtones
+    #[arg(
+        long,
+        value_name = "GAMMA",
+        default_value_t = 1.0,
+        env = "PAPERWAVE_GAMMA",
+        global = true
+    )]
+    gamma: f32,
+
+    /// Contrast around mid grey applied before quantization; 1.0 leaves the image alone
+    #[arg(
+        long,
+        value_name = "CONTRAST",
+        default_value_t = 1.0,
+        env = "PAPERWAVE_CONTRAST",
+        global = true
+    )]
+    contrast: f32,
+
+    /// Print palette usage, dither error, clipping and processing times for each frame
+    #[arg(long, global = true)]
+    stats: bool,
+
+    /// Lighten the detected subject gently and the background more strongly, keeping faces natural
+    #[arg(long, env = "PAPERWAVE_SALIENCY", global = true)]
+    saliency: bool,
+
     /// Rotate image before display (degrees clockwise)
-    #[arg(short, long = "rotate", value_enum, default_value_t = RotationArg::Deg0)]
+    #[arg(
+        short,
+        long = "rotate",
+        value_enum,
+        default_value_t = RotationArg::Deg0,
+        env = "PAPERWAVE_ROTATE",
+        global = true
+    )]
     rotation: RotationArg,
 
     /// Probe hardware and report detection results without updating the panel
@@ -30,8 +120,318 @@ struct Args {
     detect_only: bool,
 
     /// Print probe/debug information before running
-    #[arg(long)]
+    #[arg(long, global = true)]
     debug: bool,
+
+    /// Overlay the battery level when a UPS fuel gauge is detected
+    #[arg(long, global = true)]
+    battery: bool,
+
+    /// Settings file (TOML) with driver overrides such as busy timeouts [default: $XDG_CONFIG_HOME/paperwave/config.toml, if present]
+    #[arg(long, value_name = "FILE", env = "PAPERWAVE_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    /// Directory for calibration, refresh history and other saved state [default: $XDG_STATE_HOME/paperwave]
+    #[arg(long, value_name = "DIR", env = "PAPERWAVE_STATE_DIR", global = true)]
+    state_dir: Option<PathBuf>,
+
+    /// Refresh even if the panel already shows this frame
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// Warn when a slideshow would refresh the panel more often than this per day [default: 48, or `refresh.budget_per_day` in the state file]
+    #[arg(
+        long,
+        value_name = "N",
+        env = "PAPERWAVE_REFRESH_BUDGET",
+        global = true
+    )]
+    refresh_budget: Option<u32>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Cycle through the items of a playlist file
+    Slideshow {
+        /// Playlist file (TOML) listing images/directories and per-item settings
+        #[arg(long, value_name = "FILE")]
+        playlist: PathBuf,
+
+        /// React to the Impression's A-D buttons (A next, B previous, C redisplay, D clear)
+        #[arg(long)]
+        buttons: bool,
+
+        /// Rebind a button, e.g. `c=provider:clock` (implies --buttons)
+        #[arg(long = "button", value_name = "BUTTON=ACTION")]
+        bindings: Vec<String>,
+
+        /// Use an ambient light sensor to lighten frames in dim rooms and hold refreshes in the dark
+        #[arg(long)]
+        ambient: bool,
+    },
+    /// Report how an image will map to the panel without displaying it
+    Analyze {
+        /// Image to analyse
+        #[arg(value_name = "IMAGE")]
+        image: PathBuf,
+    },
+    /// Show an image with two dither/palette settings side by side
+    Compare {
+        /// Image to compare
+        #[arg(value_name = "IMAGE")]
+        image: PathBuf,
+
+        /// Dither method for the left half
+        #[arg(long, value_enum, default_value_t = DitherArg::Floyd)]
+        left: DitherArg,
+
+        /// Dither method for the right half
+        #[arg(long, value_enum, default_value_t = DitherArg::Atkinson)]
+        right: DitherArg,
+
+        /// Saturation for the left half (defaults to --saturation)
+        #[arg(long, value_name = "SAT")]
+        left_saturation: Option<f32>,
+
+        /// Saturation for the right half (defaults to --saturation)
+        #[arg(long, value_name = "SAT")]
+        right_saturation: Option<f32>,
+    },
+    /// Show (or save) a labelled chart of the panel palette and blend gradients
+    Chart {
+        /// Write the chart to a PNG at panel resolution instead of displaying it
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Derive and store a colour-correction matrix from measured chart colours
+    Calibrate {
+        /// Measured colour of each chart swatch as R,G,B, in swatch order
+        #[arg(
+            long = "measured",
+            value_name = "R,G,B",
+            required_unless_present = "reset"
+        )]
+        measured: Vec<String>,
+
+        /// Remove the stored matrix for this panel
+        #[arg(long, conflicts_with = "measured")]
+        reset: bool,
+
+        /// Print the matrix without storing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Send a file to the panel, optionally a frame saved by Pimoroni's inky library
+    Push {
+        /// File to display
+        file: PathBuf,
+
+        /// How to read the file
+        #[arg(long, value_enum, default_value_t = PushFormat::Image)]
+        format: PushFormat,
+    },
+    /// Accept the arguments of Pimoroni's inky example scripts, e.g. `--type impressions --file photo.png`
+    InkyCompat {
+        /// Panel type as named by inky (`impressions`, `7colour` or `el133uf1`); overrides detection
+        #[arg(short = 't', long = "type", value_name = "TYPE")]
+        panel_type: Option<String>,
+
+        /// Ink colour for two- and three-colour panels; ignored by the colour panels paperwave drives
+        #[arg(short, long, value_name = "COLOUR")]
+        colour: Option<String>,
+
+        /// Image to display
+        #[arg(short, long, value_name = "FILE", required_unless_present = "path")]
+        file: Option<PathBuf>,
+
+        /// Image to display, for scripts that pass it positionally
+        #[arg(conflicts_with = "file")]
+        path: Option<PathBuf>,
+    },
+    /// Build the frame from regions that each have their own content and refresh cadence
+    Compose {
+        /// Region as NAME=X,Y,WxH:SOURCE[@SECONDS], where SOURCE is `clock`, `clock:digital`, `chart`, `status` or `dir:PATH`
+        #[arg(long = "region", value_name = "REGION", required = true)]
+        regions: Vec<String>,
+    },
+    /// Show an image on one tile of a panel split into a grid of virtual displays
+    Tile {
+        /// Columns and rows to split the panel into, e.g. `2x1` for two side by side
+        #[arg(long, value_name = "COLSxROWS")]
+        grid: GridArg,
+
+        /// Tile to draw into, numbered from 1 in reading order
+        #[arg(long, value_name = "N")]
+        tile: u16,
+
+        /// Image to show on the tile
+        #[arg(value_name = "IMAGE")]
+        image: PathBuf,
+    },
+    /// Time each stage of the image pipeline, to pick settings that fit an update-latency budget
+    Bench {
+        /// Image to process; a generated gradient at panel size if omitted
+        #[arg(value_name = "IMAGE")]
+        image: Option<PathBuf>,
+
+        /// Time the SPI transfer only, without the ~30 second panel refresh
+        #[arg(long)]
+        no_refresh: bool,
+
+        /// Runs of each processing stage to average over
+        #[arg(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        runs: u32,
+    },
+    /// Clear ghosting with alternating full black/white refreshes
+    Flush {
+        /// Number of black/white cycles
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        cycles: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct GridArg {
+    columns: u16,
+    rows: u16,
+}
+
+impl std::str::FromStr for GridArg {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parsed = value.split_once('x').and_then(|(columns, rows)| {
+            Some(GridArg {
+                columns: columns.parse().ok().filter(|&n| n > 0)?,
+                rows: rows.parse().ok().filter(|&n| n > 0)?,
+            })
+        });
+        parsed.ok_or_else(|| format!("expected COLSxROWS such as 2x1, got `{value}`"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum PushFormat {
+    /// Any supported image, processed like a normal frame
+    Image,
+    /// Colour codes in panel orientation: palettized PNG, .npy or raw buffer
+    Pimoroni,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+enum SaturationArg {
+    Auto,
+    Fixed(f32),
+}
+
+impl std::str::FromStr for SaturationArg {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("auto") {
+            return Ok(SaturationArg::Auto);
+        }
+        match value.parse::<f32>() {
+            Ok(saturation) if (0.0..=1.0).contains(&saturation) => {
+                Ok(SaturationArg::Fixed(saturation))
+            }
+            _ => Err(format!(
+                "expected a number from 0.0 to 1.0 or `auto`, got `{value}`"
+            )),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl SaturationArg {
+    fn resolve(self, image: &DynamicImage) -> f32 {
+        match self {
+            SaturationArg::Auto => paperwave::auto_saturation(image),
+            SaturationArg::Fixed(saturation) => saturation,
+        }
+    }
+
+    /// Saturation for generated frames, which have no source image to
+    /// analyse; `auto` falls back to the measured ink palette.
+    fn fixed(self) -> f32 {
+        match self {
+            SaturationArg::Auto => 1.0,
+            SaturationArg::Fixed(saturation) => saturation,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+enum DitherSeedArg {
+    Random,
+    Fixed(u64),
+}
+
+impl std::str::FromStr for DitherSeedArg {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("random") {
+            return Ok(DitherSeedArg::Random);
+        }
+        value
+            .parse()
+            .map(DitherSeedArg::Fixed)
+            .map_err(|_| format!("expected a number or `random`, got `{value}`"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DitherSeedArg {
+    fn seed(self) -> u64 {
+        match self {
+            DitherSeedArg::Random => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos() as u64),
+            DitherSeedArg::Fixed(seed) => seed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DitherArg {
+    Floyd,
+    Atkinson,
+    None,
+}
+
+#[cfg(target_os = "linux")]
+impl From<DitherArg> for paperwave::DitherMethod {
+    fn from(value: DitherArg) -> Self {
+        match value {
+            DitherArg::Floyd => paperwave::DitherMethod::FloydSteinberg,
+            DitherArg::Atkinson => paperwave::DitherMethod::Atkinson,
+            DitherArg::None => paperwave::DitherMethod::None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum FitArg {
+    #[value(alias = "cover")]
+    Crop,
+    #[value(alias = "letterbox")]
+    Contain,
+    Stretch,
+}
+
+#[cfg(target_os = "linux")]
+impl From<FitArg> for paperwave::Fit {
+    fn from(value: FitArg) -> Self {
+        match value {
+            FitArg::Crop => paperwave::Fit::Crop,
+            FitArg::Contain => paperwave::Fit::Contain,
+            FitArg::Stretch => paperwave::Fit::Stretch,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, ValueEnum)]
@@ -62,27 +462,276 @@ impl From<RotationArg> for paperwave::Rotation {
 fn main() {
     let args = Args::parse();
     let rotation = args.rotation.into();
+    if let Some(dir) = &args.state_dir {
+        let _ = STATE_DIR.set(dir.clone());
+    }
+    // An explicit --config must exist; the XDG default is optional.
+    let config_path = args
+        .config
+        .clone()
+        .or_else(|| paperwave::config::default_config_path().filter(|path| path.is_file()));
+    let mut config = match &config_path {
+        Some(path) => paperwave::Config::load(path).unwrap_or_else(|err| {
+            eprintln!("Error: {err}");
+            std::process::exit(2);
+        }),
+        None => paperwave::Config::default(),
+    };
+    if let Err(err) = config.apply_env() {
+        eprintln!("Error: {err}");
+        std::process::exit(2);
+    }
+    let _ = NOTIFY.set(config.notify.clone());
+    subscribe_events();
     let probe = paperwave::probe_system();
+    event_bus().probe(&probe);
+    let battery_status = if args.battery {
+        probe.find_battery(&paperwave::LinuxDevices)
+    } else {
+        None
+    };
+    let battery = battery_status.as_ref().map(|status| status.percent);
+    // Command-line mappings come last, so they win for the same colour.
+    let mut palette_map = config.palette_map;
+    for mapping in &args.palette_map {
+        match mapping.parse::<paperwave::ColourMapping>() {
+            Ok(mapping) => palette_map.push(mapping),
+            Err(err) => {
+                eprintln!("Error: {err}");
+                std::process::exit(2);
+            }
+        }
+    }
+    let colour_names: Vec<&str> = paperwave::panel_palette(probe.display.as_ref(), 1.0)
+        .iter()
+        .map(|colour| colour.name)
+        .collect();
+    let remap =
+        paperwave::PaletteRemap::resolve(&palette_map, &colour_names).unwrap_or_else(|err| {
+            eprintln!("Error: {err}");
+            std::process::exit(2);
+        });
+    let frame = FrameSettings {
+        saturation: args.saturation,
+        lighten: args.lighten,
+        battery,
+        correction: load_correction(&probe),
+        dither_seed: args.dither_seed,
+        remap,
+        adaptive_tone: args.adaptive_tone,
+        saliency: args.saliency,
+        fit: args.fit.into(),
+        tone_map: args.tone_map,
+        gamma: args.gamma,
+        contrast: args.contrast,
+        stats: args.stats,
+        force: args.force,
+        driver: config.display,
+    };
+    let budget = match args.refresh_budget {
+        Some(per_day) => paperwave::RefreshBudget { per_day },
+        None => open_state()
+            .map(|store| paperwave::RefreshBudget::load(&store))
+            .unwrap_or_default(),
+    };
 
     if args.debug || args.detect_only {
-        print_probe(&probe);
+        print_probe(&probe, budget);
+        if args.battery {
+            match &battery_status {
+                Some(status) => println!("Battery: {status}"),
+                None => println!("Battery: no fuel gauge detected"),
+            }
+        }
     }
 
     if args.detect_only {
         return;
     }
 
+    if let Some(Command::Slideshow {
+        playlist,
+        buttons,
+        bindings,
+        ambient,
+    }) = &args.command
+    {
+        let button_map = if *buttons || !bindings.is_empty() {
+            let mut map = paperwave::ButtonMap::default();
+            for binding in bindings {
+                if let Err(err) = map.apply_binding(binding) {
+                    eprintln!("Error: {err}");
+                    std::process::exit(2);
+                }
+            }
+            Some(map)
+        } else {
+            None
+        };
+        let inputs = SlideshowInputs {
+            buttons: button_map,
+            ambient: *ambient,
+            budget,
+        };
+
+        if let Err(err) = run_slideshow(playlist, rotation, frame, &inputs, &probe) {
+            exit_with_content_update_error(&probe, &err);
+        }
+        return;
+    }
+
+    if let Some(Command::Analyze { image }) = &args.command {
+        if let Err(err) = run_analyze(image, rotation, &frame, &probe) {
+            exit_with_error(&err);
+        }
+        return;
+    }
+
+    if let Some(Command::Compare {
+        image,
+        left,
+        right,
+        left_saturation,
+        right_saturation,
+    }) = &args.command
+    {
+        let sides = [
+            CompareSide {
+                dither: (*left).into(),
+                saturation: *left_saturation,
+            },
+            CompareSide {
+                dither: (*right).into(),
+                saturation: *right_saturation,
+            },
+        ];
+        if let Err(err) = run_compare(image, rotation, frame, &sides, &probe) {
+            exit_with_update_error(&probe, &err);
+        }
+        return;
+    }
+
+    if let Some(Command::Chart { output }) = &args.command {
+        if let Err(err) = run_chart(output.as_deref(), rotation, frame, &probe) {
+            match output {
+                Some(_) => exit_with_error(&err),
+                None => exit_with_update_error(&probe, &err),
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Calibrate {
+        measured,
+        reset,
+        dry_run,
+    }) = &args.command
+    {
+        if let Err(err) = run_calibrate(measured, *reset, *dry_run, &probe) {
+            exit_with_content_error(&err);
+        }
+        return;
+    }
+
+    if let Some(Command::Push { file, format }) = &args.command {
+        let result = match format {
+            PushFormat::Image => run_image(file, rotation, frame, &probe),
+            PushFormat::Pimoroni => run_push_pimoroni(file, frame, &probe),
+        };
+        if let Err(err) = result {
+            exit_with_update_error(&probe, &err);
+        }
+        return;
+    }
+
+    if let Some(Command::InkyCompat {
+        panel_type,
+        colour: _,
+        file,
+        path,
+    }) = &args.command
+    {
+        let mut probe = probe;
+        if let Some(name) = panel_type {
+            match inky_panel_type(name) {
+                Ok(spec) => probe.display = Some(spec),
+                Err(err) => {
+                    eprintln!("Error: {err}");
+                    std::process::exit(2);
+                }
+            }
+        }
+        let Some(image) = file.as_ref().or(path.as_ref()) else {
+            eprintln!("Error: no image given; pass --file or a path");
+            std::process::exit(2);
+        };
+        if let Err(err) = run_image(image, rotation, frame, &probe) {
+            exit_with_update_error(&probe, &err);
+        }
+        return;
+    }
+
+    if let Some(Command::Compose { regions }) = &args.command {
+        let regions = regions
+            .iter()
+            .map(|spec| spec.parse::<RegionArg>())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|err| {
+                eprintln!("Error: {err}");
+                std::process::exit(2);
+            });
+        if let Err(err) = run_compose(&regions, rotation, frame, &probe) {
+            exit_with_content_update_error(&probe, &err);
+        }
+        return;
+    }
+
+    if let Some(Command::Tile { grid, tile, image }) = &args.command {
+        if let Err(err) = run_tile(image, *grid, *tile, rotation, frame, &probe) {
+            exit_with_content_update_error(&probe, &err);
+        }
+        return;
+    }
+
+    if let Some(Command::Bench {
+        image,
+        no_refresh,
+        runs,
+    }) = &args.command
+    {
+        if let Err(err) = run_bench(
+            image.as_deref(),
+            *runs,
+            *no_refresh,
+            rotation,
+            frame,
+            &probe,
+        ) {
+            exit_with_error(&err);
+        }
+        return;
+    }
+
+    if let Some(Command::Flush { cycles }) = &args.command {
+        let result =
+            paperwave::open_display(&probe, rotation, &frame.driver).and_then(|mut display| {
+                event_bus().flush(display.as_mut(), &panel_key(&probe), *cycles)
+            });
+        if let Err(err) = result {
+            exit_with_update_error(&probe, &err);
+        }
+        return;
+    }
+
     if let Some(path) = args.image {
-        if let Err(err) = run_image(&path, rotation, args.saturation, args.lighten, &probe) {
-            eprintln!("Error: {err}");
-            std::process::exit(1);
+        if let Err(err) = run_image(&path, rotation, frame, &probe) {
+            exit_with_update_error(&probe, &err);
         }
         return;
     }
 
-    if let Err(err) = run_demo(rotation, args.saturation, args.lighten, &probe) {
-        eprintln!("Error: {err}");
-        std::process::exit(1);
+    if let Err(err) = run_demo(rotation, frame, &probe) {
+        exit_with_update_error(&probe, &err);
     }
 }
 
@@ -94,11 +743,10 @@ fn main() {
 #[cfg(target_os = "linux")]
 fn run_demo(
     rotation: paperwave::Rotation,
-    saturation: f32,
-    lighten: f32,
+    frame: FrameSettings,
     probe: &paperwave::ProbeInfo,
 ) -> paperwave::Result<()> {
-    let mut display = create_display(rotation, probe)?;
+    let mut display = paperwave::open_display(probe, rotation, &frame.driver)?;
 
     let (input_w, input_h) = display.input_dimensions();
     let mut image = RgbImage::new(input_w as u32, input_h as u32);
@@ -124,7 +772,7 @@ fn run_demo(
     };
 
     let stripes = palette.len();
-    let stripe_height = ((input_h as usize) + stripes - 1) / stripes;
+    let stripe_height = (input_h as usize).div_ceil(stripes);
 
     for (index, colour) in palette.iter().enumerate() {
         let y_start = index * stripe_height;
@@ -142,61 +790,1180 @@ fn run_demo(
     }
 
     let dynamic = DynamicImage::ImageRgb8(image);
-    display.set_image(&dynamic, saturation, lighten)?;
-    display.show()
+    set_frame(display.as_mut(), &dynamic, &frame)?;
+    show_frame(display.as_mut(), probe, frame.force)
+}
+
+/// [`exit_with_error`] for commands that update the panel, counting the
+/// failure towards `[notify] after_failures`.
+#[cfg(target_os = "linux")]
+fn exit_with_update_error(probe: &paperwave::ProbeInfo, err: &paperwave::InkyError) -> ! {
+    event_bus().error(&panel_key(probe), err);
+    exit_with_error(err)
+}
+
+/// Prints `err`, with a hint for errors the user can fix, and exits.
+#[cfg(target_os = "linux")]
+fn exit_with_error(err: &paperwave::InkyError) -> ! {
+    eprintln!("Error: {err}");
+    if let paperwave::InkyError::LinesBusy { consumer, .. } = err {
+        eprintln!(
+            "Another program (`{consumer}`) is driving the display. Stop it first, e.g. a \
+             Python inky script or a service using the HAT; `gpioinfo` lists every line's owner."
+        );
+    }
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn exit_with_content_update_error(
+    probe: &paperwave::ProbeInfo,
+    err: &paperwave::ContentError,
+) -> ! {
+    emit_content_error(&panel_key(probe), err);
+    exit_with_content_error(err)
+}
+
+/// Emits display failures as update errors. Other content errors, e.g. an
+/// empty image directory, happened before anything reached the panel.
+#[cfg(target_os = "linux")]
+fn emit_content_error(panel: &str, err: &paperwave::ContentError) {
+    if let paperwave::ContentError::Display(err) = err {
+        event_bus().error(panel, err);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn exit_with_content_error(err: &paperwave::ContentError) -> ! {
+    if let paperwave::ContentError::Display(err) = err {
+        exit_with_error(err);
+    }
+    eprintln!("Error: {err}");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn run_image(
+    path: &Path,
+    rotation: paperwave::Rotation,
+    frame: FrameSettings,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::Result<()> {
+    let mut display = paperwave::open_display(probe, rotation, &frame.driver)?;
+    let image = paperwave::load_image(path, &paperwave::ImageLimits::default())?;
+    set_frame(display.as_mut(), &image, &frame)?;
+    show_frame(display.as_mut(), probe, frame.force)
+}
+
+/// Maps the `--type` names of Pimoroni's inky library onto the panels this
+/// crate drives.
+#[cfg(target_os = "linux")]
+fn inky_panel_type(name: &str) -> Result<paperwave::DisplaySpec, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "impressions" | "7colour" | "uc8159" => Ok(paperwave::DisplaySpec::Uc8159 {
+            width: 600,
+            height: 448,
+            variant: 14,
+        }),
+        "el133uf1" => Ok(paperwave::DisplaySpec::El133Uf1 {
+            width: 1600,
+            height: 1200,
+        }),
+        "phat" | "what" | "phatssd1608" | "whatssd1683" | "impressions73" => Err(format!(
+            "the `{name}` panel is not supported; paperwave drives the Inky Impression 5.7\" \
+             (impressions) and 13.3\" (el133uf1)"
+        )),
+        _ => Err(format!(
+            "unknown panel type `{name}`; expected impressions, 7colour or el133uf1"
+        )),
+    }
+}
+
+/// Shows a pre-rendered Pimoroni frame as is: no rotation, resizing,
+/// correction or dithering.
+#[cfg(target_os = "linux")]
+fn run_push_pimoroni(
+    path: &Path,
+    frame: FrameSettings,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::Result<()> {
+    let mut display = paperwave::open_display(probe, paperwave::Rotation::Deg0, &frame.driver)?;
+    let codes = paperwave::pimoroni::load_frame(path, display.width(), display.height())?;
+    display.set_panel_buffer(&codes)?;
+    show_frame(display.as_mut(), probe, frame.force)
+}
+
+/// Processing applied to every frame before it reaches the driver, and the
+/// driver settings it is shown with.
+#[cfg(target_os = "linux")]
+#[derive(Clone)]
+struct FrameSettings {
+    saturation: SaturationArg,
+    lighten: f32,
+    battery: Option<f32>,
+    correction: Option<paperwave::ColourMatrix>,
+    dither_seed: Option<DitherSeedArg>,
+    remap: paperwave::PaletteRemap,
+    adaptive_tone: bool,
+    /// Weight `lighten` by a saliency map instead of applying it evenly.
+    saliency: bool,
+    fit: paperwave::Fit,
+    tone_map: bool,
+    gamma: f32,
+    contrast: f32,
+    /// Print a [`paperwave::RenderReport`] for every frame.
+    stats: bool,
+    /// Refresh even when the quantized frame matches the one last shown.
+    force: bool,
+    driver: paperwave::DisplayOverrides,
+}
+
+/// Set from `--state-dir` before any state is read.
+#[cfg(target_os = "linux")]
+static STATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// The `[notify]` settings, set once the config is loaded.
+#[cfg(target_os = "linux")]
+static NOTIFY: OnceLock<paperwave::NotifyConfig> = OnceLock::new();
+
+/// Update events; [`subscribe_events`] attaches refresh accounting, health
+/// tracking and notifications.
+#[cfg(target_os = "linux")]
+static EVENTS: Mutex<paperwave::EventBus> = Mutex::new(paperwave::EventBus::new());
+
+#[cfg(target_os = "linux")]
+fn event_bus() -> MutexGuard<'static, paperwave::EventBus> {
+    EVENTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The state key of the detected panel, used as the panel name in events.
+#[cfg(target_os = "linux")]
+fn panel_key(probe: &paperwave::ProbeInfo) -> String {
+    paperwave::correction::device_key(probe.display.as_ref())
+}
+
+#[cfg(target_os = "linux")]
+fn subscribe_events() {
+    event_bus()
+        .on_update_completed(|event| {
+            match event.outcome {
+                paperwave::ShowOutcome::Refreshed => {
+                    report_recoveries(event.recoveries);
+                    record_refresh(event);
+                }
+                paperwave::ShowOutcome::NotModified => {
+                    println!("Frame unchanged; skipping refresh (use --force to refresh anyway)")
+                }
+            }
+            record_update_success(event.panel);
+        })
+        .on_error(|event| {
+            if matches!(event.error, paperwave::InkyError::Timeout(..)) {
+                record_degraded(event.panel, event.error);
+            }
+            record_update_failure(event.panel, event.error);
+        });
+}
+
+/// `--state-dir`, or the default XDG location.
+#[cfg(target_os = "linux")]
+fn state_dir() -> paperwave::content::Result<PathBuf> {
+    match STATE_DIR.get() {
+        Some(dir) => Ok(dir.clone()),
+        None => paperwave::state::default_state_dir(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_state() -> paperwave::content::Result<paperwave::StateStore> {
+    paperwave::StateStore::open_in(&state_dir()?)
+}
+
+/// The colour correction stored for the detected panel, if any.
+#[cfg(target_os = "linux")]
+fn load_correction(probe: &paperwave::ProbeInfo) -> Option<paperwave::ColourMatrix> {
+    let store = open_state().ok()?;
+    let device = paperwave::correction::device_key(probe.display.as_ref());
+    paperwave::ColourMatrix::load(&store, &device)
+}
+
+/// Hands `image` to the display. Colour correction, saliency-weighted
+/// lightening and the battery glyph are applied to a panel-sized copy; auto
+/// saturation is resolved against the source image, before any of them.
+#[cfg(target_os = "linux")]
+fn set_frame(
+    display: &mut dyn paperwave::InkyDisplay,
+    image: &DynamicImage,
+    frame: &FrameSettings,
+) -> paperwave::Result<()> {
+    let options = render_options(image, frame);
+    let report = if frame.battery.is_none() && frame.correction.is_none() && !frame.saliency {
+        display.set_image_with(image, &options)?
+    } else {
+        prepare_and_set_image(display, image, frame, &options)?
+    };
+    if frame.stats {
+        print_render_report(&report);
+    }
+    Ok(())
+}
+
+/// The render settings for `image` on every path that quantizes a frame, so
+/// previews and comparisons match what [`set_frame`] shows.
+#[cfg(target_os = "linux")]
+fn render_options(image: &DynamicImage, frame: &FrameSettings) -> paperwave::RenderOptions {
+    paperwave::RenderOptions {
+        saturation: frame.saturation.resolve(image),
+        lighten: frame.lighten,
+        dither: paperwave::DitherOptions {
+            seed: frame.dither_seed.map(DitherSeedArg::seed),
+            remap: frame.remap,
+            adaptive_tone: frame.adaptive_tone,
+            ..Default::default()
+        },
+        fit: frame.fit,
+        tone_map: frame.tone_map,
+        gamma: frame.gamma,
+        contrast: frame.contrast,
+        border: None,
+    }
+}
+
+/// Fits `image` to `width`x`height` and applies colour correction and the
+/// tone adjustments (saliency-weighted if enabled), everything [`set_frame`]
+/// does before quantizing apart from the battery glyph.
+#[cfg(target_os = "linux")]
+fn prepare_frame(
+    image: &DynamicImage,
+    frame: &FrameSettings,
+    options: &paperwave::RenderOptions,
+    width: u32,
+    height: u32,
+) -> RgbImage {
+    let mut rgb = options.fit_image(image, width, height);
+    if let Some(correction) = &frame.correction {
+        correction.apply_in_place(&mut rgb);
+    }
+    if frame.saliency {
+        paperwave::RenderOptions {
+            lighten: 0.0,
+            ..*options
+        }
+        .adjust_in_place(&mut rgb);
+        let map = paperwave::SaliencyMap::detect(&rgb);
+        paperwave::lighten_weighted_in_place(&mut rgb, options.lighten, &map);
+    } else {
+        options.adjust_in_place(&mut rgb);
+    }
+    rgb
+}
+
+/// The [`set_frame`] path for processing the driver does not do itself.
+#[cfg(target_os = "linux")]
+fn prepare_and_set_image(
+    display: &mut dyn paperwave::InkyDisplay,
+    image: &DynamicImage,
+    frame: &FrameSettings,
+    options: &paperwave::RenderOptions,
+) -> paperwave::Result<paperwave::RenderReport> {
+    let started = Instant::now();
+    let (width, height) = display.input_dimensions();
+    let mut rgb = prepare_frame(image, frame, options, width as u32, height as u32);
+    if let Some(percent) = frame.battery {
+        paperwave::draw_battery_glyph(&mut rgb, percent);
+    }
+    let prepare_time = started.elapsed();
+    let mut report = display.set_image_with(
+        &DynamicImage::ImageRgb8(rgb),
+        &options.without_adjustments(),
+    )?;
+    report.prepare_time += prepare_time;
+    Ok(report)
+}
+
+#[cfg(target_os = "linux")]
+fn print_render_report(report: &paperwave::RenderReport) {
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    println!(
+        "Rendered in {:.1} ms (prepare {:.1} ms, quantize {:.1} ms)",
+        millis(report.prepare_time + report.quantize_time),
+        millis(report.prepare_time),
+        millis(report.quantize_time)
+    );
+    if !report.palette.is_empty() {
+        println!("Palette usage:");
+        for usage in &report.palette {
+            println!("  {:<7} {:>5.1}%", usage.name, usage.fraction * 100.0);
+        }
+        println!("Mean dither error: {:.1}", report.mean_error);
+    }
+    println!(
+        "Clipped highlights: {:.1}%, shadows: {:.1}%",
+        report.clipped_highlights * 100.0,
+        report.clipped_shadows * 100.0
+    );
+}
+
+/// State key holding the hash of the frame last shown on the detected panel.
+#[cfg(target_os = "linux")]
+fn frame_hash_key(device: &str) -> String {
+    format!("display.{device}.frame_hash")
+}
+
+/// Refreshes the panel unless it already shows the quantized frame (or
+/// `force` is set); the completed event records what it shows.
+#[cfg(target_os = "linux")]
+fn show_frame(
+    display: &mut dyn paperwave::InkyDisplay,
+    probe: &paperwave::ProbeInfo,
+    force: bool,
+) -> paperwave::Result<()> {
+    let panel = panel_key(probe);
+    let previous = if force {
+        None
+    } else {
+        open_state()
+            .ok()
+            .and_then(|store| store.get_i64(&frame_hash_key(&panel)))
+            .map(|hash| hash as u64)
+    };
+    event_bus().show(display, &panel, previous).map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+fn report_recoveries(recoveries: u32) {
+    match recoveries {
+        0 => {}
+        1 => eprintln!("Recovered from a busy timeout with a hardware reset"),
+        resets => eprintln!("Recovered from busy timeouts after {resets} hardware resets"),
+    }
+}
+
+/// Stores the hash of the frame now on the panel and adds to its refresh
+/// counters. Failing to record either only costs accuracy, so errors are
+/// just reported.
+#[cfg(target_os = "linux")]
+fn record_refresh(event: &paperwave::UpdateCompleted) {
+    let result = open_state().and_then(|mut store| {
+        store.set_i64(&frame_hash_key(event.panel), event.frame_hash as i64)?;
+        paperwave::RefreshStats::record(
+            &mut store,
+            event.panel,
+            event.refreshes,
+            event.elapsed,
+            SystemTime::now(),
+        )?;
+        store.save()
+    });
+    if let Err(err) = result {
+        eprintln!("Warning: could not record the displayed frame: {err}");
+    }
+}
+
+/// Marks the panel degraded after the driver gave up on a busy line that
+/// never cleared; `--detect-only` reports it until a refresh succeeds.
+#[cfg(target_os = "linux")]
+fn record_degraded(device: &str, err: &paperwave::InkyError) {
+    let result = open_state().and_then(|mut store| {
+        let new =
+            paperwave::Degraded::mark(&mut store, device, &err.to_string(), SystemTime::now())?;
+        store.save().map(|()| new)
+    });
+    match result {
+        Ok(true) => notify(device, paperwave::NotifyEvent::Degraded, &err.to_string()),
+        Ok(false) => {}
+        Err(err) => eprintln!("Warning: could not record the panel as degraded: {err}"),
+    }
+}
+
+/// Counts a failed update, notifying once the streak reaches
+/// `[notify] after_failures`. A failed refresh can leave the panel showing
+/// anything, so the stored frame hash is dropped and the next update is
+/// never skipped as unchanged.
+#[cfg(target_os = "linux")]
+fn record_update_failure(device: &str, err: &paperwave::InkyError) {
+    let result = open_state().and_then(|mut store| {
+        store.remove(&frame_hash_key(device));
+        let streak = paperwave::FailureStreak::record(&mut store, device)?;
+        store.save().map(|()| streak)
+    });
+    match result {
+        Ok(streak) if streak.count == notify_threshold() => notify(
+            device,
+            paperwave::NotifyEvent::Failing,
+            &format!(
+                "{} updates in a row failed; last error: {err}",
+                streak.count
+            ),
+        ),
+        Ok(_) => {}
+        Err(err) => eprintln!("Warning: could not record the failed update: {err}"),
+    }
+}
+
+/// Ends any failure streak and clears the degraded mark, notifying if
+/// either had been reported.
+#[cfg(target_os = "linux")]
+fn record_update_success(device: &str) {
+    let result = open_state().and_then(|mut store| {
+        let streak = paperwave::FailureStreak::clear(&mut store, device);
+        let degraded = paperwave::Degraded::clear(&mut store, device);
+        store.save().map(|()| (streak, degraded))
+    });
+    match result {
+        Ok((streak, degraded)) if degraded || streak.count >= notify_threshold() => {
+            eprintln!("Panel is updating normally again");
+            notify(
+                device,
+                paperwave::NotifyEvent::Recovered,
+                "Panel is updating normally again",
+            );
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("Warning: could not record the update: {err}"),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn notify_threshold() -> u32 {
+    NOTIFY.get().cloned().unwrap_or_default().after_failures
 }
 
+/// Runs the `[notify]` command, if one is configured.
 #[cfg(target_os = "linux")]
-fn create_display(
+fn notify(device: &str, event: paperwave::NotifyEvent, message: &str) {
+    let Some(command) = NOTIFY.get().and_then(|config| config.command.as_deref()) else {
+        return;
+    };
+    if let Err(err) = paperwave::Notifier::new(command).send(event, device, message) {
+        eprintln!("Warning: {err}");
+    }
+}
+
+/// Longest `@SECONDS` a region accepts; longer intervals are capped, since
+/// the compose loop adds them to the current time.
+#[cfg(target_os = "linux")]
+const MAX_REGION_INTERVAL: Duration = Duration::from_secs(366 * 86_400);
+
+/// One `--region` of the compose command.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug)]
+struct RegionArg {
+    name: String,
+    rect: paperwave::Rect,
+    source: RegionSource,
+    interval: Option<Duration>,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug)]
+enum RegionSource {
+    Clock(paperwave::ClockFace),
+    Chart,
+    Status,
+    Directory(PathBuf),
+}
+
+#[cfg(target_os = "linux")]
+impl std::str::FromStr for RegionArg {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected NAME=X,Y,WxH:SOURCE[@SECONDS], got `{value}`");
+        let (name, rest) = value.split_once('=').ok_or_else(invalid)?;
+        let (rect, source) = rest.split_once(':').ok_or_else(invalid)?;
+        let (source, interval) = match source.rsplit_once('@') {
+            Some((source, secs)) => match secs
+                .parse::<f64>()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            {
+                Some(interval) if !interval.is_zero() => {
+                    (source, Some(interval.min(MAX_REGION_INTERVAL)))
+                }
+                _ => return Err(format!("`{secs}` is not a positive number of seconds")),
+            },
+            None => (source, None),
+        };
+        let source = match source.split_once(':') {
+            None if source == "clock" => RegionSource::Clock(paperwave::ClockFace::Analog),
+            Some(("clock", "analog")) => RegionSource::Clock(paperwave::ClockFace::Analog),
+            Some(("clock", "digital")) => RegionSource::Clock(paperwave::ClockFace::Digital),
+            None if source == "chart" => RegionSource::Chart,
+            None if source == "status" => RegionSource::Status,
+            Some(("dir", path)) if !path.is_empty() => RegionSource::Directory(path.into()),
+            _ => {
+                return Err(format!(
+                    "unknown source `{source}`; use clock, clock:digital, chart, status or dir:PATH"
+                ));
+            }
+        };
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            rect: rect.parse()?,
+            source,
+            interval,
+        })
+    }
+}
+
+/// Composes the frame from `regions` and refreshes the panel whenever a
+/// region's content changes, until interrupted.
+#[cfg(target_os = "linux")]
+fn run_compose(
+    regions: &[RegionArg],
     rotation: paperwave::Rotation,
+    frame: FrameSettings,
     probe: &paperwave::ProbeInfo,
-) -> paperwave::Result<Box<dyn paperwave::InkyDisplay>> {
-    use paperwave::InkyDisplay;
-
-    match probe.display {
-        Some(paperwave::DisplaySpec::El133Uf1 { width, height }) => {
-            let mut config = paperwave::InkyEl133Uf1Config::default();
-            config.width = width;
-            config.height = height;
-            config.rotation = rotation;
-            let mut display = paperwave::InkyEl133Uf1::new(config)?;
-            display.set_rotation(rotation);
-            Ok(Box::new(display))
-        }
-        Some(paperwave::DisplaySpec::Uc8159 { width, height, .. }) => {
-            let mut config = paperwave::InkyUc8159Config::default();
-            config.width = width;
-            config.height = height;
-            config.rotation = rotation;
-            let mut display = paperwave::InkyUc8159::new(config)?;
-            display.set_rotation(rotation);
-            Ok(Box::new(display))
+) -> paperwave::content::Result<()> {
+    let mut display = paperwave::open_display(probe, rotation, &frame.driver)?;
+    let mut compositor =
+        paperwave::Compositor::new(paperwave::PanelSpec::for_display(display.as_ref()));
+    for region in regions {
+        let provider: Box<dyn paperwave::ContentProvider> = match &region.source {
+            RegionSource::Clock(face) => Box::new(paperwave::ClockProvider::new(*face)),
+            RegionSource::Chart => Box::new(paperwave::ChartProvider::new(chart_palette(
+                probe,
+                frame.saturation.fixed(),
+            ))),
+            RegionSource::Status => Box::new(paperwave::StatusProvider::new()),
+            RegionSource::Directory(dir) => {
+                // Step through the images every minute unless told otherwise.
+                let interval = region.interval.unwrap_or(Duration::from_secs(60));
+                Box::new(paperwave::DirectoryProvider::new(dir.clone(), interval))
+            }
+        };
+        compositor.add_region(&region.name, region.rect, provider)?;
+        if let Some(interval) = region.interval {
+            compositor.set_interval(&region.name, interval)?;
+        }
+    }
+
+    loop {
+        let update = compositor.update(Instant::now());
+        for (name, err) in &update.failed {
+            eprintln!("Region `{name}` failed to render: {err}");
+        }
+        if let Some((_, err)) = update.failed.first() {
+            emit_content_error(&panel_key(probe), err);
         }
+        if update.needs_refresh() {
+            let image = DynamicImage::ImageRgb8(compositor.frame().clone());
+            set_frame(display.as_mut(), &image, &frame)
+                .and_then(|()| show_frame(display.as_mut(), probe, frame.force))?;
+        }
+        let Some(next) = compositor.next_due() else {
+            return Ok(());
+        };
+        std::thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Draws `path` into one tile of the grid and refreshes the panel. The
+/// composed frame is kept in the state directory, so the other tiles keep
+/// what earlier runs drew into them.
+#[cfg(target_os = "linux")]
+fn run_tile(
+    path: &Path,
+    grid: GridArg,
+    tile: u16,
+    rotation: paperwave::Rotation,
+    frame: FrameSettings,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::content::Result<()> {
+    let mut display = paperwave::open_display(probe, rotation, &frame.driver)?;
+    let spec = paperwave::PanelSpec::for_display(display.as_ref());
+    let mut compositor = paperwave::Compositor::tiled(spec, grid.columns, grid.rows)?;
+
+    let device = paperwave::correction::device_key(probe.display.as_ref());
+    let saved = state_dir()?.join(format!("tiles-{device}.png"));
+    // A missing or mismatched frame (e.g. after changing --rotate) starts blank.
+    if let Ok(previous) = image::open(&saved) {
+        compositor.restore(previous.to_rgb8());
+    }
+
+    let image = paperwave::load_image(path, &paperwave::ImageLimits::default())?;
+    compositor.set_region_image(&tile.to_string(), &image)?;
+    if let Some(dir) = saved.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    compositor.frame().save(&saved)?;
+
+    let composed = DynamicImage::ImageRgb8(compositor.frame().clone());
+    set_frame(display.as_mut(), &composed, &frame)?;
+    Ok(show_frame(display.as_mut(), probe, frame.force)?)
+}
+
+/// Times `stage` over `runs` runs, returning its last result.
+#[cfg(target_os = "linux")]
+fn time_stage<T>(
+    runs: u32,
+    mut stage: impl FnMut() -> paperwave::Result<T>,
+) -> paperwave::Result<(T, Vec<Duration>)> {
+    let mut timings = Vec::with_capacity(runs as usize);
+    let mut result = None;
+    for _ in 0..runs {
+        let started = Instant::now();
+        result = Some(stage()?);
+        timings.push(started.elapsed());
+    }
+    // clap rejects --runs 0, so there is always a result.
+    Ok((result.expect("at least one run"), timings))
+}
+
+#[cfg(target_os = "linux")]
+fn print_bench_row(stage: &str, timings: &[Duration]) {
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let mean = timings.iter().copied().map(millis).sum::<f64>() / timings.len() as f64;
+    let min = timings
+        .iter()
+        .copied()
+        .map(millis)
+        .fold(f64::INFINITY, f64::min);
+    println!("{stage:<22} {mean:>10.1} {min:>10.1}");
+}
+
+/// Times decoding, resizing, lightening, quantizing with each dither
+/// method, packing, and finally the transfer (or full refresh) on the panel.
+#[cfg(target_os = "linux")]
+fn run_bench(
+    path: Option<&Path>,
+    runs: u32,
+    no_refresh: bool,
+    rotation: paperwave::Rotation,
+    frame: FrameSettings,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::Result<()> {
+    let (width, height) = paperwave::panel_dimensions(probe.display.as_ref(), rotation);
+    let encoded = match path {
+        Some(path) => std::fs::read(path)?,
         None => {
-            let mut config = paperwave::InkyUc8159Config::default();
-            config.rotation = rotation;
-            let mut display = paperwave::InkyUc8159::new(config)?;
-            display.set_rotation(rotation);
-            Ok(Box::new(display))
+            // A smooth gradient dithers like a photo rather than flat swatches.
+            let gradient = RgbImage::from_fn(width as u32, height as u32, |x, y| {
+                Rgb([
+                    (x * 255 / width.max(1) as u32) as u8,
+                    (y * 255 / height.max(1) as u32) as u8,
+                    ((x + y) * 255 / (width as u32 + height as u32).max(1)) as u8,
+                ])
+            });
+            let mut png = Vec::new();
+            DynamicImage::ImageRgb8(gradient)
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+            png
         }
+    };
+    let palette: Vec<[f32; 3]> =
+        paperwave::panel_palette(probe.display.as_ref(), frame.saturation.fixed())
+            .iter()
+            .map(|colour| colour.rgb)
+            .collect();
+
+    println!(
+        "Image: {} ({} bytes), panel {width}x{height}, {runs} run(s)",
+        path.map_or("generated gradient".into(), |path| path
+            .display()
+            .to_string()),
+        encoded.len()
+    );
+    println!("{:<22} {:>10} {:>10}", "Stage", "Mean ms", "Min ms");
+
+    let limits = paperwave::ImageLimits::default();
+    let (image, timings) = time_stage(runs, || {
+        paperwave::decode_image(std::io::Cursor::new(&encoded), &limits)
+    })?;
+    print_bench_row("decode", &timings);
+
+    let (rgb, timings) = time_stage(runs, || {
+        Ok(paperwave::clamp_aspect_resize(
+            &image,
+            width as u32,
+            height as u32,
+        ))
+    })?;
+    print_bench_row("resize", &timings);
+
+    let (rgb, timings) = time_stage(runs, || {
+        let mut lightened = rgb.clone();
+        paperwave::displays::common::lighten_image_in_place(&mut lightened, frame.lighten);
+        Ok(lightened)
+    })?;
+    print_bench_row(&format!("lighten ({:.2})", frame.lighten), &timings);
+
+    let mut indices = Vec::new();
+    for method in [
+        paperwave::DitherMethod::FloydSteinberg,
+        paperwave::DitherMethod::Atkinson,
+        paperwave::DitherMethod::None,
+    ] {
+        let options = paperwave::DitherOptions {
+            method,
+            ..Default::default()
+        };
+        let timings;
+        (indices, timings) =
+            time_stage(runs, || Ok(paperwave::dither_with(&rgb, &palette, options)))?;
+        print_bench_row(&format!("quantize {method}"), &timings);
     }
+
+    let (_, timings) = time_stage(runs, || Ok(paperwave::pack_buffer_nibbles(&indices)))?;
+    print_bench_row("pack", &timings);
+
+    let key = panel_key(probe);
+    let panel = paperwave::open_display(probe, rotation, &frame.driver).and_then(|mut display| {
+        display.set_image(&image, frame.saturation.resolve(&image), frame.lighten)?;
+        if no_refresh {
+            time_stage(runs, || display.upload())
+        } else {
+            // Each refresh takes half a minute and wears the panel; once is
+            // enough. It goes through the event bus like any other update,
+            // so the stored frame hash and refresh counters stay accurate.
+            time_stage(1, || {
+                event_bus().show(display.as_mut(), &key, None).map(|_| ())
+            })
+        }
+    });
+    let stage = if no_refresh {
+        "spi transfer"
+    } else {
+        "transfer + refresh"
+    };
+    match panel {
+        Ok((_, timings)) => print_bench_row(stage, &timings),
+        Err(err) => {
+            if !no_refresh {
+                event_bus().error(&key, &err);
+            }
+            println!("{stage:<22} unavailable: {err}");
+        }
+    }
+    Ok(())
 }
 
+/// Optional hardware inputs a slideshow reacts to.
 #[cfg(target_os = "linux")]
-fn run_image(
-    path: &PathBuf,
+struct SlideshowInputs {
+    buttons: Option<paperwave::ButtonMap>,
+    ambient: bool,
+    budget: paperwave::RefreshBudget,
+}
+
+#[cfg(target_os = "linux")]
+fn run_slideshow(
+    path: &Path,
     rotation: paperwave::Rotation,
-    saturation: f32,
-    lighten: f32,
+    frame: FrameSettings,
+    inputs: &SlideshowInputs,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::content::Result<()> {
+    use paperwave::{ButtonAction, ContentProvider, PlaylistSource};
+
+    enum Slide {
+        Image(PathBuf),
+        Directory(paperwave::DirectoryProvider),
+        Provider(String),
+    }
+
+    let playlist = paperwave::Playlist::load(path)?;
+    let lap: Duration = playlist
+        .items
+        .iter()
+        .map(|item| playlist.item_duration(item))
+        .sum();
+    if let Some(overrun) = inputs
+        .budget
+        .check_interval(lap / playlist.items.len() as u32)
+    {
+        eprintln!("Warning: this playlist plans {overrun}; frequent refreshes shorten panel life");
+    }
+    let mut display = paperwave::open_display(probe, rotation, &frame.driver)?;
+    let spec = paperwave::PanelSpec::for_display(display.as_ref());
+    let min_interval = display.capabilities().min_refresh_interval;
+    if let Some((index, item)) = playlist
+        .items
+        .iter()
+        .enumerate()
+        .find(|(_, item)| playlist.item_duration(item) < min_interval)
+    {
+        eprintln!(
+            "Warning: playlist item {} is shown for {}s; this panel should refresh at most every {}s",
+            index + 1,
+            playlist.item_duration(item).as_secs(),
+            min_interval.as_secs()
+        );
+    }
+
+    let buttons = inputs.buttons.as_ref();
+    let mut events = match buttons {
+        // The same chip the display was opened on.
+        Some(_) => Some(paperwave::ButtonEvents::open(
+            frame
+                .driver
+                .gpio_chip
+                .as_deref()
+                .unwrap_or("/dev/gpiochip0"),
+            &paperwave::ButtonPins::for_display(probe.display.as_ref()),
+        )?),
+        None => None,
+    };
+    let light_sensor = if inputs.ambient {
+        let sensor = probe.find_light_sensor(&paperwave::LinuxDevices);
+        match &sensor {
+            Some(sensor) => println!("Light sensor: {sensor}"),
+            None => eprintln!("No ambient light sensor detected; ignoring --ambient"),
+        }
+        sensor
+    } else {
+        None
+    };
+    let ambient = paperwave::AmbientPolicy::default();
+    let mut providers = paperwave::ContentRegistry::new();
+    providers.register(Box::new(paperwave::ClockProvider::new(
+        paperwave::ClockFace::default(),
+    )));
+    providers.register(Box::new(paperwave::ChartProvider::new(chart_palette(
+        probe,
+        frame.saturation.fixed(),
+    ))));
+    providers.register(Box::new(paperwave::StatusProvider::new()));
+
+    let mut slides: Vec<Slide> = Vec::with_capacity(playlist.items.len());
+    for (index, item) in playlist.items.iter().enumerate() {
+        slides.push(match &item.source {
+            PlaylistSource::Image(path) => Slide::Image(path.clone()),
+            PlaylistSource::Directory(dir) => Slide::Directory(paperwave::DirectoryProvider::new(
+                dir.clone(),
+                playlist.item_duration(item),
+            )),
+            PlaylistSource::Provider(name) if providers.get(name).is_some() => {
+                Slide::Provider(name.clone())
+            }
+            PlaylistSource::Provider(name) => {
+                return Err(paperwave::ContentError::InvalidPlaylist(format!(
+                    "{}: item {}: unknown provider `{name}` (available: {})",
+                    path.display(),
+                    index + 1,
+                    providers.names().join(", ")
+                )));
+            }
+        });
+    }
+
+    let count = slides.len();
+    let mut index = 0;
+    // Consecutive failed items; a full lap of failures means nothing can be shown.
+    let mut failures = 0;
+    loop {
+        let item = &playlist.items[index];
+        let mut frame = FrameSettings {
+            saturation: item
+                .saturation
+                .map_or(frame.saturation, SaturationArg::Fixed),
+            lighten: item.lighten.unwrap_or(frame.lighten),
+            ..frame.clone()
+        };
+        let lux = light_sensor.as_ref().and_then(|sensor| sensor.read_lux());
+        if let Some(lux) = lux {
+            frame.lighten = ambient.lighten_for(lux, frame.lighten);
+        }
+        // In the dark nobody sees the refresh, so keep the current frame.
+        let result = if lux.is_some_and(|lux| ambient.is_dark(lux)) {
+            Ok(())
+        } else {
+            match &mut slides[index] {
+                Slide::Image(path) => {
+                    paperwave::load_image(path, &paperwave::ImageLimits::default())
+                        .map_err(paperwave::ContentError::from)
+                }
+                Slide::Directory(provider) => provider.render(&spec),
+                Slide::Provider(name) => providers.render(name, &spec),
+            }
+            .and_then(|image| {
+                set_frame(display.as_mut(), &image, &frame)
+                    .and_then(|()| show_frame(display.as_mut(), probe, frame.force))
+                    .map_err(Into::into)
+            })
+        };
+
+        let last = index + 1 == count;
+        let mut step_back = false;
+        match result {
+            Err(err) => {
+                eprintln!("Skipping playlist item {}: {err}", index + 1);
+                emit_content_error(&panel_key(probe), &err);
+                failures += 1;
+                if failures >= count {
+                    return Err(paperwave::ContentError::InvalidPlaylist(format!(
+                        "{}: no items could be displayed",
+                        path.display()
+                    )));
+                }
+            }
+            Ok(()) if playlist.repeat || !last => {
+                failures = 0;
+                let deadline = Instant::now() + playlist.item_duration(item);
+                while let Some(button) = wait_for_press(events.as_mut(), deadline) {
+                    let action = buttons.map_or(&ButtonAction::Ignore, |map| map.action(button));
+                    let result: paperwave::content::Result<()> = match action {
+                        ButtonAction::NextItem => break,
+                        ButtonAction::PreviousItem => {
+                            step_back = true;
+                            break;
+                        }
+                        // Redisplaying is an explicit request to refresh.
+                        ButtonAction::Redisplay => {
+                            show_frame(display.as_mut(), probe, true).map_err(Into::into)
+                        }
+                        ButtonAction::Clear => {
+                            display.clear(1);
+                            show_frame(display.as_mut(), probe, frame.force).map_err(Into::into)
+                        }
+                        ButtonAction::RunProvider(name) => {
+                            providers.render(name, &spec).and_then(|image| {
+                                set_frame(display.as_mut(), &image, &frame)
+                                    .and_then(|()| show_frame(display.as_mut(), probe, frame.force))
+                                    .map_err(Into::into)
+                            })
+                        }
+                        ButtonAction::Ignore => Ok(()),
+                    };
+                    if let Err(err) = result {
+                        eprintln!("Button {button} action failed: {err}");
+                    }
+                }
+            }
+            Ok(()) => failures = 0,
+        }
+
+        if step_back {
+            index = (index + count - 1) % count;
+        } else if last && !playlist.repeat {
+            return Ok(());
+        } else {
+            index = (index + 1) % count;
+        }
+    }
+}
+
+/// Blocks until a button is pressed or `deadline` passes, sleeping through the
+/// whole interval when buttons are not in use.
+#[cfg(target_os = "linux")]
+fn wait_for_press(
+    events: Option<&mut paperwave::ButtonEvents>,
+    deadline: std::time::Instant,
+) -> Option<paperwave::Button> {
+    match events {
+        Some(events) => events.wait_until(deadline),
+        None => {
+            std::thread::sleep(deadline.saturating_duration_since(std::time::Instant::now()));
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_analyze(
+    path: &Path,
+    rotation: paperwave::Rotation,
+    frame: &FrameSettings,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::Result<()> {
+    let image = paperwave::load_image(path, &paperwave::ImageLimits::default())?;
+    let options = render_options(&image, frame);
+    let (width, height) = paperwave::panel_dimensions(probe.display.as_ref(), rotation);
+    let rgb = prepare_frame(&image, frame, &options, width as u32, height as u32);
+    let report = paperwave::analyze_prepared(&image, &rgb, probe.display.as_ref(), &options);
+
+    println!(
+        "Image: {} ({}x{})",
+        path.display(),
+        report.source_width,
+        report.source_height
+    );
+    match &probe.display {
+        Some(spec) => println!("Panel: {spec}"),
+        None => println!("Panel: not detected, assuming 600x448 UC8159"),
+    }
+    println!(
+        "Rendered at {}x{} with saturation {:.2}, lighten {:.2}",
+        report.panel_width, report.panel_height, report.saturation, report.lighten
+    );
+
+    println!("Palette usage:");
+    for usage in &report.palette {
+        let [r, g, b] = usage.colour;
+        let bar = "#".repeat((usage.fraction * 40.0).round() as usize);
+        println!(
+            "  {:<7} #{r:02x}{g:02x}{b:02x} {:>5.1}% {bar}",
+            usage.name,
+            usage.fraction * 100.0
+        );
+    }
+
+    println!("Mean dither error: {:.1}", report.mean_error);
+    println!(
+        "Clipped highlights: {:.1}%",
+        report.clipped_highlights * 100.0
+    );
+    println!("Clipped shadows: {:.1}%", report.clipped_shadows * 100.0);
+    println!("Mean chroma: {:.2}", report.mean_chroma);
+    println!(
+        "Recommended: --saturation {:.2} --lighten {:.2}",
+        report.recommended_saturation, report.recommended_lighten
+    );
+    Ok(())
+}
+
+/// Settings for one half of a comparison frame.
+#[cfg(target_os = "linux")]
+struct CompareSide {
+    dither: paperwave::DitherMethod,
+    saturation: Option<f32>,
+}
+
+/// Prepares `path` for each half of the panel like [`set_frame`] would and
+/// quantizes the halves separately, with a black divider between them.
+#[cfg(target_os = "linux")]
+fn run_compare(
+    path: &Path,
+    rotation: paperwave::Rotation,
+    frame: FrameSettings,
+    sides: &[CompareSide; 2],
     probe: &paperwave::ProbeInfo,
 ) -> paperwave::Result<()> {
-    let mut display = create_display(rotation, probe)?;
-    display.set_image_from_path(path.as_path(), saturation, lighten)?;
-    display.show()
+    let image = paperwave::load_image(path, &paperwave::ImageLimits::default())?;
+    let mut display = paperwave::open_display(probe, rotation, &frame.driver)?;
+    let (width, height) = display.input_dimensions();
+    let (width, height) = (width as usize, height as usize);
+    let half = width / 2;
+
+    let options = render_options(&image, &frame);
+    let rgb = prepare_frame(&image, &frame, &options, half as u32, height as u32);
+
+    for (side, offset, label) in [(&sides[0], 0, "Left"), (&sides[1], width - half, "Right")] {
+        let saturation = side.saturation.unwrap_or(options.saturation);
+        println!(
+            "{label}: {} dither, saturation {saturation:.2}",
+            side.dither
+        );
+
+        let palette = paperwave::panel_palette(probe.display.as_ref(), saturation);
+        let colours: Vec<[f32; 3]> = palette.iter().map(|colour| colour.rgb).collect();
+        let dither = paperwave::DitherOptions {
+            method: side.dither,
+            ..options.dither
+        };
+        let indices = paperwave::dither_with(&rgb, &colours, dither);
+        for (i, index) in indices.iter().enumerate() {
+            display.set_pixel(offset + i % half, i / half, palette[*index as usize].value);
+        }
+    }
+
+    for y in 0..height {
+        for x in half.saturating_sub(1)..(width - half + 1).min(width) {
+            display.set_pixel(x, y, 0);
+        }
+    }
+
+    show_frame(display.as_mut(), probe, frame.force)
 }
 
+/// Renders the colour chart with the palette the display will quantize
+/// against, so each swatch lands on exactly one ink. That is the unblended
+/// reference palette (saturation 1.0) calibration measures against, and the
+/// chart is shown without lightening, whatever `--saturation` and
+/// `--lighten` say.
 #[cfg(target_os = "linux")]
-fn print_probe(probe: &paperwave::ProbeInfo) {
+fn run_chart(
+    output: Option<&Path>,
+    rotation: paperwave::Rotation,
+    frame: FrameSettings,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::Result<()> {
+    let saturation = 1.0;
+    let (width, height) = paperwave::panel_dimensions(probe.display.as_ref(), rotation);
+    let chart = paperwave::render_colour_chart(
+        width as u32,
+        height as u32,
+        &chart_palette(probe, saturation),
+    );
+
+    match output {
+        Some(path) => Ok(chart.save(path)?),
+        None => {
+            let mut display = paperwave::open_display(probe, rotation, &frame.driver)?;
+            display.set_image(&DynamicImage::ImageRgb8(chart), saturation, 0.0)?;
+            show_frame(display.as_mut(), probe, frame.force)
+        }
+    }
+}
+
+/// Fits a correction matrix mapping the measured swatch colours onto the
+/// reference ink palette (saturation 1.0) and stores it for this panel.
+#[cfg(target_os = "linux")]
+fn run_calibrate(
+    measured: &[String],
+    reset: bool,
+    dry_run: bool,
+    probe: &paperwave::ProbeInfo,
+) -> paperwave::content::Result<()> {
+    let device = paperwave::correction::device_key(probe.display.as_ref());
+    let mut store = open_state()?;
+
+    if reset {
+        if paperwave::ColourMatrix::remove(&mut store, &device) {
+            store.save()?;
+            println!("Removed colour correction for {device}");
+        } else {
+            println!("No colour correction stored for {device}");
+        }
+        return Ok(());
+    }
+
+    let measured = measured
+        .iter()
+        .map(|value| parse_rgb(value))
+        .collect::<paperwave::Result<Vec<_>>>()?;
+    let nominal: Vec<[f32; 3]> = paperwave::panel_palette(probe.display.as_ref(), 1.0)
+        .iter()
+        .map(|colour| colour.rgb)
+        .collect();
+    let matrix = paperwave::ColourMatrix::fit(&measured, &nominal)?;
+    println!("Colour correction for {device}:\n{matrix}");
+
+    if !dry_run {
+        matrix.store(&mut store, &device)?;
+        store.save()?;
+        println!("Saved to {}", store.path().display());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn parse_rgb(value: &str) -> paperwave::Result<[f32; 3]> {
+    let invalid = || {
+        paperwave::InkyError::InvalidCalibration(format!("expected R,G,B (0-255), got `{value}`"))
+    };
+    let channels: Vec<f32> = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<u8>().map(f32::from))
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    channels.try_into().map_err(|_| invalid())
+}
+
+#[cfg(target_os = "linux")]
+fn chart_palette(probe: &paperwave::ProbeInfo, saturation: f32) -> Vec<[u8; 3]> {
+    paperwave::panel_palette(probe.display.as_ref(), saturation)
+        .iter()
+        .map(|colour| colour.rgb.map(|channel| channel.round() as u8))
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn print_probe(probe: &paperwave::ProbeInfo, budget: paperwave::RefreshBudget) {
     use paperwave::I2cProbeStatus;
     use std::fmt::Write as _;
 
@@ -208,6 +1975,9 @@ fn print_probe(probe: &paperwave::ProbeInfo) {
             } else {
                 println!("EEPROM: {info}");
             }
+            if let Some(warning) = info.warning {
+                println!("EEPROM warning: {warning}");
+            }
         }
         (None, Some(err)) => println!("EEPROM: error - {err}"),
         (None, None) => println!("EEPROM: not found"),
@@ -219,6 +1989,23 @@ fn print_probe(probe: &paperwave::ProbeInfo) {
         println!("Display: not detected (fallback to 600x448)");
     }
 
+    match open_state() {
+        Ok(store) => {
+            let device = paperwave::correction::device_key(probe.display.as_ref());
+            let stats = paperwave::RefreshStats::load(&store, &device);
+            println!("Refreshes: {stats} (budget {} per day)", budget.per_day);
+            let streak = paperwave::FailureStreak::load(&store, &device);
+            match paperwave::Degraded::load(&store, &device) {
+                Some(degraded) => println!("Health: {degraded}"),
+                None if streak.count > 0 => {
+                    println!("Health: last {} updates failed", streak.count)
+                }
+                None => println!("Health: ok"),
+            }
+        }
+        Err(err) => println!("Refreshes: unavailable - {err}"),
+    }
+
     if probe.i2c_buses.is_empty() {
         println!("I2C buses: none detected");
     } else {
@@ -0,0 +1,66 @@
+//! Failure notifications. They are delivered by a user-supplied shell
+//! command, so any transport works: `curl` to a webhook, ntfy.sh or
+//! Pushover, `mosquitto_pub` for an MQTT topic, and so on.
+
+use std::fmt;
+use std::process::Command;
+
+use crate::displays::{InkyError, Result};
+
+/// Why a notification is sent, passed to the command as `PAPERWAVE_EVENT`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifyEvent {
+    /// Updates have failed the configured number of times in a row.
+    Failing,
+    /// The controller's busy line never cleared and the driver gave up.
+    Degraded,
+    /// An update succeeded after a failure was notified.
+    Recovered,
+}
+
+impl NotifyEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotifyEvent::Failing => "failing",
+            NotifyEvent::Degraded => "degraded",
+            NotifyEvent::Recovered => "recovered",
+        }
+    }
+}
+
+impl fmt::Display for NotifyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Runs the configured command through `sh -c` for each notification.
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    command: String,
+}
+
+impl Notifier {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+        }
+    }
+
+    /// Runs the command and waits for it, with `PAPERWAVE_EVENT`,
+    /// `PAPERWAVE_PANEL` (the panel's state key, e.g. `uc8159_600x448`) and
+    /// `PAPERWAVE_MESSAGE` set in its environment.
+    pub fn send(&self, event: NotifyEvent, panel: &str, message: &str) -> Result<()> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("PAPERWAVE_EVENT", event.as_str())
+            .env("PAPERWAVE_PANEL", panel)
+            .env("PAPERWAVE_MESSAGE", message)
+            .status()?;
+        if !status.success() {
+            return Err(InkyError::Notify(format!("`{}` {status}", self.command)));
+        }
+        Ok(())
+    }
+}
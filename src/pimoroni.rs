@@ -0,0 +1,234 @@
+//! Frames saved with Pimoroni's Python `inky` library, for pushing to the
+//! panel unchanged. Its buffer holds one controller colour code per pixel in
+//! the panel's native orientation, which is also the layout of this crate's
+//! driver buffers (see [`InkyDisplay::set_panel_buffer`]). Supported files:
+//!
+//! - palettized PNGs whose palette indices are the colour codes, as written
+//!   by `Image.save` on a `P` mode image quantized to the inky palette;
+//! - NumPy `.npy` dumps of the `uint8` buffer (`numpy.save(inky.buf)`);
+//! - raw buffers, one code per byte (`inky.buf.tofile`);
+//! - raw buffers packed two pixels per byte, high nibble first, as sent to
+//!   the UC8159.
+//!
+//! [`InkyDisplay::set_panel_buffer`]: crate::InkyDisplay::set_panel_buffer
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::displays::{InkyError, Result};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Reads the frame at `path` for a panel `width`x`height` pixels in its
+/// native orientation, returning one colour code per pixel.
+pub fn load_frame(path: &Path, width: u16, height: u16) -> Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    decode_frame(&bytes, width, height)
+}
+
+/// Decodes a frame held in memory; the format is detected from its contents.
+pub fn decode_frame(bytes: &[u8], width: u16, height: u16) -> Result<Vec<u8>> {
+    let pixels = width as usize * height as usize;
+    if bytes.starts_with(PNG_SIGNATURE) {
+        decode_png(bytes, width, height)
+    } else if bytes.starts_with(NPY_MAGIC) {
+        decode_npy(bytes, width, height)
+    } else if bytes.len() == pixels {
+        Ok(bytes.to_vec())
+    } else if bytes.len() == pixels.div_ceil(2) {
+        Ok(unpack_nibbles(bytes, pixels))
+    } else {
+        Err(InkyError::InvalidFrame(format!(
+            "{} bytes is neither a PNG, a .npy file nor a raw {width}x{height} buffer \
+             ({pixels} bytes, or {} packed)",
+            bytes.len(),
+            pixels.div_ceil(2)
+        )))
+    }
+}
+
+fn unpack_nibbles(bytes: &[u8], pixels: usize) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|&byte| [byte >> 4, byte & 0x0F])
+        .take(pixels)
+        .collect()
+}
+
+fn decode_png(bytes: &[u8], width: u16, height: u16) -> Result<Vec<u8>> {
+    let invalid = |err: png::DecodingError| InkyError::InvalidFrame(err.to_string());
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| InkyError::InvalidFrame("PNG is too large".to_string()))?;
+    let mut buf = vec![0; size];
+    let info = reader.next_frame(&mut buf).map_err(invalid)?;
+
+    if info.color_type != png::ColorType::Indexed {
+        return Err(InkyError::InvalidFrame(format!(
+            "PNG is {:?}, not palettized; show it as a normal image instead",
+            info.color_type
+        )));
+    }
+    check_dimensions((info.width, info.height), width, height)?;
+
+    // Indices below 8 bits are packed most significant first within each
+    // row, and rows are padded to whole bytes.
+    let bits = info.bit_depth as usize;
+    let per_byte = 8 / bits;
+    let mask = ((1u16 << bits) - 1) as u8;
+    let mut codes = Vec::with_capacity(width as usize * height as usize);
+    for row in buf.chunks(info.line_size).take(height as usize) {
+        codes.extend((0..width as usize).map(|x| {
+            let shift = 8 - bits * (x % per_byte + 1);
+            (row[x / per_byte] >> shift) & mask
+        }));
+    }
+    Ok(codes)
+}
+
+fn decode_npy(bytes: &[u8], width: u16, height: u16) -> Result<Vec<u8>> {
+    let invalid = |reason: &str| InkyError::InvalidFrame(format!(".npy {reason}"));
+    let (header_len, start) = match bytes.get(6) {
+        Some(1) => (
+            bytes
+                .get(8..10)
+                .map(|len| u16::from_le_bytes([len[0], len[1]]) as usize),
+            10,
+        ),
+        Some(2 | 3) => (
+            bytes
+                .get(8..12)
+                .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize),
+            12,
+        ),
+        _ => return Err(invalid("version is not supported")),
+    };
+    let header_len = header_len.ok_or_else(|| invalid("header is truncated"))?;
+    let header = bytes
+        .get(start..start + header_len)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| invalid("header is truncated"))?;
+
+    if !["'|u1'", "'<u1'", "'>u1'", "'u1'"]
+        .iter()
+        .any(|descr| header.contains(&format!("'descr': {descr}")))
+    {
+        return Err(invalid("data is not uint8"));
+    }
+    if header.contains("'fortran_order': True") {
+        return Err(invalid("data is in Fortran order"));
+    }
+    let shape = header
+        .split_once("'shape': (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(shape, _)| {
+            shape
+                .split(',')
+                .map(str::trim)
+                .filter(|dim| !dim.is_empty())
+                .map(str::parse::<u32>)
+                .collect::<std::result::Result<Vec<_>, _>>()
+        })
+        .and_then(|shape| shape.ok())
+        .ok_or_else(|| invalid("header has no shape"))?;
+    let [rows, columns] = shape[..] else {
+        return Err(invalid("array is not two-dimensional"));
+    };
+    check_dimensions((columns, rows), width, height)?;
+
+    let pixels = width as usize * height as usize;
+    bytes
+        .get(start + header_len..start + header_len + pixels)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| invalid("data is truncated"))
+}
+
+fn check_dimensions(received: (u32, u32), width: u16, height: u16) -> Result<()> {
+    if received != (width as u32, height as u32) {
+        return Err(InkyError::InvalidImageDimensions {
+            expected: (width, height),
+            received,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 6, 5, 4, 3];
+
+    fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = header.to_string();
+        // NumPy pads the header with spaces to a multiple of 64 bytes.
+        while !(10 + header.len() + 1).is_multiple_of(64) {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn indexed_png(bit_depth: png::BitDepth, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 4, 3);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(bit_depth);
+        encoder.set_palette(vec![0; 16 * 3]);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(data)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn decodes_raw_buffers() {
+        assert_eq!(decode_frame(&CODES, 4, 3).unwrap(), CODES);
+        let packed = [0x01, 0x23, 0x45, 0x67, 0x65, 0x43];
+        assert_eq!(decode_frame(&packed, 4, 3).unwrap(), CODES);
+    }
+
+    #[test]
+    fn decodes_npy() {
+        let header = "{'descr': '|u1', 'fortran_order': False, 'shape': (3, 4), }";
+        assert_eq!(decode_frame(&npy(header, &CODES), 4, 3).unwrap(), CODES);
+
+        let transposed = "{'descr': '|u1', 'fortran_order': False, 'shape': (4, 3), }";
+        assert!(matches!(
+            decode_frame(&npy(transposed, &CODES), 4, 3),
+            Err(InkyError::InvalidImageDimensions { .. })
+        ));
+        let floats = "{'descr': '<f8', 'fortran_order': False, 'shape': (3, 4), }";
+        assert!(decode_frame(&npy(floats, &CODES), 4, 3).is_err());
+        assert!(decode_frame(&npy(header, &CODES[..8]), 4, 3).is_err());
+    }
+
+    #[test]
+    fn decodes_palettized_png() {
+        let png = indexed_png(png::BitDepth::Eight, &CODES);
+        assert_eq!(decode_frame(&png, 4, 3).unwrap(), CODES);
+
+        // Two pixels per byte, one two-byte row per scanline.
+        let png = indexed_png(png::BitDepth::Four, &[0x01, 0x23, 0x45, 0x67, 0x65, 0x43]);
+        assert_eq!(decode_frame(&png, 4, 3).unwrap(), CODES);
+    }
+
+    #[test]
+    fn rejects_unknown_sizes() {
+        assert!(matches!(
+            decode_frame(&CODES[..5], 4, 3),
+            Err(InkyError::InvalidFrame(_))
+        ));
+    }
+}
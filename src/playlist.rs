@@ -0,0 +1,219 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::content::{ContentError, Result};
+use crate::document::{self, Entry, Section, duration_value};
+use crate::state::StateValue;
+
+const DEFAULT_DURATION: Duration = Duration::from_secs(600);
+
+/// A slideshow definition loaded from a TOML file:
+///
+/// ```toml
+/// duration = 900      # default seconds per item
+/// repeat = true
+///
+/// [[item]]
+/// path = "photos/beach.jpg"
+/// saturation = 0.6
+///
+/// [[item]]
+/// directory = "/srv/sync/family"
+/// duration = 3600
+/// lighten = 0.2
+///
+/// [[item]]
+/// provider = "clock"
+/// duration = 60
+/// ```
+///
+/// Relative paths are resolved against the playlist file's directory.
+/// Provider names are resolved by the caller, e.g. through a
+/// `ContentRegistry`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playlist {
+    pub default_duration: Duration,
+    pub repeat: bool,
+    pub items: Vec<PlaylistItem>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaylistItem {
+    pub source: PlaylistSource,
+    pub duration: Option<Duration>,
+    pub saturation: Option<f32>,
+    pub lighten: Option<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlaylistSource {
+    /// A single image file.
+    Image(PathBuf),
+    /// A directory cycled through one image per visit, see `DirectoryProvider`.
+    Directory(PathBuf),
+    /// A named content provider such as `clock`, rendered on every visit.
+    Provider(String),
+}
+
+impl PlaylistItem {
+    pub fn duration_or(&self, default: Duration) -> Duration {
+        self.duration.unwrap_or(default)
+    }
+}
+
+impl Playlist {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        Self::parse(&contents, base)
+            .map_err(|err| ContentError::InvalidPlaylist(format!("{}: {err}", path.display())))
+    }
+
+    pub fn parse(contents: &str, base_dir: &Path) -> std::result::Result<Self, String> {
+        let mut playlist = Playlist {
+            default_duration: DEFAULT_DURATION,
+            repeat: true,
+            items: Vec::new(),
+        };
+
+        for section in document::parse(contents)? {
+            match (section.name.as_deref(), section.array) {
+                (None, _) => parse_header(&mut playlist, &section)?,
+                (Some("item"), true) => playlist.items.push(parse_item(&section, base_dir)?),
+                (Some(name), _) => {
+                    return Err(format!(
+                        "line {}: unknown section `{name}` (expected [[item]])",
+                        section.line
+                    ));
+                }
+            }
+        }
+
+        if playlist.items.is_empty() {
+            return Err("playlist has no [[item]] entries".to_string());
+        }
+        Ok(playlist)
+    }
+
+    pub fn item_duration(&self, item: &PlaylistItem) -> Duration {
+        item.duration_or(self.default_duration)
+    }
+}
+
+fn parse_header(playlist: &mut Playlist, section: &Section) -> std::result::Result<(), String> {
+    for entry in &section.entries {
+        match entry.key.as_str() {
+            "duration" => playlist.default_duration = duration_value(entry)?,
+            "repeat" => playlist.repeat = bool_value(entry)?,
+            other => return Err(format!("line {}: unknown key `{other}`", entry.line)),
+        }
+    }
+    Ok(())
+}
+
+fn parse_item(section: &Section, base_dir: &Path) -> std::result::Result<PlaylistItem, String> {
+    let mut source = None;
+    let mut item = PlaylistItem {
+        source: PlaylistSource::Image(PathBuf::new()),
+        duration: None,
+        saturation: None,
+        lighten: None,
+    };
+
+    for entry in &section.entries {
+        match entry.key.as_str() {
+            "path" | "directory" | "provider" => {
+                if source.is_some() {
+                    return Err(format!(
+                        "line {}: item has more than one of `path`/`directory`/`provider`",
+                        entry.line
+                    ));
+                }
+                let value = str_value(entry)?;
+                source = Some(match entry.key.as_str() {
+                    "path" => PlaylistSource::Image(base_dir.join(value)),
+                    "directory" => PlaylistSource::Directory(base_dir.join(value)),
+                    _ => PlaylistSource::Provider(value.to_string()),
+                });
+            }
+            "duration" => item.duration = Some(duration_value(entry)?),
+            "saturation" => item.saturation = Some(unit_value(entry)?),
+            "lighten" => item.lighten = Some(unit_value(entry)?),
+            other => return Err(format!("line {}: unknown key `{other}`", entry.line)),
+        }
+    }
+
+    item.source = source.ok_or_else(|| {
+        format!(
+            "line {}: item needs a `path`, `directory` or `provider`",
+            section.line
+        )
+    })?;
+    Ok(item)
+}
+
+fn str_value(entry: &Entry) -> std::result::Result<&str, String> {
+    match &entry.value {
+        StateValue::String(value) if !value.is_empty() => Ok(value),
+        _ => Err(format!(
+            "line {}: `{}` must be a non-empty string",
+            entry.line, entry.key
+        )),
+    }
+}
+
+fn bool_value(entry: &Entry) -> std::result::Result<bool, String> {
+    match entry.value {
+        StateValue::Boolean(value) => Ok(value),
+        _ => Err(format!(
+            "line {}: `{}` must be true or false",
+            entry.line, entry.key
+        )),
+    }
+}
+
+fn unit_value(entry: &Entry) -> std::result::Result<f32, String> {
+    let value = match entry.value {
+        StateValue::Integer(value) => value as f64,
+        StateValue::Float(value) => value,
+        _ => -1.0,
+    };
+    if !(0.0..=1.0).contains(&value) {
+        return Err(format!(
+            "line {}: `{}` must be between 0.0 and 1.0",
+            entry.line, entry.key
+        ));
+    }
+    Ok(value as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_name_one_source() {
+        let playlist = Playlist::parse(
+            "duration = 60\n\n[[item]]\npath = \"beach.jpg\"\n\n[[item]]\nprovider = \"clock\"\nduration = 30\n",
+            Path::new("/srv/frame"),
+        )
+        .unwrap();
+        let sources: Vec<_> = playlist.items.iter().map(|item| &item.source).collect();
+        assert_eq!(
+            sources,
+            [
+                &PlaylistSource::Image(PathBuf::from("/srv/frame/beach.jpg")),
+                &PlaylistSource::Provider("clock".to_string()),
+            ]
+        );
+        assert_eq!(
+            playlist.item_duration(&playlist.items[1]),
+            Duration::from_secs(30)
+        );
+
+        let both = "[[item]]\npath = \"a.jpg\"\nprovider = \"clock\"\n";
+        assert!(Playlist::parse(both, Path::new(".")).is_err());
+        assert!(Playlist::parse("[[item]]\nprovider = \"\"\n", Path::new(".")).is_err());
+    }
+}
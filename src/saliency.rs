@@ -0,0 +1,155 @@
+//! Subject detection for weighting image adjustments.
+//!
+//! Lightening flattens skin and other subject detail long before it hurts a
+//! sky or wall, so a saliency map (local edge density with a mild bias
+//! towards the centre of the frame) lets the lighten strength vary across
+//! the image: gentler on the subject, stronger on the background.
+
+use image::RgbImage;
+use image::imageops::{self, FilterType};
+
+/// Longest side of the thumbnail the map is computed on; subjects are
+/// large features, and the map is smoothed anyway.
+const MAP_SIZE: u32 = 64;
+/// Box blur radius, in thumbnail pixels, that spreads edge responses over
+/// the surrounding region.
+const BLUR_RADIUS: usize = 3;
+/// Fraction of the lighten amount applied at full saliency.
+const SUBJECT_LIGHTEN: f32 = 0.25;
+/// Fraction of the lighten amount applied where saliency is zero.
+const BACKGROUND_LIGHTEN: f32 = 1.25;
+
+/// Per-pixel saliency from 0.0 (background) to 1.0 (subject), at a reduced
+/// resolution and sampled bilinearly.
+#[derive(Clone, Debug)]
+pub struct SaliencyMap {
+    width: usize,
+    height: usize,
+    scale_x: f32,
+    scale_y: f32,
+    weights: Vec<f32>,
+}
+
+impl SaliencyMap {
+    pub fn detect(rgb: &RgbImage) -> Self {
+        let (full_w, full_h) = rgb.dimensions();
+        let scale = (MAP_SIZE as f32 / full_w.max(full_h).max(1) as f32).min(1.0);
+        let width = ((full_w as f32 * scale).round() as usize).max(1);
+        let height = ((full_h as f32 * scale).round() as usize).max(1);
+        let thumb = imageops::resize(rgb, width as u32, height as u32, FilterType::Triangle);
+        let luma: Vec<f32> = thumb
+            .pixels()
+            .map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32)
+            .collect();
+
+        let at = |x: usize, y: usize| luma[y * width + x];
+        let mut weights: Vec<f32> = (0..width * height)
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                let dx = at((x + 1).min(width - 1), y) - at(x.saturating_sub(1), y);
+                let dy = at(x, (y + 1).min(height - 1)) - at(x, y.saturating_sub(1));
+                dx.abs() + dy.abs()
+            })
+            .collect();
+        box_blur(&mut weights, width, height);
+        box_blur(&mut weights, width, height);
+
+        // Subjects are usually framed towards the middle.
+        for (index, weight) in weights.iter_mut().enumerate() {
+            let nx = (index % width) as f32 / width as f32 * 2.0 - 1.0;
+            let ny = (index / width) as f32 / height as f32 * 2.0 - 1.0;
+            *weight *= 1.0 - 0.25 * (nx * nx + ny * ny);
+        }
+        let peak = weights.iter().copied().fold(0.0f32, f32::max);
+        if peak > 0.0 {
+            weights.iter_mut().for_each(|weight| *weight /= peak);
+        }
+
+        Self {
+            width,
+            height,
+            scale_x: width as f32 / full_w.max(1) as f32,
+            scale_y: height as f32 / full_h.max(1) as f32,
+            weights,
+        }
+    }
+
+    /// Saliency at full-resolution pixel (`x`, `y`).
+    pub fn weight(&self, x: u32, y: u32) -> f32 {
+        let fx = ((x as f32 + 0.5) * self.scale_x - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let fy = ((y as f32 + 0.5) * self.scale_y - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+        let at = |x: usize, y: usize| self.weights[y * self.width + x];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+        top + (bottom - top) * ty
+    }
+}
+
+/// Lightens like [`lighten_image_in_place`](crate::displays::common::lighten_image_in_place),
+/// scaling `lighten` down on the subject and up on the background.
+pub fn lighten_weighted_in_place(rgb: &mut RgbImage, lighten: f32, map: &SaliencyMap) {
+    let lighten = lighten.clamp(0.0, 1.0);
+    if lighten <= 0.0 {
+        return;
+    }
+    for (x, y, pixel) in rgb.enumerate_pixels_mut() {
+        let saliency = map.weight(x, y);
+        let share = BACKGROUND_LIGHTEN + (SUBJECT_LIGHTEN - BACKGROUND_LIGHTEN) * saliency;
+        let gamma = 1.0 - 0.5 * (lighten * share).min(1.0);
+        for channel in pixel.0.iter_mut() {
+            let value = (*channel as f32 / 255.0).powf(gamma);
+            *channel = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Separable box blur with clamped edges.
+fn box_blur(values: &mut [f32], width: usize, height: usize) {
+    let mut scratch = vec![0.0f32; values.len()];
+    let radius = BLUR_RADIUS as isize;
+    for (stride, count, lines, line_stride) in
+        [(1, width, height, width), (width, height, width, 1)]
+    {
+        for line in 0..lines {
+            let base = line * line_stride;
+            for i in 0..count as isize {
+                let sum: f32 = (i - radius..=i + radius)
+                    .map(|j| values[base + j.clamp(0, count as isize - 1) as usize * stride])
+                    .sum();
+                scratch[base + i as usize * stride] = sum / (2 * radius + 1) as f32;
+            }
+        }
+        values.copy_from_slice(&scratch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detailed_centre_is_lightened_less_than_flat_background() {
+        // A checkerboard subject in the middle of a flat grey frame.
+        let rgb = RgbImage::from_fn(120, 80, |x, y| {
+            let inside = (40..80).contains(&x) && (25..55).contains(&y);
+            let value = if inside && (x / 4 + y / 4) % 2 == 0 {
+                60
+            } else {
+                100
+            };
+            image::Rgb([value; 3])
+        });
+        let map = SaliencyMap::detect(&rgb);
+        assert!(map.weight(60, 40) > 0.5);
+        assert!(map.weight(5, 5) < 0.1);
+
+        let mut lightened = rgb.clone();
+        lighten_weighted_in_place(&mut lightened, 0.5, &map);
+        // (62, 41) and (5, 5) both start at 100.
+        assert_eq!(rgb.get_pixel(62, 41)[0], 100);
+        assert!(lightened.get_pixel(62, 41)[0] < lightened.get_pixel(5, 5)[0]);
+    }
+}
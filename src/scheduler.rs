@@ -0,0 +1,601 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::content::{ContentError, Result};
+use crate::displays::common::fnv1a;
+use crate::state::StateStore;
+
+const MINUTE: i64 = 60;
+const DAY: i64 = 86_400;
+/// How far ahead/behind to search before declaring a schedule unsatisfiable
+/// (e.g. `0 0 31 2 *`). Covers leap-day schedules.
+const SEARCH_DAYS: i64 = 366 * 8;
+/// Upper bound on occurrences replayed by `CatchUp::All`.
+const MAX_CATCH_UP: u32 = 100;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A standard five-field cron expression (`minute hour day-of-month month
+/// day-of-week`) with lists, ranges, steps, month/day names and the usual
+/// `@hourly`/`@daily`/`@weekly`/`@monthly`/`@yearly` shorthands.
+///
+/// Times are evaluated in UTC shifted by a fixed offset (see
+/// [`CronSchedule::with_utc_offset`]); there is no time zone database, so
+/// daylight-saving transitions are not followed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronSchedule {
+    source: String,
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    days_of_week: u8,
+    dom_restricted: bool,
+    dow_restricted: bool,
+    utc_offset: i64,
+}
+
+impl FromStr for CronSchedule {
+    type Err = ContentError;
+
+    fn from_str(expr: &str) -> Result<Self> {
+        let trimmed = expr.trim();
+        let expanded = match trimmed {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(cron_error(trimmed, "expected 5 fields"));
+        }
+
+        let minutes = parse_field(fields[0], 0, 59, &[], trimmed)?;
+        let hours = parse_field(fields[1], 0, 23, &[], trimmed)?;
+        let days_of_month = parse_field(fields[2], 1, 31, &[], trimmed)?;
+        let months = parse_field(fields[3], 1, 12, &MONTH_NAMES, trimmed)?;
+        // Accept 7 as Sunday, as most cron implementations do.
+        let mut days_of_week = parse_field(fields[4], 0, 7, &DAY_NAMES, trimmed)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & 0x7F;
+        }
+
+        Ok(Self {
+            source: trimmed.to_string(),
+            minutes,
+            hours: hours as u32,
+            days_of_month: days_of_month as u32,
+            months: months as u16,
+            days_of_week: days_of_week as u8,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+            utc_offset: 0,
+        })
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl CronSchedule {
+    /// Evaluates the expression in a fixed offset from UTC, e.g. `3600` for CET.
+    pub fn with_utc_offset(mut self, offset_seconds: i64) -> Self {
+        self.utc_offset = offset_seconds;
+        self
+    }
+
+    /// First occurrence strictly after `after`.
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let local = to_unix(after) + self.utc_offset;
+        let start = (local.div_euclid(MINUTE) + 1) * MINUTE;
+        let first_day = start.div_euclid(DAY);
+
+        for day in first_day..first_day + SEARCH_DAYS {
+            if !self.matches_day(day) {
+                continue;
+            }
+            let from = if day == first_day {
+                start.rem_euclid(DAY) / MINUTE
+            } else {
+                0
+            };
+            if let Some(minute) = (from..24 * 60).find(|&m| self.matches_minute_of_day(m)) {
+                return Some(from_unix(day * DAY + minute * MINUTE - self.utc_offset));
+            }
+        }
+        None
+    }
+
+    /// Latest occurrence at or before `at`.
+    pub fn prev_at_or_before(&self, at: SystemTime) -> Option<SystemTime> {
+        let local = to_unix(at) + self.utc_offset;
+        let start = local.div_euclid(MINUTE) * MINUTE;
+        let first_day = start.div_euclid(DAY);
+
+        for day in (first_day - SEARCH_DAYS..=first_day).rev() {
+            if !self.matches_day(day) {
+                continue;
+            }
+            let to = if day == first_day {
+                start.rem_euclid(DAY) / MINUTE
+            } else {
+                24 * 60 - 1
+            };
+            if let Some(minute) = (0..=to).rev().find(|&m| self.matches_minute_of_day(m)) {
+                return Some(from_unix(day * DAY + minute * MINUTE - self.utc_offset));
+            }
+        }
+        None
+    }
+
+    fn matches_minute_of_day(&self, minute_of_day: i64) -> bool {
+        let hour = minute_of_day / 60;
+        let minute = minute_of_day % 60;
+        self.hours & (1 << hour) != 0 && self.minutes & (1 << minute) != 0
+    }
+
+    fn matches_day(&self, days_since_epoch: i64) -> bool {
+        let (_, month, day) = civil_from_days(days_since_epoch);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+
+        // 1970-01-01 was a Thursday.
+        let weekday = (days_since_epoch + 4).rem_euclid(7);
+        let dom = self.days_of_month & (1 << day) != 0;
+        let dow = self.days_of_week & (1 << weekday) != 0;
+
+        // Classic cron: when both fields are restricted, either may match.
+        match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+}
+
+/// What to do with occurrences that passed while nothing was polling, e.g.
+/// because the Pi was powered off.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CatchUp {
+    /// Drop missed occurrences; only run when the latest occurrence passed
+    /// less than one poll interval ago (see [`Scheduler::with_poll_interval`]).
+    #[default]
+    Skip,
+    /// Run once if anything was missed.
+    Once,
+    /// Report every missed occurrence (capped).
+    All,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScheduledJob {
+    pub name: String,
+    pub schedule: CronSchedule,
+    /// Maximum delay added to each occurrence. The delay is stable per job
+    /// name so several frames sharing a schedule don't refresh in lockstep.
+    pub jitter: Duration,
+    pub catch_up: CatchUp,
+}
+
+impl ScheduledJob {
+    pub fn new(name: &str, schedule: CronSchedule) -> Self {
+        Self {
+            name: name.to_string(),
+            schedule,
+            jitter: Duration::ZERO,
+            catch_up: CatchUp::default(),
+        }
+    }
+
+    fn jitter_offset(&self) -> Duration {
+        let max = self.jitter.as_secs();
+        if max == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs(fnv1a(self.name.as_bytes()) % (max + 1))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DueJob {
+    pub name: String,
+    /// Number of occurrences this run stands in for (1 unless `CatchUp::All`).
+    pub occurrences: u32,
+}
+
+/// Tracks cron jobs and their last run, deciding which are due on each poll.
+#[derive(Debug)]
+pub struct Scheduler {
+    jobs: Vec<ScheduledJob>,
+    last_run: HashMap<String, SystemTime>,
+    poll_interval: Duration,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            jobs: Vec::new(),
+            last_run: HashMap::new(),
+            poll_interval: Duration::from_secs(MINUTE as u64),
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How often the caller polls (a minute by default, never less). An
+    /// occurrence counts as on time for `CatchUp::Skip` while it is less than
+    /// one interval old, so every occurrence is seen by exactly one poll.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval.max(Duration::from_secs(MINUTE as u64));
+        self
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    pub fn add(&mut self, job: ScheduledJob) {
+        self.jobs.retain(|existing| existing.name != job.name);
+        self.jobs.push(job);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.jobs.retain(|job| job.name != name);
+        self.last_run.remove(name);
+    }
+
+    pub fn jobs(&self) -> &[ScheduledJob] {
+        &self.jobs
+    }
+
+    pub fn last_run(&self, name: &str) -> Option<SystemTime> {
+        self.last_run.get(name).copied()
+    }
+
+    pub fn mark_run(&mut self, name: &str, at: SystemTime) {
+        self.last_run.insert(name.to_string(), at);
+    }
+
+    /// Next time `name` will be due, jitter included. Jobs that have never run
+    /// are anchored at `now`.
+    pub fn next_run(&self, name: &str, now: SystemTime) -> Option<SystemTime> {
+        let job = self.jobs.iter().find(|job| job.name == name)?;
+        let anchor = self.last_run(name).unwrap_or(now);
+        let next = job.schedule.next_after(anchor)?;
+        Some(next + job.jitter_offset())
+    }
+
+    /// Total occurrences of every job in `(from, to]`, jitter ignored; each
+    /// job is capped at one per minute.
+    pub fn occurrences_between(&self, from: SystemTime, to: SystemTime) -> u32 {
+        let mut total = 0;
+        for job in &self.jobs {
+            let mut cursor = from;
+            while let Some(next) = job.schedule.next_after(cursor) {
+                if next > to {
+                    break;
+                }
+                total += 1;
+                cursor = next;
+            }
+        }
+        total
+    }
+
+    /// Returns the jobs due at `now` and records them as run. Jobs without a
+    /// recorded last run only start counting from this poll.
+    pub fn poll(&mut self, now: SystemTime) -> Vec<DueJob> {
+        let mut due = Vec::new();
+        for job in &self.jobs {
+            let Some(anchor) = self.last_run.get(&job.name).copied() else {
+                continue;
+            };
+            let missed = count_occurrences(job, anchor, now);
+            if missed == 0 {
+                continue;
+            }
+
+            let occurrences = match job.catch_up {
+                CatchUp::All => missed,
+                CatchUp::Once => 1,
+                CatchUp::Skip if is_current(job, now, self.poll_interval) => 1,
+                CatchUp::Skip => 0,
+            };
+            if occurrences > 0 {
+                due.push(DueJob {
+                    name: job.name.clone(),
+                    occurrences,
+                });
+            }
+        }
+
+        for job in &self.jobs {
+            let entry = self.last_run.entry(job.name.clone()).or_insert(now);
+            if count_occurrences(job, *entry, now) > 0 {
+                *entry = now;
+            }
+        }
+        due
+    }
+
+    /// Restores last-run times saved under `scheduler.<job>.last_run`.
+    pub fn load_state(&mut self, store: &StateStore) {
+        for job in &self.jobs {
+            if let Some(secs) = store.get_i64(&last_run_key(&job.name)) {
+                self.last_run.insert(job.name.clone(), from_unix(secs));
+            }
+        }
+    }
+
+    /// Saves last-run times under `scheduler.<job>.last_run`; fails for job
+    /// names that cannot be part of a state key.
+    pub fn save_state(&self, store: &mut StateStore) -> Result<()> {
+        for (name, at) in &self.last_run {
+            store.set_i64(&last_run_key(name), to_unix(*at))?;
+        }
+        Ok(())
+    }
+}
+
+fn count_occurrences(job: &ScheduledJob, after: SystemTime, now: SystemTime) -> u32 {
+    let jitter = job.jitter_offset();
+    let mut count = 0;
+    let mut cursor = after.checked_sub(jitter).unwrap_or(after);
+    while count < MAX_CATCH_UP {
+        match job.schedule.next_after(cursor) {
+            Some(next) if next + jitter <= now => {
+                count += 1;
+                cursor = next;
+            }
+            _ => break,
+        }
+    }
+    count
+}
+
+/// Whether the latest occurrence is recent enough to count as on time rather
+/// than missed: within `grace` of its (jittered) due time.
+fn is_current(job: &ScheduledJob, now: SystemTime, grace: Duration) -> bool {
+    let jitter = job.jitter_offset();
+    let reference = now.checked_sub(jitter).unwrap_or(now);
+    match job.schedule.prev_at_or_before(reference) {
+        Some(prev) => reference
+            .duration_since(prev)
+            .is_ok_and(|late| late < grace),
+        None => false,
+    }
+}
+
+fn last_run_key(name: &str) -> String {
+    format!("scheduler.{name}.last_run")
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], expr: &str) -> Result<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .ok_or_else(|| cron_error(expr, &format!("invalid step `{step}`")))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((lo, hi)) = range.split_once('-') {
+            (
+                parse_value(lo, min, names, expr)?,
+                parse_value(hi, min, names, expr)?,
+            )
+        } else {
+            let value = parse_value(range, min, names, expr)?;
+            // `5/15` means "from 5 every 15", like `5-max/15`.
+            (value, if part.contains('/') { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(cron_error(
+                expr,
+                &format!("`{part}` out of range {min}-{max}"),
+            ));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(value: &str, min: u32, names: &[&str], expr: &str) -> Result<u32> {
+    if let Ok(number) = value.parse() {
+        return Ok(number);
+    }
+    names
+        .iter()
+        .position(|name| value.eq_ignore_ascii_case(name))
+        .map(|index| index as u32 + min)
+        .ok_or_else(|| cron_error(expr, &format!("invalid value `{value}`")))
+}
+
+fn cron_error(expr: &str, reason: &str) -> ContentError {
+    ContentError::InvalidSchedule(format!("`{expr}`: {reason}"))
+}
+
+fn to_unix(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+fn from_unix(secs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day) civil date.
+/// Howard Hinnant's algorithm.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-31 00:00 UTC.
+    const JAN_31: i64 = 1_706_659_200;
+    /// 2024-09-01 00:00 UTC, a Sunday.
+    const SEP_1: i64 = 1_725_148_800;
+
+    fn cron(expr: &str) -> CronSchedule {
+        expr.parse().unwrap()
+    }
+
+    fn at(secs: i64) -> SystemTime {
+        from_unix(secs)
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * 0 * *",
+            "* * * foo *",
+            "@often",
+        ] {
+            let err = expr.parse::<CronSchedule>().unwrap_err();
+            assert!(
+                matches!(err, ContentError::InvalidSchedule(_)),
+                "{expr}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn steps_through_the_hour() {
+        let schedule = cron("*/15 * * * *");
+        let mut cursor = at(JAN_31 + 7 * MINUTE);
+        let mut minutes = Vec::new();
+        for _ in 0..5 {
+            cursor = schedule.next_after(cursor).unwrap();
+            minutes.push((to_unix(cursor) - JAN_31) / MINUTE);
+        }
+        assert_eq!(minutes, [15, 30, 45, 60, 75]);
+    }
+
+    #[test]
+    fn either_day_field_matches_when_both_are_restricted() {
+        // The 10th, or any Friday: Fri 6th, Tue 10th, Fri 13th.
+        let schedule = cron("0 0 10 * fri");
+        let mut cursor = at(SEP_1);
+        let mut days = Vec::new();
+        for _ in 0..3 {
+            cursor = schedule.next_after(cursor).unwrap();
+            days.push((to_unix(cursor) - SEP_1) / DAY + 1);
+        }
+        assert_eq!(days, [6, 10, 13]);
+    }
+
+    #[test]
+    fn crosses_month_ends() {
+        let schedule = cron("@monthly");
+        assert_eq!(
+            schedule.next_after(at(JAN_31 + 23 * 3600 + 50 * MINUTE)),
+            Some(at(JAN_31 + DAY))
+        );
+
+        // February has no 31st, so the previous run was in January.
+        let schedule = cron("0 12 31 * *");
+        let march_1 = JAN_31 + 30 * DAY;
+        assert_eq!(
+            schedule.prev_at_or_before(at(march_1)),
+            Some(at(JAN_31 + 12 * 3600))
+        );
+        assert_eq!(
+            schedule.next_after(at(JAN_31 + 13 * 3600)),
+            Some(at(march_1 + 30 * DAY + 12 * 3600))
+        );
+    }
+
+    #[test]
+    fn catches_up_on_missed_runs() {
+        // Last ran at 10:00; polled again at 11:07, after 10:15 to 11:00.
+        let ten = JAN_31 + 10 * 3600;
+        let now = at(ten + 67 * MINUTE);
+        let due = |catch_up| {
+            let mut scheduler = Scheduler::new();
+            let mut job = ScheduledJob::new("frame", cron("*/15 * * * *"));
+            job.catch_up = catch_up;
+            scheduler.add(job);
+            scheduler.mark_run("frame", at(ten));
+            let due = scheduler.poll(now);
+            assert_eq!(scheduler.last_run("frame"), Some(now));
+            due.iter().map(|job| job.occurrences).sum::<u32>()
+        };
+        assert_eq!(due(CatchUp::All), 4);
+        assert_eq!(due(CatchUp::Once), 1);
+        assert_eq!(due(CatchUp::Skip), 0);
+    }
+
+    #[test]
+    fn skip_follows_the_poll_interval() {
+        // Polling every five minutes: 10:15 is seen on time by the 10:17 poll.
+        let mut scheduler = Scheduler::new().with_poll_interval(Duration::from_secs(300));
+        scheduler.add(ScheduledJob::new("frame", cron("*/15 * * * *")));
+        let ten = JAN_31 + 10 * 3600;
+        scheduler.mark_run("frame", at(ten + 12 * MINUTE));
+        assert_eq!(scheduler.poll(at(ten + 17 * MINUTE)).len(), 1);
+        // With the default one-minute interval the same poll is too late.
+        let mut scheduler = Scheduler::new();
+        scheduler.add(ScheduledJob::new("frame", cron("*/15 * * * *")));
+        scheduler.mark_run("frame", at(ten + 12 * MINUTE));
+        assert!(scheduler.poll(at(ten + 17 * MINUTE)).is_empty());
+        // Shorter intervals are not honoured.
+        assert_eq!(
+            Scheduler::new()
+                .with_poll_interval(Duration::ZERO)
+                .poll_interval(),
+            Duration::from_secs(60)
+        );
+    }
+}
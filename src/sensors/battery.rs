@@ -0,0 +1,203 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::LinuxI2CDevice;
+use image::{Rgb, RgbImage};
+
+use crate::draw;
+
+const MAX17040_ADDRESS: u16 = 0x36;
+const MAX17040_VCELL: u8 = 0x02;
+const MAX17040_SOC: u8 = 0x04;
+const MAX17040_VERSION: u8 = 0x08;
+
+/// The default address, and the two that Waveshare UPS HATs use.
+const INA219_ADDRESSES: [u16; 3] = [0x40, 0x42, 0x43];
+const INA219_BUS_VOLTAGE: u8 = 0x02;
+
+/// Resting LiPo cell voltage to charge percentage, for gauges that only
+/// report voltage.
+const LIPO_CURVE: [(f32, f32); 9] = [
+    (3.00, 0.0),
+    (3.50, 10.0),
+    (3.60, 20.0),
+    (3.70, 40.0),
+    (3.80, 60.0),
+    (3.90, 75.0),
+    (4.00, 85.0),
+    (4.10, 95.0),
+    (4.20, 100.0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatteryChip {
+    /// MAX17040/MAX17048 fuel gauge (reports state of charge directly).
+    Max17040,
+    /// INA219 power monitor (charge estimated from voltage).
+    Ina219,
+}
+
+impl fmt::Display for BatteryChip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatteryChip::Max17040 => f.write_str("MAX17040"),
+            BatteryChip::Ina219 => f.write_str("INA219"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BatteryStatus {
+    pub chip: BatteryChip,
+    pub bus: PathBuf,
+    pub voltage: f32,
+    pub percent: f32,
+}
+
+impl fmt::Display for BatteryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0}% ({:.2} V, {} via {})",
+            self.percent,
+            self.voltage,
+            self.chip,
+            self.bus.display()
+        )
+    }
+}
+
+/// Looks for a supported battery gauge on `bus`. Other HATs may answer on
+/// the same addresses, so a MAX17040 has to identify itself first, and
+/// readings outside a plausible range are treated as "not present".
+pub fn read_battery<P: AsRef<Path>>(bus: P) -> Option<BatteryStatus> {
+    let bus = bus.as_ref();
+    read_max17040(bus).or_else(|| read_ina219(bus))
+}
+
+fn read_max17040(bus: &Path) -> Option<BatteryStatus> {
+    let mut device = LinuxI2CDevice::new(bus, MAX17040_ADDRESS).ok()?;
+    // 0x0002/0x0003 for the MAX17040/1, 0x001X for the MAX17048/9.
+    let version = read_register(&mut device, MAX17040_VERSION)?;
+    if !matches!(version, 0x0002 | 0x0003 | 0x0010..=0x001F) {
+        return None;
+    }
+    let vcell = read_register(&mut device, MAX17040_VCELL)?;
+    let soc = read_register(&mut device, MAX17040_SOC)?;
+
+    // VCELL is 12 bits of 1.25 mV; SOC is percent in the high byte plus 1/256ths.
+    let voltage = (vcell >> 4) as f32 * 1.25e-3;
+    let percent = (soc >> 8) as f32 + (soc & 0xFF) as f32 / 256.0;
+    if !(2.5..=4.5).contains(&voltage) || percent > 110.0 {
+        return None;
+    }
+
+    Some(BatteryStatus {
+        chip: BatteryChip::Max17040,
+        bus: bus.to_path_buf(),
+        voltage,
+        percent: percent.min(100.0),
+    })
+}
+
+fn read_ina219(bus: &Path) -> Option<BatteryStatus> {
+    INA219_ADDRESSES
+        .iter()
+        .find_map(|&address| read_ina219_at(bus, address))
+}
+
+fn read_ina219_at(bus: &Path, address: u16) -> Option<BatteryStatus> {
+    let mut device = LinuxI2CDevice::new(bus, address).ok()?;
+    // The INA219 has no ID register, and UPS daemons reconfigure it, so
+    // only the reading itself can tell it apart.
+    let raw = read_register(&mut device, INA219_BUS_VOLTAGE)?;
+
+    // Bus voltage is bits 15..3 in 4 mV steps.
+    let voltage = (raw >> 3) as f32 * 4e-3;
+    if !(2.5..=4.5).contains(&voltage) {
+        return None;
+    }
+
+    Some(BatteryStatus {
+        chip: BatteryChip::Ina219,
+        bus: bus.to_path_buf(),
+        voltage,
+        percent: lipo_percent(voltage),
+    })
+}
+
+fn read_register(device: &mut LinuxI2CDevice, register: u8) -> Option<u16> {
+    device.write(&[register]).ok()?;
+    let mut buf = [0u8; 2];
+    device.read(&mut buf).ok()?;
+    Some(u16::from_be_bytes(buf))
+}
+
+fn lipo_percent(voltage: f32) -> f32 {
+    let (first_v, first_p) = LIPO_CURVE[0];
+    if voltage <= first_v {
+        return first_p;
+    }
+    for pair in LIPO_CURVE.windows(2) {
+        let (v0, p0) = pair[0];
+        let (v1, p1) = pair[1];
+        if voltage <= v1 {
+            return p0 + (p1 - p0) * (voltage - v0) / (v1 - v0);
+        }
+    }
+    100.0
+}
+
+/// Draws a small battery icon in the top-right corner, filled to `percent`
+/// and coloured green, yellow or red by charge level.
+pub fn draw_battery_glyph(image: &mut RgbImage, percent: f32) {
+    let img_w = image.width() as i64;
+    let width = (img_w / 20).max(24);
+    let height = width / 2;
+    let margin = (width / 4).max(4);
+    let border = (width / 16).max(2);
+    let nub = border * 2;
+
+    let x = img_w - margin - width - nub;
+    let y = margin;
+
+    let level = percent.clamp(0.0, 100.0);
+    let fill_colour = if level < 20.0 {
+        Rgb([200, 0, 0])
+    } else if level < 50.0 {
+        Rgb([230, 200, 0])
+    } else {
+        Rgb([0, 128, 0])
+    };
+
+    // White backing so the glyph stays legible over busy photos.
+    draw::fill_rect(
+        image,
+        x - border,
+        y - border,
+        width + nub + border * 2,
+        height + border * 2,
+        Rgb([255, 255, 255]),
+    );
+    draw::stroke_rect(image, x, y, width, height, border, Rgb([0, 0, 0]));
+    draw::fill_rect(
+        image,
+        x + width,
+        y + height / 4,
+        nub,
+        height / 2,
+        Rgb([0, 0, 0]),
+    );
+
+    let inner_w = width - border * 4;
+    let fill_w = (inner_w as f32 * level / 100.0).round() as i64;
+    draw::fill_rect(
+        image,
+        x + border * 2,
+        y + border * 2,
+        fill_w.max(1),
+        height - border * 4,
+        fill_colour,
+    );
+}
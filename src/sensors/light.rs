@@ -0,0 +1,213 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::LinuxI2CDevice;
+
+const BH1750_ADDRESSES: [u16; 2] = [0x23, 0x5C];
+const BH1750_POWER_ON: u8 = 0x01;
+const BH1750_ONE_TIME_HIGH_RES: u8 = 0x20;
+
+const TSL2561_ADDRESSES: [u16; 3] = [0x39, 0x29, 0x49];
+const TSL2561_COMMAND: u8 = 0x80;
+const TSL2561_WORD: u8 = 0x20;
+const TSL2561_CONTROL: u8 = 0x00;
+const TSL2561_TIMING: u8 = 0x01;
+const TSL2561_ID: u8 = 0x0A;
+const TSL2561_DATA0: u8 = 0x0C;
+const TSL2561_DATA1: u8 = 0x0E;
+const TSL2561_POWER_ON: u8 = 0x03;
+const TSL2561_POWER_OFF: u8 = 0x00;
+/// 402 ms integration at 1x gain.
+const TSL2561_TIMING_402MS_1X: u8 = 0x02;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightChip {
+    Bh1750,
+    Tsl2561,
+}
+
+impl fmt::Display for LightChip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightChip::Bh1750 => f.write_str("BH1750"),
+            LightChip::Tsl2561 => f.write_str("TSL2561"),
+        }
+    }
+}
+
+/// Location of a detected light sensor, so it can be read again later
+/// without re-scanning.
+#[derive(Clone, Debug)]
+pub struct LightSensor {
+    pub chip: LightChip,
+    pub bus: PathBuf,
+    pub address: u16,
+}
+
+impl LightSensor {
+    /// Takes a fresh reading in lux. This blocks for the sensor's
+    /// integration time (about 180 ms for the BH1750, 400 ms for the TSL2561).
+    pub fn read_lux(&self) -> Option<f32> {
+        let mut device = LinuxI2CDevice::new(&self.bus, self.address).ok()?;
+        match self.chip {
+            LightChip::Bh1750 => read_bh1750(&mut device),
+            LightChip::Tsl2561 => read_tsl2561(&mut device),
+        }
+    }
+}
+
+impl fmt::Display for LightSensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at 0x{:02x} via {}",
+            self.chip,
+            self.address,
+            self.bus.display()
+        )
+    }
+}
+
+/// Looks for a supported ambient light sensor on `bus`. Anything that
+/// answers at a sensor address has to behave like that sensor before it is
+/// trusted: a BH1750 must complete a measurement, a TSL2561 must report its
+/// part number and power up.
+pub fn find_light_sensor<P: AsRef<Path>>(bus: P) -> Option<LightSensor> {
+    let bus = bus.as_ref();
+    let bh1750 = BH1750_ADDRESSES.iter().find_map(|&address| {
+        let mut device = LinuxI2CDevice::new(bus, address).ok()?;
+        read_bh1750(&mut device)?;
+        Some(LightSensor {
+            chip: LightChip::Bh1750,
+            bus: bus.to_path_buf(),
+            address,
+        })
+    });
+    bh1750.or_else(|| {
+        TSL2561_ADDRESSES.iter().find_map(|&address| {
+            let mut device = LinuxI2CDevice::new(bus, address).ok()?;
+            if !is_tsl2561(&mut device) {
+                return None;
+            }
+            Some(LightSensor {
+                chip: LightChip::Tsl2561,
+                bus: bus.to_path_buf(),
+                address,
+            })
+        })
+    })
+}
+
+fn is_tsl2561(device: &mut LinuxI2CDevice) -> bool {
+    // The part number lives in the high nibble of the ID register: 0x0 or
+    // 0x4 for the TSL2560, 0x1 or 0x5 for the TSL2561 (CS and T/FN/CL
+    // packages).
+    let Ok(id) = device.smbus_read_byte_data(TSL2561_COMMAND | TSL2561_ID) else {
+        return false;
+    };
+    if !matches!(id >> 4, 0x0 | 0x1 | 0x4 | 0x5) {
+        return false;
+    }
+    // A powered-up TSL2561 reads its control register back as 0x03.
+    let powered = device
+        .smbus_write_byte_data(TSL2561_COMMAND | TSL2561_CONTROL, TSL2561_POWER_ON)
+        .is_ok()
+        && device
+            .smbus_read_byte_data(TSL2561_COMMAND | TSL2561_CONTROL)
+            .is_ok_and(|control| control & 0x03 == TSL2561_POWER_ON);
+    let _ = device.smbus_write_byte_data(TSL2561_COMMAND | TSL2561_CONTROL, TSL2561_POWER_OFF);
+    powered
+}
+
+fn read_bh1750(device: &mut LinuxI2CDevice) -> Option<f32> {
+    device.write(&[BH1750_POWER_ON]).ok()?;
+    device.write(&[BH1750_ONE_TIME_HIGH_RES]).ok()?;
+    thread::sleep(Duration::from_millis(180));
+    let mut buf = [0u8; 2];
+    device.read(&mut buf).ok()?;
+    // One count is 1/1.2 lux in high-resolution mode.
+    Some(u16::from_be_bytes(buf) as f32 / 1.2)
+}
+
+fn read_tsl2561(device: &mut LinuxI2CDevice) -> Option<f32> {
+    device
+        .smbus_write_byte_data(TSL2561_COMMAND | TSL2561_CONTROL, TSL2561_POWER_ON)
+        .ok()?;
+    device
+        .smbus_write_byte_data(TSL2561_COMMAND | TSL2561_TIMING, TSL2561_TIMING_402MS_1X)
+        .ok()?;
+    thread::sleep(Duration::from_millis(410));
+    let ch0 = device
+        .smbus_read_word_data(TSL2561_COMMAND | TSL2561_WORD | TSL2561_DATA0)
+        .ok()?;
+    let ch1 = device
+        .smbus_read_word_data(TSL2561_COMMAND | TSL2561_WORD | TSL2561_DATA1)
+        .ok()?;
+    let _ = device.smbus_write_byte_data(TSL2561_COMMAND | TSL2561_CONTROL, TSL2561_POWER_OFF);
+
+    // The datasheet coefficients assume 16x gain.
+    Some(tsl2561_lux(ch0 as f32 * 16.0, ch1 as f32 * 16.0))
+}
+
+/// Lux from the broadband (`ch0`) and infrared (`ch1`) channels, using the
+/// piecewise approximation from the TSL2561 datasheet (T/FN/CL package).
+fn tsl2561_lux(ch0: f32, ch1: f32) -> f32 {
+    if ch0 <= 0.0 {
+        return 0.0;
+    }
+    let ratio = ch1 / ch0;
+    let lux = if ratio <= 0.50 {
+        0.0304 * ch0 - 0.062 * ch0 * ratio.powf(1.4)
+    } else if ratio <= 0.61 {
+        0.0224 * ch0 - 0.031 * ch1
+    } else if ratio <= 0.80 {
+        0.0128 * ch0 - 0.0153 * ch1
+    } else if ratio <= 1.30 {
+        0.00146 * ch0 - 0.00112 * ch1
+    } else {
+        0.0
+    };
+    lux.max(0.0)
+}
+
+/// How frames react to ambient light: below `dark_lux` refreshes are held
+/// back entirely, and between that and `bright_lux` the image is lightened
+/// progressively more (up to `max_lighten`) as the room gets dimmer.
+#[derive(Clone, Copy, Debug)]
+pub struct AmbientPolicy {
+    pub dark_lux: f32,
+    pub bright_lux: f32,
+    pub max_lighten: f32,
+}
+
+impl Default for AmbientPolicy {
+    fn default() -> Self {
+        Self {
+            dark_lux: 5.0,
+            bright_lux: 200.0,
+            max_lighten: 0.3,
+        }
+    }
+}
+
+impl AmbientPolicy {
+    pub fn is_dark(&self, lux: f32) -> bool {
+        lux < self.dark_lux
+    }
+
+    /// The lighten amount to use at `lux`, never less than `base`.
+    pub fn lighten_for(&self, lux: f32, base: f32) -> f32 {
+        if lux >= self.bright_lux || self.bright_lux <= self.dark_lux {
+            return base;
+        }
+        // Interpolate on a log scale, since perceived brightness is roughly
+        // logarithmic in lux.
+        let low = self.dark_lux.max(1.0).ln();
+        let high = self.bright_lux.max(1.0).ln();
+        let position = ((lux.max(1.0).ln() - low) / (high - low)).clamp(0.0, 1.0);
+        (base + self.max_lighten * (1.0 - position)).clamp(0.0, 1.0)
+    }
+}
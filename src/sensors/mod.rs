@@ -0,0 +1,5 @@
+pub mod battery;
+pub mod light;
+
+pub use battery::{BatteryChip, BatteryStatus, draw_battery_glyph, read_battery};
+pub use light::{AmbientPolicy, LightChip, LightSensor, find_light_sensor};
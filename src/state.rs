@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::content::{ContentError, Result};
+use crate::document;
+
+pub const STATE_SCHEMA_VERSION: i64 = 1;
+
+const STATE_FILE_NAME: &str = "state.toml";
+const VERSION_KEY: &str = "schema_version";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl fmt::Display for StateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&document::format_value(self))
+    }
+}
+
+/// Persistent key/value state shared by the CLI and library subsystems.
+///
+/// Stored as a flat TOML document of dotted keys (`refresh.count = 12`) with a
+/// `schema_version` header. Writes go to a temporary file that is renamed over
+/// the original, so a crash mid-save never leaves a truncated document.
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+    values: BTreeMap<String, StateValue>,
+}
+
+impl StateStore {
+    /// Opens the store at `path`, starting empty if the file does not exist.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let values = match fs::read_to_string(&path) {
+            Ok(contents) => parse_document(&contents)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut store = Self { path, values };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    /// Opens the store file inside `dir`, e.g. a `--state-dir` override.
+    pub fn open_in(dir: &Path) -> Result<Self> {
+        Self::open(dir.join(STATE_FILE_NAME))
+    }
+
+    pub fn open_default() -> Result<Self> {
+        Self::open_in(&default_state_dir()?)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, key: &str) -> Option<&StateValue> {
+        self.values.get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.values.get(key) {
+            Some(StateValue::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        match self.values.get(key) {
+            Some(StateValue::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.values.get(key) {
+            Some(StateValue::Float(value)) => Some(*value),
+            Some(StateValue::Integer(value)) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.values.get(key) {
+            Some(StateValue::Boolean(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Sets `key` to `value`. Anything the state file could not load back
+    /// is rejected, since one bad entry would make the whole file
+    /// unloadable: keys must be non-empty ASCII letters, digits, `_`, `-`
+    /// and `.`, and floats must be finite.
+    pub fn set(&mut self, key: &str, value: StateValue) -> Result<()> {
+        if !document::is_valid_key(key) {
+            return Err(ContentError::State(format!("invalid state key `{key}`")));
+        }
+        if let StateValue::Float(float) = value
+            && !float.is_finite()
+        {
+            return Err(ContentError::State(format!(
+                "`{key}` cannot be set to {float}"
+            )));
+        }
+        self.values.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    pub fn set_str(&mut self, key: &str, value: &str) -> Result<()> {
+        self.set(key, StateValue::String(value.to_string()))
+    }
+
+    pub fn set_i64(&mut self, key: &str, value: i64) -> Result<()> {
+        self.set(key, StateValue::Integer(value))
+    }
+
+    pub fn set_f64(&mut self, key: &str, value: f64) -> Result<()> {
+        self.set(key, StateValue::Float(value))
+    }
+
+    pub fn set_bool(&mut self, key: &str, value: bool) -> Result<()> {
+        self.set(key, StateValue::Boolean(value))
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<StateValue> {
+        self.values.remove(key)
+    }
+
+    /// Keys under `prefix.`, e.g. every `history.*` entry.
+    pub fn keys_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+        self.values
+            .keys()
+            .filter(move |key| {
+                key.strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('.'))
+            })
+            .map(String::as_str)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut contents = String::new();
+        for (key, value) in &self.values {
+            contents.push_str(&format!("{key} = {value}\n"));
+        }
+
+        let tmp_path = self.path.with_extension("toml.tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    fn migrate(&mut self) -> Result<()> {
+        let version = match self.values.get(VERSION_KEY) {
+            Some(StateValue::Integer(version)) => *version,
+            Some(_) => {
+                return Err(ContentError::State(format!(
+                    "{VERSION_KEY} must be an integer"
+                )));
+            }
+            None => 0,
+        };
+
+        if version > STATE_SCHEMA_VERSION {
+            return Err(ContentError::State(format!(
+                "state file {} has schema version {version}, newer than supported {STATE_SCHEMA_VERSION}",
+                self.path.display()
+            )));
+        }
+
+        // Version 0 is an empty or pre-versioned document; nothing to rewrite yet.
+        self.set_i64(VERSION_KEY, STATE_SCHEMA_VERSION)
+    }
+}
+
+/// `$XDG_STATE_HOME/paperwave`, falling back to `~/.local/state/paperwave`.
+pub fn default_state_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir).join("paperwave"));
+    }
+    match env::var_os("HOME").filter(|d| !d.is_empty()) {
+        Some(home) => Ok(PathBuf::from(home).join(".local/state/paperwave")),
+        None => Err(ContentError::State(
+            "neither XDG_STATE_HOME nor HOME is set".to_string(),
+        )),
+    }
+}
+
+fn parse_document(contents: &str) -> Result<BTreeMap<String, StateValue>> {
+    let sections = document::parse(contents).map_err(ContentError::State)?;
+    let mut values = BTreeMap::new();
+    for section in sections {
+        if section.name.is_some() {
+            return Err(ContentError::State(format!(
+                "line {}: tables are not supported in the state file",
+                section.line
+            )));
+        }
+        for entry in section.entries {
+            values.insert(entry.key, entry.value);
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> StateStore {
+        let dir = env::temp_dir().join(format!("paperwave-state-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        StateStore::open_in(&dir).unwrap()
+    }
+
+    #[test]
+    fn strings_round_trip_through_the_file() {
+        let mut store = temp_store("strings");
+        let values = [
+            "",
+            "plain",
+            "say \"cheese\"",
+            "C:\\photos\\",
+            "two\nlines\tand\r\n",
+            "# not a comment",
+            "ünïcødé ✓",
+            "\\n is not a newline",
+        ];
+        for (index, value) in values.iter().enumerate() {
+            store.set_str(&format!("text.v{index}"), value).unwrap();
+        }
+        store.save().unwrap();
+
+        let loaded = StateStore::open(store.path()).unwrap();
+        for (index, value) in values.iter().enumerate() {
+            assert_eq!(loaded.get_str(&format!("text.v{index}")), Some(*value));
+        }
+        let _ = fs::remove_dir_all(store.path().parent().unwrap());
+    }
+
+    #[test]
+    fn rejects_what_the_file_cannot_hold() {
+        let mut store = temp_store("reject");
+        for key in [
+            "",
+            "with space",
+            "tab\tkey",
+            "quote\"",
+            "scheduler.my job.last_run",
+        ] {
+            assert!(store.set_i64(key, 1).is_err(), "{key:?}");
+        }
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(store.set_f64("ratio", value).is_err());
+        }
+        assert!(store.get("ratio").is_none());
+        store.set_f64("ratio", 0.5).unwrap();
+        assert_eq!(store.get_f64("ratio"), Some(0.5));
+    }
+}
@@ -0,0 +1,142 @@
+//! Palette-aware tone mapping, run as a first pass before dithering.
+//!
+//! A panel palette only has a handful of brightness levels, and a typical
+//! photo leaves some of them nearly unused: a hazy landscape never reaches
+//! black, a night scene never reaches white. The pass measures how many
+//! pixels would land on each level and builds a tone curve that widens the
+//! input range of the starved levels, so the dithered frame spans more of
+//! what the panel can show.
+
+use image::RgbImage;
+
+/// Share of each level's target usage taken from a uniform spread rather
+/// than the image's own distribution; 0 leaves the image alone, 1 fully
+/// equalises it across the levels.
+const STRENGTH: f32 = 0.5;
+/// Palette entries closer than this in luma count as one level.
+const LEVEL_TOLERANCE: f32 = 8.0;
+
+/// A luma-to-luma curve, applied by shifting all three channels so hue and
+/// chroma are left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToneCurve {
+    offsets: [f32; 256],
+}
+
+impl ToneCurve {
+    /// Builds the curve for `rgb` against `palette`. Images with fewer
+    /// than two palette levels to spread over get an identity curve.
+    pub fn for_palette(rgb: &RgbImage, palette: &[[f32; 3]]) -> Self {
+        let mut levels: Vec<f32> = palette.iter().map(|&colour| luma(colour)).collect();
+        levels.sort_by(f32::total_cmp);
+        levels.dedup_by(|a, b| *a - *b < LEVEL_TOLERANCE);
+
+        let total = rgb.width() as usize * rgb.height() as usize;
+        if levels.len() < 2 || total == 0 {
+            return Self::identity();
+        }
+
+        let mut histogram = [0usize; 256];
+        for pixel in rgb.pixels() {
+            let value = luma([pixel[0] as f32, pixel[1] as f32, pixel[2] as f32]);
+            histogram[value.round().clamp(0.0, 255.0) as usize] += 1;
+        }
+        let mut cdf = [0.0f32; 256];
+        let mut running = 0usize;
+        for (value, count) in histogram.iter().enumerate() {
+            running += count;
+            cdf[value] = running as f32 / total as f32;
+        }
+
+        // Each pair of neighbouring levels meets at their midpoint; the
+        // fraction of pixels below each midpoint is that level's usage.
+        let boundaries: Vec<f32> = levels
+            .windows(2)
+            .map(|pair| (pair[0] + pair[1]) / 2.0)
+            .collect();
+        let uniform = 1.0 / levels.len() as f32;
+        let mut knots = vec![(0.0f32, 0.0f32)];
+        for (index, &boundary) in boundaries.iter().enumerate() {
+            let used = cdf_at(&cdf, boundary);
+            let target = used * (1.0 - STRENGTH) + uniform * (index + 1) as f32 * STRENGTH;
+            // The input luma below which `target` of the pixels fall now
+            // maps to the boundary, so that share lands on the lower levels.
+            let input = quantile(&cdf, target).max(knots.last().unwrap().0 + 1.0);
+            if input < 255.0 {
+                knots.push((input, boundary));
+            }
+        }
+        knots.push((255.0, 255.0));
+
+        let mut offsets = [0.0f32; 256];
+        for (value, offset) in offsets.iter_mut().enumerate() {
+            let x = value as f32;
+            let segment = knots
+                .windows(2)
+                .find(|pair| x <= pair[1].0)
+                .expect("the last knot is at 255");
+            let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+            let y = y0 + (x - x0) / (x1 - x0) * (y1 - y0);
+            *offset = y - x;
+        }
+        Self { offsets }
+    }
+
+    pub fn identity() -> Self {
+        Self {
+            offsets: [0.0; 256],
+        }
+    }
+
+    /// `colour` with its luma moved along the curve.
+    pub fn apply(&self, colour: [f32; 3]) -> [f32; 3] {
+        let offset = self.offsets[luma(colour).round().clamp(0.0, 255.0) as usize];
+        colour.map(|channel| (channel + offset).clamp(0.0, 255.0))
+    }
+}
+
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+/// Fraction of pixels with luma at or below `value`, interpolated.
+fn cdf_at(cdf: &[f32; 256], value: f32) -> f32 {
+    let value = value.clamp(0.0, 255.0);
+    let low = value.floor() as usize;
+    let high = (low + 1).min(255);
+    cdf[low] + (cdf[high] - cdf[low]) * (value - low as f32)
+}
+
+/// Smallest luma with at least `fraction` of the pixels at or below it.
+fn quantile(cdf: &[f32; 256], fraction: f32) -> f32 {
+    cdf.iter()
+        .position(|&share| share >= fraction)
+        .unwrap_or(255) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREYS: [[f32; 3]; 3] = [[0.0; 3], [128.0; 3], [255.0; 3]];
+
+    #[test]
+    fn hazy_images_are_stretched_to_the_outer_levels() {
+        // Luma 96-159: nothing would land on black or white.
+        let rgb = RgbImage::from_fn(64, 8, |x, _| image::Rgb([(96 + x) as u8; 3]));
+        let curve = ToneCurve::for_palette(&rgb, &GREYS);
+
+        assert!(curve.apply([96.0; 3])[0] < 64.0);
+        assert!(curve.apply([159.0; 3])[0] > 192.0);
+        let mid = curve.apply([128.0; 3])[0];
+        assert!((96.0..160.0).contains(&mid), "{mid}");
+    }
+
+    #[test]
+    fn flat_palettes_leave_images_alone() {
+        let rgb = RgbImage::from_pixel(4, 4, image::Rgb([50, 60, 70]));
+        let curve = ToneCurve::for_palette(&rgb, &[[0.0; 3], [2.0; 3]]);
+        assert_eq!(curve, ToneCurve::identity());
+        assert_eq!(curve.apply([50.0, 60.0, 70.0]), [50.0, 60.0, 70.0]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Golden-image regression tests for the image pipeline: resize, lighten
+//! and dither each fixture in `tests/fixtures` and compare the palette
+//! indices with the goldens in `tests/golden`.
+//!
+//! After an intentional output change, regenerate the goldens with
+//!
+//! ```sh
+//! PAPERWAVE_BLESS=1 cargo test --test golden
+//! ```
+//!
+//! and review the new files (they are binary PGMs, one palette index per
+//! pixel) before committing them.
+#![cfg(target_os = "linux")]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use paperwave::displays::common::lighten_image_in_place;
+use paperwave::displays::{el133uf1, uc8159};
+use paperwave::{
+    DitherMethod, DitherOptions, ImageLimits, PaletteRemap, clamp_aspect_resize, dither_with,
+};
+
+const BLESS_VAR: &str = "PAPERWAVE_BLESS";
+
+/// Landscape output, so the portrait fixture is cropped top and bottom.
+const TARGET: (u32, u32) = (60, 45);
+const LIGHTEN: f32 = 0.2;
+
+const FIXTURES: [&str; 2] = ["hue_sweep", "scene"];
+
+const MODES: [(&str, DitherOptions); 4] = [
+    (
+        "floyd",
+        DitherOptions {
+            method: DitherMethod::FloydSteinberg,
+            seed: None,
+            remap: PaletteRemap::IDENTITY,
+            adaptive_tone: false,
+        },
+    ),
+    (
+        "floyd_seeded",
+        DitherOptions {
+            method: DitherMethod::FloydSteinberg,
+            seed: Some(7),
+            remap: PaletteRemap::IDENTITY,
+            adaptive_tone: false,
+        },
+    ),
+    (
+        "atkinson",
+        DitherOptions {
+            method: DitherMethod::Atkinson,
+            seed: None,
+            remap: PaletteRemap::IDENTITY,
+            adaptive_tone: false,
+        },
+    ),
+    (
+        "none",
+        DitherOptions {
+            method: DitherMethod::None,
+            seed: None,
+            remap: PaletteRemap::IDENTITY,
+            adaptive_tone: false,
+        },
+    ),
+];
+
+fn palettes() -> [(&'static str, Vec<[f32; 3]>); 2] {
+    [
+        ("uc8159", uc8159::build_palette(0.5).to_vec()),
+        ("el133uf1", el133uf1::blend_palette(0.5).to_vec()),
+    ]
+}
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn render(fixture: &str, palette: &[[f32; 3]], options: DitherOptions) -> Vec<u8> {
+    let path = root().join("fixtures").join(format!("{fixture}.png"));
+    let image = paperwave::load_image(&path, &ImageLimits::default()).unwrap();
+    let mut rgb = clamp_aspect_resize(&image, TARGET.0, TARGET.1);
+    assert_eq!(rgb.dimensions(), TARGET);
+    lighten_image_in_place(&mut rgb, LIGHTEN);
+    dither_with(&rgb, palette, options)
+}
+
+fn encode_pgm(indices: &[u8]) -> Vec<u8> {
+    let mut out = format!("P5\n{} {}\n255\n", TARGET.0, TARGET.1).into_bytes();
+    out.extend_from_slice(indices);
+    out
+}
+
+fn decode_pgm(path: &Path, bytes: &[u8]) -> Vec<u8> {
+    let header = format!("P5\n{} {}\n255\n", TARGET.0, TARGET.1);
+    match bytes.strip_prefix(header.as_bytes()) {
+        Some(indices) => indices.to_vec(),
+        None => panic!(
+            "{} is not a {}x{} golden",
+            path.display(),
+            TARGET.0,
+            TARGET.1
+        ),
+    }
+}
+
+/// Compares `actual` with the stored golden, or rewrites it when blessing.
+/// Returns a description of the mismatch, if any.
+fn check(name: &str, actual: &[u8]) -> Option<String> {
+    let path = root().join("golden").join(format!("{name}.pgm"));
+    if env::var_os(BLESS_VAR).is_some() {
+        fs::write(&path, encode_pgm(actual)).unwrap();
+        return None;
+    }
+
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return Some(format!(
+                "{name}: cannot read {} ({err}); run with {BLESS_VAR}=1 to create it",
+                path.display()
+            ));
+        }
+    };
+    let expected = decode_pgm(&path, &bytes);
+    if expected.len() != actual.len() {
+        return Some(format!(
+            "{name}: expected {} pixels, got {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    let differing: Vec<usize> = (0..expected.len())
+        .filter(|&i| expected[i] != actual[i])
+        .collect();
+    let first = differing.first()?;
+    Some(format!(
+        "{name}: {} of {} pixels differ, first at ({}, {}): expected {}, got {}",
+        differing.len(),
+        expected.len(),
+        first % TARGET.0 as usize,
+        first / TARGET.0 as usize,
+        expected[*first],
+        actual[*first]
+    ))
+}
+
+#[test]
+fn pipeline_matches_goldens() {
+    let mut failures = Vec::new();
+    for fixture in FIXTURES {
+        for (palette_name, palette) in palettes() {
+            for (mode, options) in MODES {
+                let indices = render(fixture, &palette, options);
+                assert!(
+                    indices
+                        .iter()
+                        .all(|&index| (index as usize) < palette.len())
+                );
+                failures.extend(check(&format!("{fixture}_{palette_name}_{mode}"), &indices));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "pipeline output changed:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn dither_is_repeatable() {
+    let (_, palette) = &palettes()[0];
+    for (_, options) in MODES {
+        assert_eq!(
+            render("scene", palette, options),
+            render("scene", palette, options)
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! Probing and display construction against a simulated `/dev`: a temporary
+//! directory of placeholder device nodes, answered by
+//! [`MockDevices`](paperwave::displays::detect::mock::MockDevices), with the
+//! drivers running on the mock HAL.
+#![cfg(target_os = "linux")]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use image::{DynamicImage, Rgb, RgbImage};
+use paperwave::displays::detect::mock::MockDevices;
+use paperwave::displays::hal::mock::{Event, Log, MockInputPin, MockOutputPin, MockSpi, NoDelay};
+use paperwave::displays::{El133Uf1Hal, Uc8159Hal, uc8159};
+use paperwave::{
+    BatteryChip, BatteryStatus, DisplaySpec, DitherOptions, EventBus, Fit, I2cProbeStatus,
+    InkyDisplay, InkyEl133Uf1, InkyEl133Uf1Config, InkyUc8159, InkyUc8159Config, PaletteRemap,
+    ProbeInfo, RenderOptions, Rotation, analyze, probe_system_at,
+};
+
+/// A directory of empty files named like device nodes, removed on drop.
+struct SimulatedDev {
+    root: PathBuf,
+}
+
+impl SimulatedDev {
+    fn new(name: &str, nodes: &[&str]) -> Self {
+        let root = env::temp_dir().join(format!("paperwave-sim-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for node in nodes {
+            fs::write(root.join(node), b"").unwrap();
+        }
+        Self { root }
+    }
+
+    fn path(&self, node: &str) -> PathBuf {
+        self.root.join(node)
+    }
+
+    fn probe(&self, devices: &MockDevices) -> ProbeInfo {
+        probe_system_at(&self.root, devices)
+    }
+}
+
+impl Drop for SimulatedDev {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+const PI_NODES: [&str; 6] = [
+    "spidev0.0",
+    "spidev0.1",
+    "gpiochip0",
+    "i2c-1",
+    "i2c-22",
+    "tty0",
+];
+
+fn dump(header: [u8; 7], write_time: &str) -> Vec<u8> {
+    let mut data = header.to_vec();
+    data.push(write_time.len() as u8);
+    data.extend_from_slice(write_time.as_bytes());
+    data.resize(29, 0);
+    data
+}
+
+fn impression_57() -> Vec<u8> {
+    dump([0x58, 0x02, 0xc0, 0x01, 5, 12, 14], "2021-06-08 14:02:51.3")
+}
+
+fn impression_133() -> Vec<u8> {
+    dump([0x40, 0x06, 0xb0, 0x04, 7, 10, 21], "2024-11-27 16:20:05.5")
+}
+
+fn spi_bytes(log: &Log) -> usize {
+    log.events()
+        .iter()
+        .map(|event| match event {
+            Event::Spi(bytes) => bytes.len(),
+            Event::Pin(..) => 0,
+        })
+        .sum()
+}
+
+#[test]
+fn lists_device_nodes_under_root() {
+    let dev = SimulatedDev::new("nodes", &PI_NODES);
+    let devices = MockDevices::new().with_gpio_label("gpiochip0", "gpiochip0 (pinctrl-bcm2711)");
+    let probe = dev.probe(&devices);
+
+    assert_eq!(probe.dev_root, dev.root);
+    assert_eq!(
+        probe.spi_devices,
+        [dev.path("spidev0.0"), dev.path("spidev0.1")]
+    );
+    assert_eq!(probe.gpio_chips, [dev.path("gpiochip0")]);
+    assert_eq!(probe.i2c_buses, [dev.path("i2c-1"), dev.path("i2c-22")]);
+    assert_eq!(
+        probe.gpio_chip_labels,
+        [format!(
+            "{} -> gpiochip0 (pinctrl-bcm2711)",
+            dev.path("gpiochip0").display()
+        )]
+    );
+    assert!(probe.find_battery(&devices).is_none());
+    assert!(probe.find_light_sensor(&devices).is_none());
+}
+
+#[test]
+fn detects_display_from_eeprom() {
+    let dev = SimulatedDev::new("detect", &PI_NODES);
+    let devices = MockDevices::new().with_eeprom("i2c-1", &impression_57());
+    let probe = dev.probe(&devices);
+
+    assert!(matches!(
+        probe.display,
+        Some(DisplaySpec::Uc8159 {
+            width: 600,
+            height: 448,
+            variant: 14
+        })
+    ));
+    assert_eq!(probe.eeprom_bus, Some(dev.path("i2c-1")));
+    assert!(probe.eeprom_error.is_none());
+    assert!(matches!(
+        probe.i2c_bus_results[1].status,
+        I2cProbeStatus::Unavailable
+    ));
+}
+
+#[test]
+fn detects_display_despite_odd_write_time() {
+    // The write time length counts its NUL padding.
+    let dev = SimulatedDev::new("padded", &PI_NODES);
+    let devices = MockDevices::new().with_eeprom(
+        "i2c-1",
+        include_bytes!("fixtures/eeprom/impression_13_3.bin"),
+    );
+    let probe = dev.probe(&devices);
+
+    assert!(matches!(
+        probe.display,
+        Some(DisplaySpec::El133Uf1 {
+            width: 1600,
+            height: 1200
+        })
+    ));
+    let warning = probe.eeprom.and_then(|info| info.warning).unwrap();
+    assert_eq!(
+        warning.to_string(),
+        "write time has non-printable byte 0x00 at offset 24"
+    );
+}
+
+#[test]
+fn skips_blank_and_failing_buses() {
+    let dev = SimulatedDev::new("skip", &["i2c-0", "i2c-1", "i2c-2"]);
+    let devices = MockDevices::new()
+        .with_eeprom("i2c-0", &[0xff; 29])
+        .with_eeprom_error("i2c-1", "Remote I/O error (os error 121)")
+        .with_eeprom("i2c-2", &impression_133());
+    let probe = dev.probe(&devices);
+
+    assert!(matches!(
+        probe.display,
+        Some(DisplaySpec::El133Uf1 {
+            width: 1600,
+            height: 1200
+        })
+    ));
+    assert_eq!(probe.eeprom_bus, Some(dev.path("i2c-2")));
+    assert!(probe.eeprom_error.is_none());
+}
+
+fn battery(bus: &Path) -> BatteryStatus {
+    BatteryStatus {
+        chip: BatteryChip::Max17040,
+        bus: bus.to_path_buf(),
+        voltage: 3.9,
+        percent: 75.0,
+    }
+}
+
+#[test]
+fn reads_battery_only_on_the_hat_bus() {
+    let dev = SimulatedDev::new("battery", &["i2c-1", "i2c-2", "i2c-22"]);
+    let devices = MockDevices::new().with_battery("i2c-22", battery(&dev.path("i2c-22")));
+    assert!(dev.probe(&devices).find_battery(&devices).is_none());
+
+    let devices = devices
+        .with_eeprom("i2c-2", &impression_57())
+        .with_battery("i2c-1", battery(&dev.path("i2c-1")))
+        .with_battery("i2c-2", battery(&dev.path("i2c-2")));
+    let probe = dev.probe(&devices);
+    assert_eq!(
+        probe.find_battery(&devices).map(|status| status.bus),
+        Some(dev.path("i2c-2"))
+    );
+
+    let devices = MockDevices::new().with_battery("i2c-1", battery(&dev.path("i2c-1")));
+    let probe = dev.probe(&devices);
+    assert_eq!(
+        probe.find_battery(&devices).map(|status| status.bus),
+        Some(dev.path("i2c-1"))
+    );
+}
+
+#[test]
+fn falls_back_to_default_display() {
+    let dev = SimulatedDev::new("fallback", &PI_NODES);
+    let devices = MockDevices::new().with_eeprom("i2c-1", &impression_57()[..12]);
+    let probe = dev.probe(&devices);
+
+    assert!(probe.display.is_none());
+    let error = probe.eeprom_error.as_deref().unwrap();
+    assert!(error.starts_with("invalid data: truncated"), "{error}");
+    assert!(matches!(
+        probe.display_or_default(),
+        DisplaySpec::Uc8159 {
+            width: 600,
+            height: 448,
+            ..
+        }
+    ));
+
+    let empty = SimulatedDev::new("empty", &[]);
+    let probe = empty.probe(&MockDevices::new());
+    assert!(probe.spi_devices.is_empty() && probe.i2c_buses.is_empty());
+    assert!(probe.eeprom.is_none() && probe.eeprom_error.is_none());
+}
+
+#[test]
+fn constructs_and_shows_uc8159() {
+    let dev = SimulatedDev::new("uc8159", &PI_NODES);
+    let devices = MockDevices::new().with_eeprom("i2c-1", &impression_57());
+    let probe = dev.probe(&devices);
+    let DisplaySpec::Uc8159 { width, height, .. } = probe.display_or_default() else {
+        panic!("expected a UC8159");
+    };
+
+    let config = || {
+        InkyUc8159Config {
+            width,
+            height,
+            ..InkyUc8159Config::default()
+        }
+        .with_dev_root(&probe.dev_root)
+    };
+    assert_eq!(Path::new(&config().spi_path), dev.path("spidev0.0"));
+    assert_eq!(Path::new(&config().gpio_chip), dev.path("gpiochip0"));
+
+    // The placeholder nodes are plain files, so opening them for real fails
+    // cleanly rather than touching hardware.
+    assert!(InkyUc8159::new(config()).is_err());
+
+    let log = Log::new();
+    let hal = Uc8159Hal {
+        spi: MockSpi::new(&log),
+        cs: MockOutputPin::new("cs", &log),
+        dc: MockOutputPin::new("dc", &log),
+        reset: MockOutputPin::new("reset", &log),
+        busy: MockInputPin::pattern(&[0, 1]),
+        delay: NoDelay,
+    };
+    let mut display = InkyUc8159::from_hal(config(), hal).unwrap();
+    let image = DynamicImage::ImageRgb8(RgbImage::new(width as u32, height as u32));
+    display.set_image(&image, 0.5, 0.0).unwrap();
+    display.show().unwrap();
+    assert!(spi_bytes(&log) >= width as usize * height as usize / 2);
+}
+
+#[test]
+fn constructs_and_shows_el133uf1() {
+    let dev = SimulatedDev::new("el133uf1", &PI_NODES);
+    let devices = MockDevices::new().with_eeprom("i2c-1", &impression_133());
+    let probe = dev.probe(&devices);
+    let DisplaySpec::El133Uf1 { width, height } = probe.display_or_default() else {
+        panic!("expected an EL133UF1");
+    };
+
+    let config = || {
+        InkyEl133Uf1Config {
+            width,
+            height,
+            ..InkyEl133Uf1Config::default()
+        }
+        .with_dev_root(&probe.dev_root)
+    };
+    assert_eq!(Path::new(&config().spi_path), dev.path("spidev0.0"));
+    assert!(InkyEl133Uf1::new(config()).is_err());
+
+    let log = Log::new();
+    let hal = El133Uf1Hal {
+        spi: MockSpi::new(&log),
+        cs0: MockOutputPin::new("cs0", &log),
+        cs1: MockOutputPin::new("cs1", &log),
+        dc: MockOutputPin::new("dc", &log),
+        reset: MockOutputPin::new("reset", &log),
+        busy: MockInputPin::constant(0),
+        delay: NoDelay,
+    };
+    let mut display = InkyEl133Uf1::from_hal(config(), hal).unwrap();
+    let image = DynamicImage::ImageRgb8(RgbImage::new(width as u32, height as u32));
+    display.set_image(&image, 0.5, 0.0).unwrap();
+    display.show().unwrap();
+    assert!(spi_bytes(&log) >= width as usize * height as usize / 2);
+}
+
+#[test]
+fn event_bus_reports_updates() {
+    let log = Log::new();
+    let hal = Uc8159Hal {
+        spi: MockSpi::new(&log),
+        cs: MockOutputPin::new("cs", &log),
+        dc: MockOutputPin::new("dc", &log),
+        reset: MockOutputPin::new("reset", &log),
+        busy: MockInputPin::pattern(&[0, 1]),
+        delay: NoDelay,
+    };
+    let mut display = InkyUc8159::from_hal(InkyUc8159Config::default(), hal).unwrap();
+    display.clear(1);
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut bus = EventBus::new();
+    let started = Arc::clone(&seen);
+    let completed = Arc::clone(&seen);
+    bus.on_update_started(move |event| {
+        started
+            .lock()
+            .unwrap()
+            .push(format!("started {}", event.panel))
+    })
+    .on_update_completed(move |event| {
+        completed.lock().unwrap().push(format!(
+            "completed {:?} x{}",
+            event.outcome, event.refreshes
+        ))
+    });
+
+    bus.show(&mut display, "panel", None).unwrap();
+    let hash = display.frame_hash();
+    bus.show(&mut display, "panel", Some(hash)).unwrap();
+    // Nothing is refreshed, so nothing is reported either.
+    bus.flush(&mut display, "panel", 0).unwrap();
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            "started panel",
+            "completed Refreshed x1",
+            "started panel",
+            "completed NotModified x0",
+        ]
+    );
+}
+
+#[test]
+fn set_image_with_reports_palette_usage() {
+    let log = Log::new();
+    let hal = Uc8159Hal {
+        spi: MockSpi::new(&log),
+        cs: MockOutputPin::new("cs", &log),
+        dc: MockOutputPin::new("dc", &log),
+        reset: MockOutputPin::new("reset", &log),
+        busy: MockInputPin::constant(1),
+        delay: NoDelay,
+    };
+    let mut display = InkyUc8159::from_hal(InkyUc8159Config::default(), hal).unwrap();
+    // Pure white, half the width of the panel, letterboxed to fill it.
+    let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(300, 100, Rgb([255, 255, 255])));
+    let options = RenderOptions {
+        saturation: 1.0,
+        fit: Fit::Contain,
+        ..Default::default()
+    };
+
+    let report = display.set_image_with(&image, &options).unwrap();
+    let names: Vec<_> = report.palette.iter().map(|usage| usage.name).collect();
+    assert_eq!(names, uc8159::COLOUR_NAMES);
+    assert_eq!(report.palette[1].fraction, 1.0);
+    assert_eq!(report.mean_error, 0.0);
+    assert_eq!(report.clipped_highlights, 1.0);
+}
+
+#[test]
+fn analyze_matches_the_driver_report() {
+    let log = Log::new();
+    let hal = Uc8159Hal {
+        spi: MockSpi::new(&log),
+        cs: MockOutputPin::new("cs", &log),
+        dc: MockOutputPin::new("dc", &log),
+        reset: MockOutputPin::new("reset", &log),
+        busy: MockInputPin::constant(1),
+        delay: NoDelay,
+    };
+    let mut display = InkyUc8159::from_hal(InkyUc8159Config::default(), hal).unwrap();
+    let image = DynamicImage::ImageRgb8(RgbImage::from_fn(320, 160, |x, y| {
+        Rgb([(x * 255 / 320) as u8, (y * 255 / 160) as u8, 90])
+    }));
+    let mapping = "green=black".parse().unwrap();
+    let options = RenderOptions {
+        fit: Fit::Contain,
+        gamma: 1.4,
+        dither: DitherOptions {
+            seed: Some(7),
+            remap: PaletteRemap::resolve(&[mapping], &uc8159::COLOUR_NAMES).unwrap(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let report = display.set_image_with(&image, &options).unwrap();
+    let analysis = analyze(&image, None, Rotation::Deg0, &options);
+    let fractions = |usage: &[paperwave::PaletteUsage]| -> Vec<f32> {
+        usage.iter().map(|usage| usage.fraction).collect()
+    };
+    assert_eq!(fractions(&analysis.palette), fractions(&report.palette));
+    assert_eq!(analysis.palette[2].fraction, 0.0);
+    assert_eq!(analysis.clipped_highlights, report.clipped_highlights);
+}